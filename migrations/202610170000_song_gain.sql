@@ -0,0 +1,5 @@
+--- ReplayGain, read from tags or measured at import
+ALTER TABLE songs ADD COLUMN track_gain REAL;
+ALTER TABLE songs ADD COLUMN track_peak REAL;
+ALTER TABLE songs ADD COLUMN album_gain REAL;
+ALTER TABLE songs ADD COLUMN album_peak REAL;
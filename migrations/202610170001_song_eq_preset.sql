@@ -0,0 +1,2 @@
+--- EQ preset pinned to a song, NULL follows the global preset
+ALTER TABLE songs ADD COLUMN eq_preset TEXT;
@@ -0,0 +1,10 @@
+--- Named positions inside a song
+CREATE TABLE IF NOT EXISTS bookmarks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    song_path TEXT NOT NULL,
+    name TEXT NOT NULL,
+    position REAL NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_bookmarks_song_path ON bookmarks(song_path);
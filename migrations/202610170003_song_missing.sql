@@ -0,0 +1,2 @@
+--- Set while the file of a song can't be found, cleared once it is back
+ALTER TABLE songs ADD COLUMN is_missing BOOLEAN NOT NULL DEFAULT 0;
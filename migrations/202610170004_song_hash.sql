@@ -0,0 +1,2 @@
+--- SHA-256 of the file, finds a song again once its file was moved
+ALTER TABLE songs ADD COLUMN file_hash TEXT;
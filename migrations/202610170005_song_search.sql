@@ -0,0 +1,30 @@
+--- Full-text index over the tags songs are searched by, kept in step with songs
+CREATE VIRTUAL TABLE IF NOT EXISTS songs_fts USING fts5(
+    title,
+    artist,
+    album,
+    album_artist,
+    genre,
+    content = 'songs',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS songs_fts_insert AFTER INSERT ON songs BEGIN
+    INSERT INTO songs_fts (rowid, title, artist, album, album_artist, genre)
+    VALUES (new.id, new.title, new.artist, new.album, new.album_artist, new.genre);
+END;
+
+CREATE TRIGGER IF NOT EXISTS songs_fts_delete AFTER DELETE ON songs BEGIN
+    INSERT INTO songs_fts (songs_fts, rowid, title, artist, album, album_artist, genre)
+    VALUES ('delete', old.id, old.title, old.artist, old.album, old.album_artist, old.genre);
+END;
+
+CREATE TRIGGER IF NOT EXISTS songs_fts_update AFTER UPDATE OF title, artist, album, album_artist, genre ON songs BEGIN
+    INSERT INTO songs_fts (songs_fts, rowid, title, artist, album, album_artist, genre)
+    VALUES ('delete', old.id, old.title, old.artist, old.album, old.album_artist, old.genre);
+    INSERT INTO songs_fts (rowid, title, artist, album, album_artist, genre)
+    VALUES (new.id, new.title, new.artist, new.album, new.album_artist, new.genre);
+END;
+
+INSERT INTO songs_fts (songs_fts) VALUES ('rebuild');
@@ -0,0 +1,2 @@
+--- Histogram of the gating blocks of a scanned song, album gains are measured over it
+ALTER TABLE songs ADD COLUMN loudness_histogram BLOB;
@@ -1,35 +1,42 @@
-use std::rc::Rc;
+use std::cell::Cell;
 use std::sync::mpsc::Sender;
-use std::{cell::RefCell, io, time::Duration};
+use std::sync::{Arc, Mutex};
+use std::{io, time::Duration};
+
+use chrono::NaiveTime;
 
 use ratatui::{
+    layout::Rect,
     style::{Style, palette::tailwind},
     text::Line,
 };
 use sqlx::SqlitePool;
 use strum::{Display, EnumIter, FromRepr};
+use tokio::task::JoinHandle;
 use tokio::time::{self, Interval};
 
 use super::awdio::song::Song;
 use super::result::EchoResult;
 use super::ui;
 use crate::awdio::AudioPlayer;
-use crate::db::Playlist;
-use crate::db::library::Library;
+use crate::awdio::device::OutputTarget;
+use crate::awdio::eq::{self, EqBand, MAX_GAIN_DB};
+use crate::awdio::queue::PlayQueue;
+use crate::awdio::stretch::{MAX_SPEED, MIN_SPEED, SpeedMode};
+use crate::awdio::waveform::Waveform;
+use crate::db::library::{
+    Library,
+    doctor::Issue,
+    import::{ImportMode, ImportProgress},
+    watch::LibraryWatcher,
+};
+use crate::db::{Bookmark, Playlist};
 use crate::result::EchoReport;
-use crate::{config::UiConfig, ignite::Paths};
-
-#[derive(Debug)]
-pub struct AnimationTimeStamp {
-    pub vals: [String; 50],
-}
-
-impl Default for AnimationTimeStamp {
-    fn default() -> Self {
-        let vals = core::array::from_fn(|_| String::from(""));
-        AnimationTimeStamp { vals }
-    }
-}
+use crate::timer::{Alarm, FadeIn, SleepTimer};
+use crate::{
+    config::{EqConfig, UiConfig},
+    ignite::Paths,
+};
 
 #[derive(Debug, Default)]
 pub struct AnimationState {
@@ -37,7 +44,6 @@ pub struct AnimationState {
     pub timestamp_location: usize,
 
     // animations
-    pub animation_timestamp: Rc<RefCell<AnimationTimeStamp>>,
     pub animation_spinner: (usize, usize),
     pub animation_hpulse: (usize, usize),
     pub animation_dot: (usize, usize),
@@ -71,6 +77,13 @@ pub enum PlaylistSubTab {
     InputName,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Popup {
+    Output,
+    Bookmarks,
+    Doctor,
+}
+
 #[derive(Default, Debug, Clone, Copy, Display, FromRepr, EnumIter)]
 pub enum SelectedTab {
     #[default]
@@ -111,6 +124,31 @@ impl SelectedTab {
     }
 }
 
+/// What the panel on top of the Echo tab shows, `f` steps through them.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Display, FromRepr)]
+pub enum Visualizer {
+    #[default]
+    #[strum(to_string = "SPECTRUM")]
+    Spectrum,
+    #[strum(to_string = "OSCILLOSCOPE")]
+    Oscilloscope,
+    #[strum(to_string = "SPECTROGRAM")]
+    Spectrogram,
+    #[strum(to_string = "VU/PPM")]
+    Meters,
+    #[strum(to_string = "GONIOMETER")]
+    Stereo,
+    #[strum(to_string = "OFF")]
+    Off,
+}
+
+impl Visualizer {
+    /// The next mode, back to the first after `Off`.
+    pub fn next(self) -> Self {
+        Self::from_repr(self as usize + 1).unwrap_or_default()
+    }
+}
+
 #[derive(Debug, Default)]
 pub enum LogLevel {
     #[default]
@@ -139,6 +177,7 @@ impl Default for Report {
 #[derive(Debug)]
 pub struct EchoTabState {
     pub is_fft_enable: bool,
+    pub visualizer: Visualizer,
     pub prev_sub_state: EchoSubTab,
     pub echo_subtab: EchoSubTab,
 
@@ -151,6 +190,19 @@ pub struct EchoTabState {
 
     pub is_echo_import_buffer_being_filled: bool,
     pub import_buffer: String,
+    // the running or last import, filled by the import task
+    pub import_progress: Arc<Mutex<ImportProgress>>,
+
+    pub is_echo_seek_buffer_being_filled: bool,
+    pub seek_buffer: String,
+
+    pub is_echo_bookmark_buffer_being_filled: bool,
+    pub bookmark_buffer: String,
+    // seconds into the song the bookmark being named points at
+    pub bookmark_position: f64,
+
+    // percent of the song the waveform cursor points at, `W` toggles it
+    pub waveform_cursor: Option<f64>,
 
     pub is_zero_local_song: bool,
 }
@@ -159,16 +211,130 @@ impl EchoTabState {
     pub fn new() -> Self {
         Self {
             is_fft_enable: true,
+            visualizer: Visualizer::default(),
             prev_sub_state: EchoSubTab::SEARCH,
             echo_subtab: EchoSubTab::SEARCH,
             echo_metadata_selected_pos: 0,
             is_echo_metadata_buffer_being_filled: false,
             is_echo_search_buffer_being_filled: false,
             is_echo_import_buffer_being_filled: false,
+            is_echo_seek_buffer_being_filled: false,
+            is_echo_bookmark_buffer_being_filled: false,
             is_zero_local_song: true,
             metadata_buffer: "".into(),
             search_buffer: "".into(),
             import_buffer: "".into(),
+            import_progress: Arc::new(Mutex::new(ImportProgress::default())),
+            seek_buffer: "".into(),
+            bookmark_buffer: "".into(),
+            waveform_cursor: None,
+            bookmark_position: 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EqState {
+    // the built-in `flat` first, then the presets of echo.toml by name
+    pub presets: Vec<(String, Vec<EqBand>)>,
+    pub selected_preset: usize,
+    pub selected_band: usize,
+    pub is_enabled: bool,
+}
+
+impl Default for EqState {
+    fn default() -> Self {
+        Self {
+            presets: vec![("flat".into(), eq::flat())],
+            selected_preset: 0,
+            selected_band: 0,
+            is_enabled: false,
+        }
+    }
+}
+
+impl EqState {
+    pub fn from_config(config: &EqConfig) -> Self {
+        let mut state = Self::default();
+
+        let mut names: Vec<&String> = config.presets.keys().collect();
+        names.sort();
+        for name in names {
+            let bands = config.presets[name].clone();
+            match state.presets.iter_mut().find(|(n, _)| n == name) {
+                Some(preset) => preset.1 = bands,
+                None => state.presets.push((name.clone(), bands)),
+            }
+        }
+
+        if let Some(name) = &config.preset {
+            state.selected_preset = state
+                .presets
+                .iter()
+                .position(|(n, _)| n == name)
+                .unwrap_or(0);
+        }
+        state.is_enabled = config.enabled;
+        state
+    }
+
+    pub fn preset_name(&self) -> &str {
+        &self.presets[self.selected_preset].0
+    }
+
+    pub fn bands(&self) -> &[EqBand] {
+        &self.presets[self.selected_preset].1
+    }
+
+    /// The bands `song` plays through: its pinned preset if that still
+    /// exists, the selected one otherwise, none while switched off.
+    pub fn bands_for(&self, song: &Song) -> Vec<EqBand> {
+        if !self.is_enabled {
+            return Vec::new();
+        }
+
+        song.eq_preset
+            .as_ref()
+            .and_then(|name| self.presets.iter().find(|(n, _)| n == name))
+            .map(|(_, bands)| bands.clone())
+            .unwrap_or_else(|| self.bands().to_vec())
+    }
+
+    pub fn next_preset(&mut self) {
+        self.selected_preset = (self.selected_preset + 1) % self.presets.len();
+        self.selected_band = 0;
+    }
+
+    pub fn previous_preset(&mut self) {
+        self.selected_preset = self
+            .selected_preset
+            .checked_sub(1)
+            .unwrap_or(self.presets.len() - 1);
+        self.selected_band = 0;
+    }
+
+    pub fn next_band(&mut self) {
+        let count = self.bands().len();
+        if count > 0 {
+            self.selected_band = (self.selected_band + 1) % count;
+        }
+    }
+
+    pub fn previous_band(&mut self) {
+        let count = self.bands().len();
+        if count > 0 {
+            self.selected_band = self.selected_band.checked_sub(1).unwrap_or(count - 1);
+        }
+    }
+
+    /// Move the gain of the selected band by `delta` dB, `None` resets it to 0.
+    pub fn adjust_gain(&mut self, delta: Option<f32>) {
+        let selected = self.selected_band;
+        if let Some(band) = self.presets[self.selected_preset].1.get_mut(selected) {
+            band.gain = match delta {
+                Some(delta) => (band.gain + delta).clamp(-MAX_GAIN_DB, MAX_GAIN_DB),
+                None => 0.0,
+            };
         }
     }
 }
@@ -184,6 +350,7 @@ pub struct State {
     pub animations: AnimationState,
 
     pub active_track: Song,
+    pub queue: PlayQueue,
 
     pub uptime: Duration,
     pub uptime_readable: String,
@@ -212,7 +379,46 @@ pub struct State {
     pub selected_playlist_song_idx: usize,
     pub playlist_subtab: PlaylistSubTab,
     pub playlist_name_buffer: String,
-    pub is_popup: bool,
+    pub popup: Option<Popup>,
+
+    // Output device
+    pub output_target: OutputTarget,
+    pub output_devices: Vec<OutputTarget>,
+    pub selected_output_idx: usize,
+
+    // Bookmarks of the playing song
+    pub bookmarks: Vec<Bookmark>,
+    pub selected_bookmark_idx: usize,
+
+    // Equalizer
+    pub eq: EqState,
+
+    // Playback speed
+    pub speed: f32,
+    pub speed_mode: SpeedMode,
+
+    // Sleep timer and alarm
+    pub sleep_timer: Option<SleepTimer>,
+    // recomputed every tick while the sleep timer runs
+    pub sleep_remaining: Option<Duration>,
+    pub alarm: Alarm,
+    pub fade_in: Option<FadeIn>,
+
+    // Waveform overview of the playing song, filled in the background
+    pub waveform: Arc<Mutex<Waveform>>,
+    // where the overview was last drawn, for seeking with the mouse
+    pub waveform_area: Cell<Rect>,
+
+    // Picks up what other programs do to the library folders
+    pub library_watcher: Option<LibraryWatcher>,
+
+    // Library doctor findings
+    pub doctor_issues: Vec<Issue>,
+    pub selected_issue_idx: usize,
+    // the check runs in the background, the popup waits for it
+    pub doctor_check: Option<JoinHandle<EchoResult<Vec<Issue>>>>,
+    // `x` was pressed once on the selected issue, a second one deletes it
+    pub is_doctor_delete_armed: bool,
 }
 
 impl State {
@@ -224,6 +430,7 @@ impl State {
             buffer: "".into(),
             animations: AnimationState::default(),
             active_track: Song::default(),
+            queue: PlayQueue::default(),
             uptime: Duration::default(),
             uptime_readable: "".into(),
             current_clock: "".into(),
@@ -242,7 +449,26 @@ impl State {
             selected_playlist_song_idx: 0,
             playlist_subtab: PlaylistSubTab::default(),
             playlist_name_buffer: String::new(),
-            is_popup: false,
+            popup: None,
+            output_target: OutputTarget::default(),
+            output_devices: Vec::new(),
+            selected_output_idx: 0,
+            bookmarks: Vec::new(),
+            selected_bookmark_idx: 0,
+            eq: EqState::default(),
+            speed: 1.0,
+            speed_mode: SpeedMode::default(),
+            sleep_timer: None,
+            sleep_remaining: None,
+            alarm: Alarm::new(NaiveTime::default(), Duration::ZERO, 0.3),
+            fade_in: None,
+            waveform: Arc::new(Mutex::new(Waveform::default())),
+            waveform_area: Cell::new(Rect::default()),
+            library_watcher: None,
+            doctor_issues: Vec::new(),
+            selected_issue_idx: 0,
+            doctor_check: None,
+            is_doctor_delete_armed: false,
         }
     }
 
@@ -265,28 +491,10 @@ impl State {
         }
     }
 
-    pub fn set_animations(
-        &mut self,
-        spinner: usize,
-        hpulse: usize,
-        dot: usize,
-        timestamp: String,
-        timestamp_bar: String,
-    ) {
+    pub fn set_animations(&mut self, spinner: usize, hpulse: usize, dot: usize) {
         self.animations.animation_spinner.1 = spinner;
         self.animations.animation_hpulse.1 = hpulse;
         self.animations.animation_dot.1 = dot;
-
-        for i in self
-            .animations
-            .animation_timestamp
-            .borrow_mut()
-            .vals
-            .iter_mut()
-        {
-            *i = timestamp_bar.clone();
-        }
-        self.animations.animation_timestamp.borrow_mut().vals[0] = timestamp;
     }
 
     pub fn append_input(&mut self, input: &str) {
@@ -324,8 +532,6 @@ pub async fn start(data: (UiConfig, SqlitePool, Paths)) -> EchoResult<()> {
         data.0.animations["animations"].spinner.len(),
         data.0.animations["animations"].hpulse.len(),
         data.0.animations["animations"].dot,
-        data.0.animations["animations"].timestamp.clone(),
-        data.0.animations["animations"].timestamp_bar.clone(),
     );
 
     let local_songs = Library::get_songs_from_db(&data.1, 0, 10).await?;
@@ -334,14 +540,40 @@ pub async fn start(data: (UiConfig, SqlitePool, Paths)) -> EchoResult<()> {
     }
     state.local_songs = local_songs;
 
+    state.output_target = data.0.output();
+    state.eq = EqState::from_config(&data.0.eq());
+
+    let playback = data.0.playback();
+    state.speed = playback.speed.unwrap_or(1.0).clamp(MIN_SPEED, MAX_SPEED);
+    state.speed_mode = playback.speed_mode;
+
     // Load playlists from DB
     if let Ok(pls) = crate::db::get_all_playlists(&data.1).await {
         state.playlists = pls;
     }
 
+    let timer = data.0.timer();
+    state.alarm = Alarm::new(timer.alarm_time(), timer.alarm_fade(), timer.alarm_volume());
+    if let Some(name) = &timer.alarm_playlist {
+        state.alarm.playlist_id = state
+            .playlists
+            .iter()
+            .find(|playlist| &playlist.name == name)
+            .map(|playlist| playlist.id);
+        if state.alarm.playlist_id.is_some() {
+            state.alarm.arm();
+        }
+    }
+
     let mut canvas =
         ui::EchoCanvas::init(state, data.0, data.1, None, AudioPlayer::bad(), rx, data.2);
 
+    // referenced roots pick up what was added while echo was closed
+    if canvas.ui_config.library().mode == ImportMode::Reference {
+        canvas.import_library();
+    }
+    canvas.watch_library();
+
     let ui = canvas.paint().await;
 
     match ui {
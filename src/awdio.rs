@@ -1,63 +1,130 @@
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
-use cpal::Stream;
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use rustfft::{FftPlanner, num_complex::Complex};
+use rtrb::{Consumer, Producer, RingBuffer};
 use symphonia::core::codecs::Decoder;
-use symphonia::core::formats::FormatReader;
-
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatReader, SeekMode, SeekTo};
+use symphonia::core::units::Time;
+
+use crate::awdio::crossfade::{Crossfade, FadeOut};
+use crate::awdio::device::OutputTarget;
+use crate::awdio::eq::{EqBand, Equalizer};
+use crate::awdio::loudness::GainStage;
+use crate::awdio::ramp::{DEFAULT_RAMP_MS, Ramp};
+use crate::awdio::resample::Converter;
+use crate::awdio::scope::Scope;
+use crate::awdio::sink::{OutputStream, StreamFormat};
+use crate::awdio::spectrum::{Analyzer, FFT_SIZE, SpectrumSettings};
+use crate::awdio::stretch::{SpeedMode, SpeedStage};
 use crate::result::EchoResult;
 
+pub mod crossfade;
+pub mod device;
+pub mod eq;
+pub mod export;
+pub mod flac;
+pub mod format;
+pub mod loudness;
 pub mod metadata;
+pub mod queue;
+pub mod ramp;
+pub mod resample;
+pub mod scope;
+pub mod sink;
 pub mod song;
+pub mod spectrum;
+pub mod stretch;
+pub mod waveform;
+
+// a file that fails this often in a row is treated as ended
+const MAX_ERRORS_IN_A_ROW: usize = 64;
 
 #[derive(Clone, Default)]
 pub struct DurationInfo {
     pub readable: String,
     pub seconds: u64,
+    // exact length in frames of the file, at its own `sample_rate`
+    pub frames: u64,
+    pub sample_rate: u32,
 }
 
-#[derive(Default)]
-pub struct AudioData {
-    pub samples: VecDeque<f32>,
-    pub sample_rate: u32,
-    pub channels: u16,
-    pub file_size: String,
-    pub duration: DurationInfo,
-    pub host: String,
+impl DurationInfo {
+    /// Length in frames at `sample_rate`, 0 when unknown.
+    pub fn frames_at(&self, sample_rate: u32) -> u64 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        self.frames * sample_rate as u64 / self.sample_rate as u64
+    }
+}
 
-    pub is_seeking: bool,
-    pub is_finished: bool,
-    pub is_pause: bool,
-    pub volume: f32,
+/// An absolute position to seek to, as typed by the user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeekTarget {
+    Seconds(f64),
+    Percent(f64),
+}
 
-    pub total_samples_played: u64,
-    pub min_buffer_threshold: usize,
+impl SeekTarget {
+    /// Reads `mm:ss`, `h:mm:ss`, plain seconds or a percentage like `40%`.
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim();
+        if let Some(percent) = input.strip_suffix('%') {
+            let percent: f64 = percent.trim().parse().ok()?;
+            return (0.0..=100.0)
+                .contains(&percent)
+                .then_some(Self::Percent(percent));
+        }
 
-    pub format_reader: Option<Box<dyn FormatReader + Send>>,
-    pub decoder: Option<Box<dyn Decoder + Send>>,
-    pub track_id: u32,
+        let parts: Vec<&str> = input.split(':').collect();
+        if parts.len() > 3 {
+            return None;
+        }
 
-    pub fft_state: Vec<f32>,
-    pub enable_fft_compute: bool,
-}
+        let mut seconds = 0.0;
+        for part in parts {
+            let value: f64 = part.trim().parse().ok()?;
+            if !value.is_finite() || value < 0.0 {
+                return None;
+            }
+            seconds = seconds * 60.0 + value;
+        }
 
-pub struct AudioPlayer {
-    pub state: Arc<Mutex<AudioData>>,
-    pub cpal_stream: Option<Stream>,
+        Some(Self::Seconds(seconds))
+    }
 }
 
-impl AudioPlayer {
-    pub fn bad() -> Self {
-        let audio_data = AudioData::default();
-        Self {
-            state: Arc::new(Mutex::new(audio_data)),
-            cpal_stream: None,
-        }
-    }
+/// An opened file, ready to be decoded packet by packet.
+pub struct DecodeSource {
+    pub path: String,
+    pub format_reader: Box<dyn FormatReader + Send>,
+    pub decoder: Box<dyn Decoder + Send>,
+    pub track_id: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    // sample size of lossless codecs, lossy ones have none
+    pub bits_per_sample: Option<u32>,
+    pub file_size: String,
+    pub duration: DurationInfo,
+    pub n_frames: Option<u64>,
+    pub frames_decoded: u64,
+    // corrupt packets skipped since the decode loop last collected them
+    pub skipped_packets: u64,
+    // frames before the seek target still in the next decoded packet
+    discard_frames: u64,
+    converter: Option<Converter>,
+    eq: Option<Equalizer>,
+    // the `AudioData::eq_generation` the equalizer was built from
+    eq_generation: u64,
+    gain: Option<GainStage>,
+    // the output layout `converter` produces
+    out_format: (u32, u16),
+}
 
-    pub fn new(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+impl DecodeSource {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let file = std::fs::File::open(path)?;
         let file_size = human_readable_size(file.metadata()?.len());
         let mss = symphonia::core::io::MediaSourceStream::new(Box::new(file), Default::default());
@@ -85,118 +152,907 @@ impl AudioPlayer {
             .channels
             .map(|c| c.count() as u16)
             .unwrap_or(2);
-        let track_id = track.id;
 
         let decoder = symphonia::default::get_codecs().make(
             &track.codec_params,
             &symphonia::core::codecs::DecoderOptions::default(),
         )?;
 
-        let audio_data = AudioData {
-            samples: VecDeque::new(),
+        Ok(Self {
+            path: path.to_string(),
+            format_reader,
+            decoder,
+            track_id: track.id,
             sample_rate,
             channels,
+            bits_per_sample: track.codec_params.bits_per_sample,
             file_size,
             duration,
+            n_frames: track.codec_params.n_frames,
+            frames_decoded: 0,
+            skipped_packets: 0,
+            discard_frames: 0,
+            converter: None,
+            eq: None,
+            eq_generation: 0,
+            gain: None,
+            out_format: (sample_rate, channels),
+        })
+    }
+
+    /// Convert everything this source decodes to the layout of the output stream.
+    pub fn attach(&mut self, out_rate: u32, out_channels: u16) {
+        self.out_format = (out_rate, out_channels);
+        self.converter = if out_rate == self.sample_rate && out_channels == self.channels {
+            None
+        } else {
+            Some(Converter::new(
+                self.sample_rate,
+                self.channels,
+                out_rate,
+                out_channels,
+            ))
+        };
+        if let Some(eq) = self.eq.as_mut() {
+            *eq = Equalizer::new(eq.bands(), out_rate, out_channels);
+        }
+        if let Some(stage) = self.gain.as_mut() {
+            *stage = GainStage::new(stage.gain, out_rate);
+        }
+    }
+
+    /// Run this source through `bands`. A flat or empty set turns the
+    /// equalizer off.
+    pub fn set_eq(&mut self, bands: &[EqBand]) {
+        if bands.iter().all(|band| band.gain == 0.0) {
+            self.eq = None;
+            return;
+        }
+
+        let (out_rate, out_channels) = self.out_format;
+        match self.eq.as_mut() {
+            Some(eq) => eq.set_bands(bands, out_rate),
+            None => self.eq = Some(Equalizer::new(bands, out_rate, out_channels)),
+        }
+    }
+
+    /// Play this source `gain` times louder, through the limiter.
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = (gain != 1.0).then(|| GainStage::new(gain, self.out_format.0));
+    }
+
+    /// Jump to `seconds` into the file. An accurate seek lands on the packet
+    /// holding that time, the frames before it are dropped once decoded.
+    fn seek(&mut self, seconds: f64) -> Result<(), SymphoniaError> {
+        let seeked = self.format_reader.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::from(seconds),
+                track_id: Some(self.track_id),
+            },
+        )?;
+
+        let time_base = self
+            .format_reader
+            .tracks()
+            .iter()
+            .find(|t| t.id == self.track_id)
+            .and_then(|t| t.codec_params.time_base);
+        let sample_rate = self.sample_rate as f64;
+        // timestamps count in the time base of the track, not in frames
+        let to_frames = |ts: u64| match time_base {
+            Some(time_base) => {
+                let time = time_base.calc_time(ts);
+                ((time.seconds as f64 + time.frac) * sample_rate).round() as u64
+            }
+            None => ts,
+        };
+
+        let actual = to_frames(seeked.actual_ts);
+        self.discard_frames = to_frames(seeked.required_ts).saturating_sub(actual);
+        self.frames_decoded = actual;
+
+        self.decoder.reset();
+        if let Some(converter) = self.converter.as_mut() {
+            converter.reset();
+        }
+
+        Ok(())
+    }
+
+    /// Seconds left until the end of the file, if the container knows its length.
+    pub fn remaining_seconds(&self) -> Option<f64> {
+        self.n_frames.map(|total| {
+            total.saturating_sub(self.frames_decoded) as f64 / self.sample_rate.max(1) as f64
+        })
+    }
+
+    /// Decode the next packet in the layout of the output stream, `None`
+    /// once the stream ended.
+    fn next_output(&mut self) -> Option<Vec<f32>> {
+        let mut output = match self.next_samples() {
+            Some(samples) => match self.converter.as_mut() {
+                Some(converter) => converter.process(&samples),
+                None => samples,
+            },
+            None => {
+                let tail = self
+                    .converter
+                    .as_mut()
+                    .map(|converter| converter.flush())
+                    .unwrap_or_default();
+                if tail.is_empty() {
+                    return None;
+                }
+                tail
+            }
+        };
+
+        if let Some(eq) = self.eq.as_mut() {
+            eq.process(&mut output);
+        }
+        if let Some(stage) = self.gain.as_mut() {
+            stage.process(&mut output, self.out_format.1 as usize);
+        }
+        Some(output)
+    }
+
+    /// Decode the next packet of this source, `None` once the stream ended.
+    /// Corrupt packets are skipped and counted, packets of other tracks are
+    /// ignored.
+    fn next_samples(&mut self) -> Option<Vec<f32>> {
+        let mut errors_in_a_row = 0;
+
+        loop {
+            if errors_in_a_row >= MAX_ERRORS_IN_A_ROW {
+                // nothing left in this file that decodes
+                return None;
+            }
+
+            let packet = match self.format_reader.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(SymphoniaError::DecodeError(_)) => {
+                    self.skipped_packets += 1;
+                    errors_in_a_row += 1;
+                    continue;
+                }
+                // end of stream, or the file can't be read any further
+                Err(_) => return None,
+            };
+
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(SymphoniaError::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(SymphoniaError::DecodeError(_) | SymphoniaError::IoError(_)) => {
+                    self.skipped_packets += 1;
+                    errors_in_a_row += 1;
+                    continue;
+                }
+                Err(_) => return None,
+            };
+
+            let mut sample_buffer = symphonia::core::audio::SampleBuffer::<f32>::new(
+                decoded.capacity() as u64,
+                *decoded.spec(),
+            );
+            sample_buffer.copy_interleaved_ref(decoded);
+
+            let channels = self.channels.max(1) as usize;
+            let mut samples = sample_buffer.samples().to_vec();
+            self.frames_decoded += (samples.len() / channels) as u64;
+
+            if self.discard_frames > 0 {
+                let dropped = (self.discard_frames as usize).min(samples.len() / channels);
+                samples.drain(..dropped * channels);
+                self.discard_frames -= dropped as u64;
+                if samples.is_empty() {
+                    continue;
+                }
+            }
+
+            return Some(samples);
+        }
+    }
+}
+
+/// Where the next track starts in the output, once the samples of the
+/// previous one still waiting to be played are through. An A–B loop jumping
+/// back leaves one too, without a new track.
+struct TrackBoundary {
+    starts_at: u64,
+    // track frame the output continues from
+    position: u64,
+    track: Option<BoundaryTrack>,
+}
+
+struct BoundaryTrack {
+    path: String,
+    file_size: String,
+    duration: DurationInfo,
+}
+
+/// The part of the player the output callback touches. Everything in here
+/// is atomic, so the real-time thread never waits on a lock.
+#[derive(Default)]
+pub struct Transport {
+    volume: AtomicU32,
+    is_pause: AtomicBool,
+    // how long pause, resume, seek and stop fade for
+    ramp_ms: AtomicU32,
+    // raised by the callback while the ramp holds the output at zero
+    is_silent: AtomicBool,
+    // raised by the decoder, lowered by the callback once the ring is empty
+    flush: AtomicBool,
+    // frames the callback took out of the ring, played or flushed
+    frames_output: AtomicU64,
+}
+
+impl Transport {
+    pub fn with_volume(volume: f32) -> Self {
+        Self {
+            volume: AtomicU32::new(volume.to_bits()),
+            ramp_ms: AtomicU32::new(DEFAULT_RAMP_MS),
+            ..Default::default()
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_pause.load(Ordering::Relaxed)
+    }
+
+    pub fn toggle_pause(&self) {
+        self.is_pause.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn set_paused(&self, is_paused: bool) {
+        self.is_pause.store(is_paused, Ordering::Relaxed);
+    }
+
+    pub fn ramp_ms(&self) -> u32 {
+        self.ramp_ms.load(Ordering::Relaxed)
+    }
+
+    pub fn set_ramp_ms(&self, ramp_ms: u32) {
+        self.ramp_ms.store(ramp_ms, Ordering::Relaxed);
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.is_silent.load(Ordering::Acquire)
+    }
+
+    pub fn frames_output(&self) -> u64 {
+        self.frames_output.load(Ordering::Acquire)
+    }
+
+    fn is_flushing(&self) -> bool {
+        self.flush.load(Ordering::Acquire)
+    }
+}
+
+#[derive(Default)]
+pub struct AudioData {
+    // decoded samples waiting for room in the ring
+    pub samples: VecDeque<f32>,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub file_size: String,
+    pub duration: DurationInfo,
+    pub host: String,
+    pub current_path: String,
+
+    pub seek_to: Option<u64>,
+    pub is_finished: bool,
+    pub is_stopped: bool,
+    pub transport: Arc<Transport>,
+
+    // the output frame, track frame and speed the position counts from
+    origin_output: u64,
+    origin_position: u64,
+    origin_speed: f64,
+    // the position to count from once a flush went through
+    pending_origin: Option<u64>,
+    pub frames_pushed: u64,
+    pub min_buffer_threshold: usize,
+
+    ring: Option<Producer<f32>>,
+    tap: Option<Consumer<f32>>,
+
+    pub source: Option<DecodeSource>,
+    // bumped every time `source` is replaced from outside the decode thread
+    pub source_generation: u64,
+
+    pub next_path: Option<String>,
+    pub next_gain: f32,
+    pub next_source: Option<DecodeSource>,
+    pub crossfade: Crossfade,
+    pub fade_out: Option<FadeOut>,
+    boundaries: VecDeque<TrackBoundary>,
+    pub track_changed: bool,
+    pub skipped_packets: u64,
+
+    // bands every source plays through, bumped on change
+    pub eq: Vec<EqBand>,
+    pub eq_generation: u64,
+
+    // A–B loop points in track frames, B only counts once A is set
+    pub loop_a: Option<u64>,
+    pub loop_b: Option<u64>,
+
+    // playback speed of everything decoded from here on
+    pub speed: f32,
+    pub speed_mode: SpeedMode,
+    stretch: Option<SpeedStage>,
+
+    pub scope: Scope,
+    pub spectrum_settings: SpectrumSettings,
+    pub enable_fft_compute: bool,
+}
+
+impl AudioData {
+    /// Frames of the current track the output has played.
+    pub fn position_frames(&self) -> u64 {
+        if let Some(position) = self.pending_origin {
+            return position;
+        }
+        let played = self
+            .transport
+            .frames_output()
+            .saturating_sub(self.origin_output);
+        self.origin_position + (played as f64 * self.origin_speed) as u64
+    }
+
+    /// Length of the current track in output frames, 0 when unknown.
+    pub fn duration_frames(&self) -> u64 {
+        self.duration.frames_at(self.sample_rate)
+    }
+
+    /// Samples decoded but not played yet, here or in the ring.
+    pub fn buffered_samples(&self) -> usize {
+        let in_ring = self
+            .frames_pushed
+            .saturating_sub(self.transport.frames_output());
+        self.samples.len() + in_ring as usize * self.channels as usize
+    }
+
+    /// Catch up with the output: move past every boundary it played through
+    /// and start counting from a flush once the callback went through it.
+    fn sync(&mut self) {
+        let played = self.transport.frames_output();
+        while self
+            .boundaries
+            .front()
+            .is_some_and(|b| played >= b.starts_at)
+        {
+            self.apply_boundary();
+        }
+
+        if !self.transport.is_flushing()
+            && let Some(position) = self.pending_origin.take()
+        {
+            self.set_origin(self.frames_pushed, position);
+        }
+    }
+
+    /// Count the position from `position` at output frame `output`, at the
+    /// current speed.
+    fn set_origin(&mut self, output: u64, position: u64) {
+        self.origin_output = output;
+        self.origin_position = position;
+        self.origin_speed = self.speed as f64;
+    }
+
+    /// Start a fresh speed stage for the current speed and output layout.
+    fn rebuild_stretch(&mut self) {
+        self.stretch =
+            SpeedStage::new(self.speed, self.speed_mode, self.sample_rate, self.channels);
+    }
+
+    /// Bring the current and the primed source up to the latest EQ bands.
+    fn refresh_eq(&mut self) {
+        let sources = [self.source.as_mut(), self.next_source.as_mut()];
+        for source in sources.into_iter().flatten() {
+            if source.eq_generation != self.eq_generation {
+                source.set_eq(&self.eq);
+                source.eq_generation = self.eq_generation;
+            }
+        }
+    }
+
+    fn apply_boundary(&mut self) {
+        let Some(boundary) = self.boundaries.pop_front() else {
+            return;
+        };
+        self.origin_output = boundary.starts_at;
+        self.origin_position = boundary.position;
+
+        if let Some(track) = boundary.track {
+            self.current_path = track.path;
+            self.file_size = track.file_size;
+            self.duration = track.duration;
+            self.track_changed = true;
+            self.loop_a = None;
+            self.loop_b = None;
+        }
+    }
+
+    /// The A–B loop in track frames, once both points are set.
+    pub fn ab_loop(&self) -> Option<(u64, u64)> {
+        match (self.loop_a, self.loop_b) {
+            (Some(a), Some(b)) if a < b => Some((a, b)),
+            _ => None,
+        }
+    }
+
+    /// If `source` just decoded past B, cut `samples` off at B and return A,
+    /// where decoding goes on from. `decoded_before` is where the source was
+    /// before this packet.
+    fn loop_back(
+        &self,
+        source: &DecodeSource,
+        decoded_before: u64,
+        samples: &mut Vec<f32>,
+    ) -> Option<u64> {
+        let (a, b) = self.ab_loop()?;
+        let out_rate = self.sample_rate.max(1) as u64;
+        let b_source = loop_end_in_source(b, source.sample_rate, self.sample_rate);
+        if !crosses_loop_end(decoded_before, source.frames_decoded, b_source) {
+            return None;
+        }
+
+        let channels = self.channels.max(1) as usize;
+        let overrun =
+            (source.frames_decoded - b_source) * out_rate / source.sample_rate.max(1) as u64;
+        let keep = (samples.len() / channels).saturating_sub(overrun as usize);
+        samples.truncate(keep * channels);
+        Some(a)
+    }
+
+    /// A, when the decoder is already at or past B. The decoder runs ahead of
+    /// the output, so a B set at the playing position is behind it and no
+    /// packet will cross it, the loop has to start over from A with a flush.
+    fn missed_loop(&self) -> Option<u64> {
+        let (a, b) = self.ab_loop()?;
+        let source = self.source.as_ref()?;
+        let b_source = loop_end_in_source(b, source.sample_rate, self.sample_rate);
+        (source.frames_decoded >= b_source).then_some(a)
+    }
+
+    /// Drop everything decoded so far, here and in the ring, and count the
+    /// output from `position` on once the callback emptied the ring.
+    fn flush(&mut self, position: u64) {
+        self.samples.clear();
+        self.rebuild_stretch();
+        self.pending_origin = Some(position);
+        self.transport.flush.store(true, Ordering::Release);
+    }
+
+    /// Move staged samples into the ring, whole frames at a time.
+    fn fill_ring(&mut self) {
+        let channels = self.channels.max(1) as usize;
+        let Some(ring) = self.ring.as_mut() else {
+            return;
+        };
+
+        let count = ring.slots().min(self.samples.len()) / channels * channels;
+        if count == 0 {
+            return;
+        }
+        if let Ok(chunk) = ring.write_chunk_uninit(count) {
+            chunk.fill_from_iter(self.samples.drain(..count));
+            self.frames_pushed += (count / channels) as u64;
+        }
+    }
+
+    /// Switch the decoder over to the primed next track.
+    fn start_next_source(&mut self) -> bool {
+        let Some(next) = self.next_source.take() else {
+            return false;
+        };
+        self.next_path = None;
+        self.push_boundary(next);
+
+        true
+    }
+
+    /// Whether the current source is close enough to its end to start
+    /// mixing in the next one.
+    fn should_crossfade(&self, source: &DecodeSource) -> bool {
+        let fade_seconds = self.crossfade.seconds as f64;
+        fade_seconds > 0.0
+            && self.ab_loop().is_none()
+            && self.fade_out.is_none()
+            && self.next_source.is_some()
+            && source
+                .remaining_seconds()
+                .is_some_and(|remaining| remaining <= fade_seconds)
+    }
+
+    /// Move `outgoing` to the fade-out slot and let the next track take over.
+    fn start_crossfade(&mut self, outgoing: DecodeSource) {
+        let fade_seconds = outgoing
+            .remaining_seconds()
+            .unwrap_or_default()
+            .min(self.crossfade.seconds as f64);
+        let fade_frames = (fade_seconds * self.sample_rate as f64) as u64;
+
+        if let Some(next) = self.next_source.take() {
+            self.next_path = None;
+            self.push_boundary(next);
+            self.fade_out = Some(FadeOut::new(
+                outgoing,
+                self.channels,
+                fade_frames,
+                self.crossfade.curve,
+            ));
+        }
+    }
+
+    /// Hook up the ring of a freshly opened stream. Whatever the old ring
+    /// still held was never played, so the decoder seeks back to where the
+    /// output actually was, in the new layout.
+    fn attach_output(
+        &mut self,
+        sample_rate: u32,
+        channels: u16,
+        ring: Producer<f32>,
+        tap: Consumer<f32>,
+    ) {
+        self.sync();
+        let played = self.transport.frames_output();
+        let was_lost =
+            self.frames_pushed > played || !self.samples.is_empty() || self.fade_out.is_some();
+
+        // the decoder already moved on, so playback resumes in the new track
+        while !self.boundaries.is_empty() {
+            self.apply_boundary();
+        }
+
+        let old_rate = self.sample_rate.max(1) as u64;
+        let position = self.position_frames() * sample_rate as u64 / old_rate;
+        self.seek_to = self
+            .seek_to
+            .map(|target| target * sample_rate as u64 / old_rate);
+
+        if (self.sample_rate, self.channels) != (sample_rate, channels) {
+            self.sample_rate = sample_rate;
+            self.channels = channels;
+
+            if let Some(source) = self.source.as_mut() {
+                source.attach(sample_rate, channels);
+            }
+            if let Some(next) = self.next_source.as_mut() {
+                next.attach(sample_rate, channels);
+            }
+        }
+
+        self.samples.clear();
+        self.fade_out = None;
+        self.ring = Some(ring);
+        self.tap = Some(tap);
+
+        // nothing is left to drain in a new ring
+        self.transport.flush.store(false, Ordering::Release);
+        self.pending_origin = None;
+        self.frames_pushed = played;
+        self.rebuild_stretch();
+        self.set_origin(played, position);
+
+        // the source may be out with the decoder right now, the seek is then
+        // made once it is handed back
+        if was_lost && self.seek_to.is_none() && !self.is_finished {
+            self.seek_to = Some(position);
+        }
+    }
+
+    fn push_boundary(&mut self, next: DecodeSource) {
+        self.boundaries.push_back(TrackBoundary {
+            starts_at: self.staged_end(),
+            position: 0,
+            track: Some(BoundaryTrack {
+                path: next.path.clone(),
+                file_size: next.file_size.clone(),
+                duration: next.duration.clone(),
+            }),
+        });
+        self.source = Some(next);
+    }
+
+    /// The output frame right after everything decoded so far.
+    fn staged_end(&self) -> u64 {
+        let channels = self.channels.max(1) as u64;
+        self.frames_pushed
+            + self.samples.len() as u64 / channels
+            + self.stretch.as_ref().map_or(0, |s| s.latency_frames())
+    }
+}
+
+pub struct AudioPlayer {
+    pub state: Arc<Mutex<AudioData>>,
+    pub output_stream: Option<OutputStream>,
+}
+
+impl AudioPlayer {
+    pub fn bad() -> Self {
+        let audio_data = AudioData::default();
+        Self {
+            state: Arc::new(Mutex::new(audio_data)),
+            output_stream: None,
+        }
+    }
+
+    pub fn new(path: &str, gain: f32) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut source = DecodeSource::open(path)?;
+        source.set_gain(gain);
+
+        let audio_data = AudioData {
+            samples: VecDeque::new(),
+            sample_rate: source.sample_rate,
+            channels: source.channels,
+            file_size: source.file_size.clone(),
+            duration: source.duration.clone(),
             host: String::new(),
+            current_path: source.path.clone(),
 
-            is_seeking: false,
+            seek_to: None,
             is_finished: false,
-            is_pause: false,
-            volume: 0.3,
-
-            total_samples_played: 0,
+            is_stopped: false,
+            transport: Arc::new(Transport::with_volume(0.3)),
+
+            origin_output: 0,
+            origin_position: 0,
+            origin_speed: 1.0,
+            pending_origin: None,
+            frames_pushed: 0,
             min_buffer_threshold: 4096,
 
-            format_reader: Some(format_reader),
-            decoder: Some(decoder),
-            track_id,
+            ring: None,
+            tap: None,
+
+            source: Some(source),
+            source_generation: 0,
+
+            next_path: None,
+            next_gain: 1.0,
+            next_source: None,
+            crossfade: Crossfade::default(),
+            fade_out: None,
+            boundaries: VecDeque::new(),
+            track_changed: false,
+            skipped_packets: 0,
+
+            eq: Vec::new(),
+            eq_generation: 0,
+
+            loop_a: None,
+            loop_b: None,
 
-            fft_state: vec![],
+            speed: 1.0,
+            speed_mode: SpeedMode::default(),
+            stretch: None,
+
+            scope: Scope::default(),
+            spectrum_settings: SpectrumSettings::default(),
             enable_fft_compute: true,
         };
 
         Ok(Self {
             state: Arc::new(Mutex::new(audio_data)),
-            cpal_stream: None,
+            output_stream: None,
         })
     }
 
-    pub fn play(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let host = cpal::default_host();
-        let device = host.default_output_device().expect("no default device");
+    /// Swap the playing file without touching the output stream. Returns
+    /// `Ok(false)` when there is no stream to play it on yet.
+    pub fn load(&self, path: &str, gain: f32) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.output_stream.is_none() {
+            return Ok(false);
+        }
 
-        let mut state = self.state.lock().unwrap();
-        state.host = device.name().unwrap();
-        drop(state);
+        let mut source = DecodeSource::open(path)?;
+        let mut audio_data = self.state.lock().map_err(|_| "Mutex lock failed")?;
+        source.attach(audio_data.sample_rate, audio_data.channels);
+        source.set_gain(gain);
+
+        audio_data.boundaries.clear();
+        audio_data.next_path = None;
+        audio_data.next_source = None;
+        audio_data.fade_out = None;
+        audio_data.track_changed = false;
+        audio_data.loop_a = None;
+        audio_data.loop_b = None;
+
+        audio_data.current_path = source.path.clone();
+        audio_data.file_size = source.file_size.clone();
+        audio_data.duration = source.duration.clone();
+        audio_data.seek_to = None;
+        audio_data.is_finished = false;
+        audio_data.flush(0);
+
+        audio_data.source = Some(source);
+        audio_data.source_generation += 1;
+
+        Ok(true)
+    }
 
-        let (channels, sample_rate) = {
-            let state = self.state.lock().map_err(|_| "Mutex lock failed")?;
-            (state.channels, state.sample_rate)
+    /// Tell the decoder which file follows the current one, so it can be
+    /// opened ahead of time and played without a gap, `gain` times louder.
+    pub fn set_next(&self, next: Option<(String, f32)>) {
+        let (path, gain) = match next {
+            Some((path, gain)) => (Some(path), gain),
+            None => (None, 1.0),
         };
 
-        let config = cpal::StreamConfig {
-            channels,
-            sample_rate: cpal::SampleRate(sample_rate),
-            buffer_size: cpal::BufferSize::Default,
-        };
+        if let Ok(mut audio_data) = self.state.lock() {
+            if audio_data.next_path == path && audio_data.next_gain == gain {
+                return;
+            }
+            audio_data.next_path = path;
+            audio_data.next_gain = gain;
+            audio_data.next_source = None;
+        }
+    }
 
-        let state_clone = self.state.clone();
+    /// Play every track through `bands` from now on, an empty set plays flat.
+    pub fn set_eq(&self, bands: Vec<EqBand>) {
+        if let Ok(mut audio_data) = self.state.lock() {
+            if audio_data.eq == bands {
+                return;
+            }
+            audio_data.eq = bands;
+            audio_data.eq_generation += 1;
+        }
+    }
 
-        let stream = device.build_output_stream(
-            &config,
-            move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-                if let Ok(mut audio_data) = state_clone.lock() {
-                    if audio_data.is_pause {
-                        for sample in data.iter_mut() {
-                            *sample = 0.0;
-                        }
-                        return;
-                    }
-                    for frame in data.chunks_mut(audio_data.channels as usize) {
-                        for (_, sample) in frame.iter_mut().enumerate() {
-                            *sample =
-                                audio_data.samples.pop_front().unwrap_or(0.0) * audio_data.volume;
-                        }
-                        if audio_data.is_finished != true {
-                            audio_data.total_samples_played += 1;
-                        }
-                    }
-                }
-            },
-            |err| eprintln!("Stream error: {}", err),
-            None,
-        )?;
+    /// Play at `speed` times the normal rate from now on. What was already
+    /// decoded is dropped, so the change is heard at once.
+    pub fn set_speed(&self, speed: f32, mode: SpeedMode) {
+        if let Ok(mut audio_data) = self.state.lock() {
+            if (audio_data.speed, audio_data.speed_mode) == (speed, mode) {
+                return;
+            }
+
+            let position = audio_data.position_frames();
+            audio_data.speed = speed;
+            audio_data.speed_mode = mode;
+            audio_data.rebuild_stretch();
+
+            let is_playing = audio_data.ring.is_some() && !audio_data.is_finished;
+            if is_playing && audio_data.seek_to.is_none() {
+                audio_data.seek_to = Some(position);
+            } else if !is_playing {
+                let output = audio_data.transport.frames_output();
+                audio_data.set_origin(output, position);
+            }
+        }
+    }
 
-        stream.play()?;
+    /// Fade over `ramp` whenever playback pauses, resumes, seeks, loads a
+    /// file or stops. Zero cuts at once.
+    pub fn set_ramp(&self, ramp: std::time::Duration) {
+        if let Ok(audio_data) = self.state.lock() {
+            audio_data.transport.set_ramp_ms(ramp.as_millis() as u32);
+        }
+    }
 
-        self.cpal_stream = Some(stream);
+    /// Bar count and frequency range of the spectrum analyzer.
+    pub fn set_spectrum(&self, settings: SpectrumSettings) {
+        if let Ok(mut audio_data) = self.state.lock() {
+            audio_data.spectrum_settings = settings;
+        }
+    }
+
+    pub fn set_crossfade(&self, crossfade: Crossfade) {
+        if let Ok(mut audio_data) = self.state.lock() {
+            audio_data.crossfade = crossfade;
+        }
+    }
+
+    /// Returns true once after the output moved on to the primed next track.
+    pub fn take_track_change(&self) -> bool {
+        match self.state.lock() {
+            Ok(mut audio_data) => {
+                audio_data.sync();
+                std::mem::take(&mut audio_data.track_changed)
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// How many corrupt packets were skipped since the last call.
+    pub fn take_skipped_packets(&self) -> u64 {
+        match self.state.lock() {
+            Ok(mut audio_data) => std::mem::take(&mut audio_data.skipped_packets),
+            Err(_) => 0,
+        }
+    }
+
+    pub fn play(&mut self, target: &OutputTarget) -> Result<(), Box<dyn std::error::Error>> {
+        self.open_stream(target)?;
 
         let state_clone_2 = self.state.clone();
         std::thread::spawn(move || {
             Self::decode_loop(state_clone_2);
         });
 
-        let fft_state = self.state.clone();
-        let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(2056);
-
+        let spectrum_state = self.state.clone();
         std::thread::spawn(move || {
+            let mut window: VecDeque<f32> = VecDeque::with_capacity(FFT_SIZE * 2);
+            let mut analyzer: Option<Analyzer> = None;
+
             loop {
-                let maybe_chunk = {
-                    let data = fft_state.lock().unwrap();
-                    if data.enable_fft_compute && data.samples.len() >= 4056 {
-                        let chunk: Vec<f32> = data.samples.iter().take(2056).cloned().collect();
-                        Some(chunk)
-                    } else {
-                        None
+                let (is_enabled, is_fresh, settings, sample_rate) = {
+                    let mut data = spectrum_state.lock().unwrap();
+                    if data.is_stopped {
+                        return;
+                    }
+                    let channels = data.channels.max(1) as usize;
+                    let samples: Vec<f32> = match data.tap.as_mut() {
+                        Some(tap) => match tap.read_chunk(tap.slots() / channels * channels) {
+                            Ok(chunk) => chunk.into_iter().collect(),
+                            Err(_) => Vec::new(),
+                        },
+                        None => Vec::new(),
+                    };
+                    let is_fresh = !samples.is_empty();
+
+                    // the analyzer looks at the mono sum of every channel
+                    window.extend(
+                        samples
+                            .chunks_exact(channels)
+                            .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+                    );
+                    if data.enable_fft_compute {
+                        if is_fresh {
+                            data.scope.push(&samples, channels);
+                        } else {
+                            data.scope.idle();
+                        }
                     }
+                    (
+                        data.enable_fft_compute,
+                        is_fresh,
+                        data.spectrum_settings,
+                        data.sample_rate,
+                    )
                 };
 
-                if let Some(chunk) = maybe_chunk {
-                    let fft_result = Self::compute_fft(&chunk, &fft);
-                    let mut data = fft_state.lock().unwrap();
-                    data.fft_state = fft_result;
+                let excess = window.len().saturating_sub(FFT_SIZE);
+                window.drain(..excess);
+                if !is_fresh {
+                    // paused or stalled, the bars fall back to the floor
+                    window.clear();
+                }
+
+                if is_enabled {
+                    let analyzer = match analyzer.as_mut() {
+                        Some(analyzer) if analyzer.fits(settings, sample_rate) => analyzer,
+                        _ => analyzer.insert(Analyzer::new(settings, sample_rate)),
+                    };
+                    let spectrum = if window.len() == FFT_SIZE {
+                        analyzer.process(window.make_contiguous())
+                    } else {
+                        analyzer.decay()
+                    };
+                    spectrum_state.lock().unwrap().scope.set_spectrum(spectrum);
                 }
 
                 std::thread::sleep(std::time::Duration::from_millis(30));
@@ -206,105 +1062,366 @@ impl AudioPlayer {
         Ok(())
     }
 
-    fn compute_fft(samples: &[f32], fft: &Arc<dyn rustfft::Fft<f32>>) -> Vec<f32> {
-        let mut buffer: Vec<Complex<f32>> = samples
-            .iter()
-            .map(|&x| Complex { re: x, im: 0.0 })
-            .collect();
+    /// Move playback to another output device, keeping the current position.
+    pub fn switch_output(
+        &mut self,
+        target: &OutputTarget,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.output_stream.is_none() {
+            return Ok(());
+        }
+
+        // release the old device first, some backends only allow one stream
+        let was_paused = self.fade_out();
+        self.output_stream = None;
+        self.open_stream(target)?;
+
+        if let Ok(audio_data) = self.state.lock() {
+            audio_data.transport.set_paused(was_paused);
+        }
+        Ok(())
+    }
+
+    /// Pause and wait, for a moment at most, until the callback faded the
+    /// output to silence, so the stream can go without a click. Returns
+    /// whether playback was paused already.
+    fn fade_out(&self) -> bool {
+        let (transport, is_audible) = match self.state.lock() {
+            Ok(audio_data) => (
+                audio_data.transport.clone(),
+                audio_data.buffered_samples() > 0,
+            ),
+            Err(_) => return false,
+        };
+        let was_paused = transport.is_paused();
+        transport.set_paused(true);
+        if was_paused || !is_audible || self.output_stream.is_none() {
+            return was_paused;
+        }
+
+        let deadline = std::time::Instant::now()
+            + std::time::Duration::from_millis(transport.ramp_ms() as u64 + 100);
+        while !transport.is_silent() && std::time::Instant::now() < deadline {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        was_paused
+    }
+
+    fn open_stream(&mut self, target: &OutputTarget) -> Result<(), Box<dyn std::error::Error>> {
+        let sink = sink::open(target)?;
+
+        let (format, transport, mut ring, mut tap) = {
+            let mut state = self.state.lock().map_err(|_| "Mutex lock failed")?;
+            state.host = sink.name();
+
+            let (file_rate, file_channels) = state
+                .source
+                .as_ref()
+                .map(|source| (source.sample_rate, source.channels))
+                .unwrap_or((state.sample_rate, state.channels));
+            let format = sink.negotiate(StreamFormat {
+                sample_rate: file_rate,
+                channels: file_channels,
+            });
+
+            // about a quarter of a second of audio between decoder and output
+            let capacity =
+                (format.sample_rate as usize * format.channels.max(1) as usize / 4).max(4096);
+            let (ring_in, ring_out) = RingBuffer::new(capacity);
+            let (tap_in, tap_out) = RingBuffer::new(FFT_SIZE * 4);
+
+            // from here on every source is converted to what the output plays
+            state.attach_output(format.sample_rate, format.channels, ring_in, tap_out);
+            (format, state.transport.clone(), ring_out, tap_in)
+        };
+
+        let channels = format.channels.max(1) as usize;
+        let sample_rate = format.sample_rate as u64;
+        let mut ramp = Ramp::new(0);
+
+        let stream = sink.start(
+            format,
+            Box::new(move |data: &mut [f32]| {
+                ramp.set_frames((transport.ramp_ms() as u64 * sample_rate / 1000) as u32);
+                fill_output(data, &mut ring, &mut tap, &transport, &mut ramp, channels);
+            }),
+        )?;
+
+        self.output_stream = Some(stream);
+
+        Ok(())
+    }
 
-        fft.process(&mut buffer);
-        buffer.iter().map(|c| c.norm()).collect()
+    /// Whether the decoder reached the end and the output drained every sample.
+    pub fn has_ended(&self) -> bool {
+        match self.state.lock() {
+            Ok(audio_data) => {
+                self.output_stream.is_some()
+                    && audio_data.is_finished
+                    && audio_data.buffered_samples() == 0
+                    && !audio_data.transport.is_flushing()
+            }
+            Err(_) => false,
+        }
     }
 
     fn decode_loop(state: Arc<Mutex<AudioData>>) {
         loop {
-            let (format_reader, decoder, track_id) = {
+            let (source, fade_out, generation, prime_path) = {
                 let mut audio_data = state.lock().unwrap();
-                if audio_data.is_finished {
+                if audio_data.is_stopped {
                     return;
                 }
 
-                if audio_data.is_seeking {
-                    audio_data.is_seeking = false;
-                    audio_data.samples.clear();
+                audio_data.sync();
+                if audio_data.transport.is_flushing() {
+                    // the callback still has to throw away what it holds
+                    drop(audio_data);
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    continue;
+                }
 
-                    let target_samples = audio_data.total_samples_played;
+                if audio_data.seek_to.is_none()
+                    && let Some(a) = audio_data.missed_loop()
+                {
+                    let seconds = a as f64 / audio_data.sample_rate.max(1) as f64;
+                    let is_seeked = audio_data
+                        .source
+                        .as_mut()
+                        .is_some_and(|source| source.seek(seconds).is_ok());
+                    if is_seeked {
+                        audio_data.flush(a);
+                    } else {
+                        // a file that can't seek can't loop either
+                        audio_data.loop_b = None;
+                    }
+                    continue;
+                }
 
-                    let time_base = audio_data
-                        .format_reader
-                        .as_ref()
-                        .unwrap()
-                        .tracks()
-                        .iter()
-                        .find(|t| t.id == audio_data.track_id)
-                        .unwrap()
-                        .codec_params
-                        .time_base
-                        .unwrap();
-
-                    let seek_time = time_base.calc_time(target_samples);
-
-                    let seek_to = symphonia::core::formats::SeekTo::Time {
-                        time: seek_time,
-                        track_id: Some(audio_data.track_id),
-                    };
+                if let Some(target) = audio_data.seek_to.take() {
+                    // the decoder already moved on, so the seek lands in the new track
+                    while !audio_data.boundaries.is_empty() {
+                        audio_data.apply_boundary();
+                    }
+                    audio_data.fade_out = None;
 
-                    audio_data
-                        .format_reader
+                    // the output counts frames at the device rate, the file in seconds
+                    let seconds = target as f64 / audio_data.sample_rate.max(1) as f64;
+                    let is_seeked = audio_data
+                        .source
                         .as_mut()
-                        .unwrap()
-                        .seek(symphonia::core::formats::SeekMode::Accurate, seek_to)
-                        .unwrap();
+                        .is_some_and(|source| source.seek(seconds).is_ok());
+                    if is_seeked {
+                        audio_data.flush(target);
+                    }
+                    continue;
                 }
 
-                if audio_data.samples.len() > audio_data.min_buffer_threshold {
+                audio_data.fill_ring();
+                audio_data.refresh_eq();
+
+                let prime_path = match (&audio_data.next_path, &audio_data.next_source) {
+                    (Some(path), None) => Some(path.clone()),
+                    _ => None,
+                };
+
+                if audio_data.is_finished
+                    || (prime_path.is_none()
+                        && audio_data.samples.len() > audio_data.min_buffer_threshold)
+                {
                     drop(audio_data);
                     std::thread::sleep(std::time::Duration::from_millis(10));
                     continue;
                 }
-                (
-                    audio_data.format_reader.take(),
-                    audio_data.decoder.take(),
-                    audio_data.track_id,
-                )
-            };
 
-            let (Some(mut format_reader), Some(mut decoder)) = (format_reader, decoder) else {
-                return;
-            };
+                if prime_path.is_some() {
+                    (None, None, audio_data.source_generation, prime_path)
+                } else {
+                    let crossfade_now = audio_data
+                        .source
+                        .as_ref()
+                        .is_some_and(|source| audio_data.should_crossfade(source));
+                    if crossfade_now && let Some(outgoing) = audio_data.source.take() {
+                        audio_data.start_crossfade(outgoing);
+                        continue;
+                    }
 
-            let result = format_reader.next_packet().ok().and_then(|packet| {
-                if packet.track_id() != track_id {
-                    return None;
+                    (
+                        audio_data.source.take(),
+                        audio_data.fade_out.take(),
+                        audio_data.source_generation,
+                        None,
+                    )
                 }
-                match decoder.decode(&packet) {
-                    Ok(decoded) => {
-                        let mut sample_buffer = symphonia::core::audio::SampleBuffer::<f32>::new(
-                            decoded.capacity() as u64,
-                            *decoded.spec(),
-                        );
-                        sample_buffer.copy_interleaved_ref(decoded);
-
-                        Some(sample_buffer.samples().to_vec())
+            };
+
+            // open the upcoming file ahead of time, away from the lock
+            if let Some(path) = prime_path {
+                let primed = DecodeSource::open(&path);
+                let mut audio_data = state.lock().unwrap();
+                if audio_data.next_path.as_ref() == Some(&path) {
+                    match primed {
+                        Ok(mut next) => {
+                            next.attach(audio_data.sample_rate, audio_data.channels);
+                            next.set_gain(audio_data.next_gain);
+                            audio_data.next_source = Some(next);
+                        }
+                        Err(_) => audio_data.next_path = None,
                     }
-                    Err(_) => None,
                 }
-            });
+                continue;
+            }
+
+            let Some(mut source) = source else {
+                state.lock().unwrap().is_finished = true;
+                continue;
+            };
+
+            let decoded_before = source.frames_decoded;
+            let mut result = source.next_output();
+
+            // mix the tail of the previous track under the new one
+            let mut fade_out = fade_out;
+            if let (Some(samples), Some(fade)) = (result.as_mut(), fade_out.as_mut())
+                && !fade.mix_into(samples)
+            {
+                fade_out = None;
+            }
 
             let mut audio_data = state.lock().unwrap();
-            audio_data.format_reader = Some(format_reader);
-            audio_data.decoder = Some(decoder);
+            audio_data.skipped_packets += std::mem::take(&mut source.skipped_packets);
+            if audio_data.source_generation != generation {
+                // a new file was loaded while this packet was decoding
+                continue;
+            }
 
-            if let Some(samples) = result {
-                audio_data.samples.extend(samples);
-            } else {
-                audio_data.is_finished = true;
-                return;
+            if source.out_format != (audio_data.sample_rate, audio_data.channels) {
+                // the output device changed while this packet was decoding
+                source.attach(audio_data.sample_rate, audio_data.channels);
+                audio_data.source = Some(source);
+                continue;
+            }
+
+            match result {
+                Some(mut samples) => {
+                    let loop_to = audio_data.loop_back(&source, decoded_before, &mut samples);
+                    audio_data.fade_out = fade_out;
+                    let samples = match audio_data.stretch.as_mut() {
+                        Some(stretch) => stretch.process(&samples),
+                        None => samples,
+                    };
+                    audio_data.samples.extend(samples);
+
+                    // go on decoding from A, the output plays on without a gap
+                    let seconds =
+                        |frames: u64| frames as f64 / audio_data.sample_rate.max(1) as f64;
+                    if let Some(a) = loop_to
+                        && source.seek(seconds(a)).is_ok()
+                    {
+                        let starts_at = audio_data.staged_end();
+                        audio_data.boundaries.push_back(TrackBoundary {
+                            starts_at,
+                            position: a,
+                            track: None,
+                        });
+                    }
+                    audio_data.source = Some(source);
+                }
+                None => {
+                    if audio_data.seek_to.is_some() {
+                        // a seek came in while this packet was decoding, it
+                        // lands in this track
+                        audio_data.source = Some(source);
+                    } else if !audio_data.start_next_source() {
+                        audio_data.source = Some(source);
+                        audio_data.is_finished = true;
+                        // play out what the speed stage still holds
+                        if let Some(tail) = audio_data.stretch.as_mut().map(|s| s.flush()) {
+                            audio_data.samples.extend(tail);
+                        }
+                    }
+                }
             }
         }
     }
 }
 
+/// Fill one output buffer from the ring. Runs on the real-time thread, so
+/// it only ever touches the ring and atomics.
+fn fill_output(
+    data: &mut [f32],
+    ring: &mut Consumer<f32>,
+    tap: &mut Producer<f32>,
+    transport: &Transport,
+    ramp: &mut Ramp,
+    channels: usize,
+) {
+    // what plays fades out before a pause, and before a flush drops the rest
+    let is_flushing = transport.is_flushing();
+    let is_fading_out = is_flushing || transport.is_paused();
+
+    let mut ready = data.len().min(ring.slots()) / channels * channels;
+    if is_fading_out {
+        ready = ready.min(ramp.frames_to_silence() * channels);
+    }
+    if let Ok(chunk) = ring.read_chunk(ready) {
+        let (first, second) = chunk.as_slices();
+        data[..first.len()].copy_from_slice(first);
+        data[first.len()..ready].copy_from_slice(second);
+        chunk.commit_all();
+    }
+    data[ready..].fill(0.0);
+
+    // the analyzer sees what actually plays, a full tap just skips a buffer
+    let tapped = ready.min(tap.slots()) / channels * channels;
+    if let Ok(chunk) = tap.write_chunk_uninit(tapped) {
+        chunk.fill_from_iter(data[..tapped].iter().copied());
+    }
+
+    ramp.apply(
+        &mut data[..ready],
+        channels,
+        if is_fading_out { 0.0 } else { 1.0 },
+    );
+    let volume = transport.volume();
+    for sample in data[..ready].iter_mut() {
+        *sample *= volume;
+    }
+
+    let mut played = ready / channels;
+    let is_flushed = is_flushing && (ramp.frames_to_silence() == 0 || ring.slots() == 0);
+    if is_flushed {
+        let stale = ring.slots();
+        if let Ok(chunk) = ring.read_chunk(stale) {
+            chunk.commit_all();
+        }
+        played += stale / channels;
+        // whatever comes after the flush fades in
+        ramp.silence();
+    }
+
+    transport
+        .frames_output
+        .fetch_add(played as u64, Ordering::AcqRel);
+    transport
+        .is_silent
+        .store(ramp.is_silent(), Ordering::Release);
+    if is_flushed {
+        transport.flush.store(false, Ordering::Release);
+    }
+}
+
+impl Drop for AudioPlayer {
+    fn drop(&mut self) {
+        self.fade_out();
+
+        // let the decode and fft threads of this player wind down
+        if let Ok(mut audio_data) = self.state.lock() {
+            audio_data.is_stopped = true;
+        }
+    }
+}
+
 fn human_readable_size(size: u64) -> String {
     let units = ["b", "kb", "mb", "gb", "tb"];
     let mut size_f = size as f64;
@@ -336,44 +1453,62 @@ fn get_audio_duration(track: &symphonia::core::formats::Track) -> DurationInfo {
         DurationInfo {
             readable,
             seconds: duration_secs.round() as u64,
+            frames: n_frames,
+            sample_rate,
         }
     } else {
         DurationInfo {
             readable: "Unknown".into(),
-            seconds: 0,
+            ..Default::default()
         }
     }
 }
 
+/// Move `skip_seconds` forward or back from where the output is.
 pub fn skip(state: &mut AudioData, skip_seconds: f64) -> EchoResult<()> {
-    let audio_data = state;
-    audio_data.is_finished = false;
-
-    let sample_rate = audio_data.sample_rate as f64;
-    let channels = audio_data.channels as f64;
-    let samples_to_skip = (skip_seconds * sample_rate * channels) as i64;
+    let frames = (skip_seconds * state.sample_rate as f64) as i64;
+    let target = (state.position_frames() as i64)
+        .saturating_add(frames)
+        .max(0);
+    seek_frames(state, target as u64);
 
-    let current_position_i64 = audio_data.total_samples_played as i64;
+    Ok(())
+}
 
-    let max_samples = (audio_data.duration.seconds as f64 * audio_data.sample_rate as f64) as i64;
+/// Jump to an absolute position of the current track.
+pub fn seek(state: &mut AudioData, target: SeekTarget) -> EchoResult<()> {
+    let frames = match target {
+        SeekTarget::Seconds(seconds) => seconds * state.sample_rate as f64,
+        SeekTarget::Percent(percent) => state.duration_frames() as f64 * percent / 100.0,
+    };
+    seek_frames(state, frames as u64);
 
-    let mut target_samples = current_position_i64
-        .saturating_add(samples_to_skip)
-        .min(max_samples);
+    Ok(())
+}
 
-    if target_samples >= max_samples {
-        target_samples = max_samples - 1;
-        audio_data.is_finished = true;
-    }
+fn seek_frames(state: &mut AudioData, target: u64) {
+    let duration = state.duration_frames();
+    // a seek to the very end lets the decoder run into the next track
+    let target = if duration > 0 {
+        target.min(duration - 1)
+    } else {
+        target
+    };
 
-    if target_samples < 0 {
-        target_samples = 0;
-    }
+    state.is_finished = false;
+    state.seek_to = Some(target);
+}
 
-    audio_data.total_samples_played = target_samples as u64;
-    audio_data.is_seeking = true;
+/// B of the A–B loop, counted in output frames, in frames of a source that
+/// decodes at `source_rate`.
+fn loop_end_in_source(b: u64, source_rate: u32, out_rate: u32) -> u64 {
+    b * source_rate as u64 / out_rate.max(1) as u64
+}
 
-    Ok(())
+/// Whether a packet that took the source from `before` to `after` ran into
+/// B, only then it can be cut off there without a gap.
+fn crosses_loop_end(before: u64, after: u64, b_source: u64) -> bool {
+    before < b_source && after >= b_source
 }
 
 pub fn current_timestamp(total_samples_played: u64, sample_rate: u32) -> (String, f64) {
@@ -391,3 +1526,115 @@ pub fn current_timestamp(total_samples_played: u64, sample_rate: u32) -> (String
 
     (readable, seconds)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::awdio::device::SinkKind;
+    use crate::testing::{TempDir, sine_wav};
+
+    #[test]
+    fn loop_end_follows_the_source_rate() {
+        assert_eq!(loop_end_in_source(48_000, 44_100, 48_000), 44_100);
+        assert_eq!(loop_end_in_source(44_100, 44_100, 44_100), 44_100);
+    }
+
+    #[test]
+    fn packet_crossing_b_is_cut() {
+        let b = loop_end_in_source(44_100, 44_100, 44_100);
+        assert!(crosses_loop_end(43_000, 44_152, b));
+        assert!(crosses_loop_end(43_000, 44_100, b));
+        assert!(!crosses_loop_end(42_000, 43_000, b));
+    }
+
+    #[test]
+    fn loop_set_behind_the_decoder_starts_over() {
+        let dir = TempDir::new("echo_loop_behind");
+        let path = dir.join("song.wav");
+        sine_wav(&path, 44_100, 2.0, 0.5);
+        let player = AudioPlayer::new(path.to_str().unwrap(), 1.0).unwrap();
+        let mut audio_data = player.state.lock().unwrap();
+
+        // the decoder runs a quarter second ahead of the playing position
+        let mut source = audio_data.source.take().unwrap();
+        while source.frames_decoded < 44_100 + 11_025 {
+            source.next_output().unwrap();
+        }
+        audio_data.source = Some(source);
+
+        audio_data.loop_a = Some(22_050);
+        audio_data.loop_b = Some(44_100);
+        let source = audio_data.source.as_ref().unwrap();
+        let decoded = source.frames_decoded;
+        let mut samples = vec![0.0; 1_152];
+        assert_eq!(audio_data.loop_back(source, decoded, &mut samples), None);
+        assert_eq!(audio_data.missed_loop(), Some(22_050));
+
+        // once back at A the packets cross B again
+        audio_data.source.as_mut().unwrap().seek(0.5).unwrap();
+        assert_eq!(audio_data.missed_loop(), None);
+
+        drop(audio_data);
+    }
+
+    #[test]
+    fn b_behind_the_decoder_is_never_crossed() {
+        // B set at the playing position while the decoder is a ring's worth,
+        // a quarter second, ahead
+        let b = loop_end_in_source(44_100, 44_100, 44_100);
+        let decoded = 44_100 + 11_025;
+        assert!(!crosses_loop_end(decoded, decoded + 1_152, b));
+        assert!(decoded >= b);
+    }
+
+    #[test]
+    fn track_plays_through_the_wav_sink_headless() {
+        let dir = TempDir::new("echo_headless");
+        let (path, out) = (dir.join("song.wav"), dir.join("out.wav"));
+        sine_wav(&path, 44_100, 1.0, 0.5);
+        let mut player = AudioPlayer::new(path.to_str().unwrap(), 1.0).unwrap();
+        let target = OutputTarget {
+            sink: SinkKind::Wav,
+            wav_path: Some(out.clone()),
+            ..Default::default()
+        };
+
+        player.play(&target).unwrap();
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while !player.has_ended() {
+            assert!(std::time::Instant::now() < deadline, "playback never ended");
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        drop(player);
+
+        let mut reader = hound::WavReader::open(&out).unwrap();
+        assert_eq!(reader.spec().sample_rate, 44_100);
+        assert_eq!(reader.spec().channels, 1);
+        let samples: Vec<f32> = reader.samples::<f32>().map(Result::unwrap).collect();
+        // every frame of the track made it out, quiet ones only at the fades
+        let audible = samples.iter().filter(|s| s.abs() > 1e-4).count();
+        assert!(audible >= 44_100 * 95 / 100, "{} audible frames", audible);
+        assert!(samples.len() >= 44_100);
+    }
+
+    #[test]
+    fn output_lost_while_decoding_seeks_back_once_the_source_returns() {
+        let dir = TempDir::new("echo_lost_decoding");
+        let path = dir.join("song.wav");
+        sine_wav(&path, 44_100, 1.0, 0.5);
+        let player = AudioPlayer::new(path.to_str().unwrap(), 1.0).unwrap();
+        let mut audio_data = player.state.lock().unwrap();
+
+        // the decoder holds the source while the device goes away with
+        // samples that were never played
+        let source = audio_data.source.take().unwrap();
+        audio_data.samples.extend([0.25; 4_410]);
+        let (ring, _) = RingBuffer::new(4_096);
+        let (_, tap) = RingBuffer::new(4_096);
+        audio_data.attach_output(44_100, 1, ring, tap);
+
+        assert_eq!(audio_data.seek_to, Some(audio_data.position_frames()));
+        audio_data.source = Some(source);
+        drop(audio_data);
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
+
+use serde::Deserialize;
+
+use super::DecodeSource;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossfadeCurve {
+    #[default]
+    Linear,
+    EqualPower,
+}
+
+impl CrossfadeCurve {
+    /// Gains of the (outgoing, incoming) track at `t` in `0.0..=1.0` of the fade.
+    pub fn gains(self, t: f32) -> (f32, f32) {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => (1.0 - t, t),
+            Self::EqualPower => {
+                let angle = t * FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Crossfade {
+    pub seconds: f32,
+    pub curve: CrossfadeCurve,
+}
+
+/// The track being faded out while the next one already plays.
+pub struct FadeOut {
+    pub source: DecodeSource,
+    pending: VecDeque<f32>,
+    channels: usize,
+    frame: u64,
+    total_frames: u64,
+    curve: CrossfadeCurve,
+    is_drained: bool,
+}
+
+impl FadeOut {
+    pub fn new(
+        source: DecodeSource,
+        channels: u16,
+        total_frames: u64,
+        curve: CrossfadeCurve,
+    ) -> Self {
+        Self {
+            channels: channels.max(1) as usize,
+            source,
+            pending: VecDeque::new(),
+            frame: 0,
+            total_frames: total_frames.max(1),
+            curve,
+            is_drained: false,
+        }
+    }
+
+    /// Mix the outgoing track under `incoming`. Returns false once the fade is over.
+    pub fn mix_into(&mut self, incoming: &mut [f32]) -> bool {
+        for frame in incoming.chunks_mut(self.channels) {
+            if self.frame >= self.total_frames {
+                return false;
+            }
+
+            let t = self.frame as f32 / self.total_frames as f32;
+            let (out_gain, in_gain) = self.curve.gains(t);
+            for sample in frame.iter_mut() {
+                let outgoing = self.next_sample();
+                *sample = *sample * in_gain + outgoing * out_gain;
+            }
+            self.frame += 1;
+        }
+
+        self.frame < self.total_frames
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        while self.pending.is_empty() && !self.is_drained {
+            match self.source.next_output() {
+                Some(samples) => self.pending.extend(samples),
+                None => self.is_drained = true,
+            }
+        }
+        self.pending.pop_front().unwrap_or(0.0)
+    }
+}
@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use cpal::traits::{DeviceTrait, HostTrait};
+use serde::Deserialize;
+
+/// What kind of output the samples go to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    /// A sound card, through cpal.
+    #[default]
+    Device,
+    /// Nowhere, paced like a sound card.
+    Null,
+    /// A WAV file, paced like a sound card.
+    Wav,
+}
+
+/// An output device picked by host and device name. `None` falls back to
+/// the system default.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub struct OutputTarget {
+    #[serde(default)]
+    pub sink: SinkKind,
+
+    #[serde(default)]
+    pub host: Option<String>,
+
+    #[serde(default)]
+    pub device: Option<String>,
+
+    // where the `wav` sink writes to
+    #[serde(default)]
+    pub wav_path: Option<PathBuf>,
+}
+
+impl OutputTarget {
+    pub fn null() -> Self {
+        Self {
+            sink: SinkKind::Null,
+            ..Default::default()
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match (self.sink, &self.host, &self.device) {
+            (SinkKind::Null, _, _) => "Null output".into(),
+            (SinkKind::Wav, _, _) => format!("WAV · {}", self.wav_path().display()),
+            (SinkKind::Device, None, None) => "System default".into(),
+            (SinkKind::Device, host, device) => format!(
+                "{} · {}",
+                host.as_deref().unwrap_or("default"),
+                device.as_deref().unwrap_or("default")
+            ),
+        }
+    }
+
+    /// The file the `wav` sink writes, `echo.wav` in the working directory
+    /// unless configured.
+    pub fn wav_path(&self) -> PathBuf {
+        self.wav_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("echo.wav"))
+    }
+}
+
+/// Every output device of every available host, the system default first
+/// and the null output last.
+pub fn list_outputs() -> Vec<OutputTarget> {
+    let mut outputs = vec![OutputTarget::default()];
+
+    for host_id in cpal::available_hosts() {
+        let Ok(host) = cpal::host_from_id(host_id) else {
+            continue;
+        };
+        let Ok(devices) = host.output_devices() else {
+            continue;
+        };
+
+        for device in devices {
+            if let Ok(name) = device.name() {
+                outputs.push(OutputTarget {
+                    host: Some(host_id.name().to_string()),
+                    device: Some(name),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    outputs.push(OutputTarget::null());
+    outputs
+}
+
+/// Find the device `target` points at, along with a readable "host · device" name.
+pub fn resolve(
+    target: &OutputTarget,
+) -> Result<(cpal::Device, String), Box<dyn std::error::Error>> {
+    let host = match &target.host {
+        Some(name) => {
+            let host_id = cpal::available_hosts()
+                .into_iter()
+                .find(|id| id.name().eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("Unknown audio host: {}", name))?;
+            cpal::host_from_id(host_id)?
+        }
+        None => cpal::default_host(),
+    };
+
+    let device = match &target.device {
+        Some(name) => host
+            .output_devices()?
+            .find(|d| d.name().is_ok_and(|n| &n == name))
+            .ok_or_else(|| format!("Unknown output device: {}", name))?,
+        None => host
+            .default_output_device()
+            .ok_or("No default output device")?,
+    };
+
+    let readable = format!(
+        "{} · {}",
+        host.id().name(),
+        device.name().unwrap_or_else(|_| "unknown".into())
+    );
+
+    Ok((device, readable))
+}
@@ -0,0 +1,286 @@
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+
+// the keyboard can't push a band further than this
+pub const MAX_GAIN_DB: f32 = 12.0;
+
+/// Octave bands of the built-in `flat` preset.
+const GRAPHIC_FREQS: [f32; 10] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BandKind {
+    #[default]
+    Peaking,
+    LowShelf,
+    HighShelf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct EqBand {
+    pub freq: f32,
+
+    #[serde(default)]
+    pub gain: f32,
+
+    #[serde(default = "default_q")]
+    pub q: f32,
+
+    #[serde(default)]
+    pub kind: BandKind,
+}
+
+fn default_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+/// Ten octave bands at 0 dB.
+pub fn flat() -> Vec<EqBand> {
+    GRAPHIC_FREQS
+        .iter()
+        .map(|&freq| EqBand {
+            freq,
+            gain: 0.0,
+            q: default_q(),
+            kind: BandKind::Peaking,
+        })
+        .collect()
+}
+
+/// One second order section in transposed direct form II.
+pub(super) struct Biquad {
+    pub(super) b: [f64; 3],
+    pub(super) a: [f64; 3],
+    pub(super) z: [f64; 2],
+}
+
+impl Biquad {
+    pub(super) fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[1] * output + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[2] * output;
+        output
+    }
+
+    /// Coefficients from the RBJ audio EQ cookbook.
+    fn design(band: &EqBand, sample_rate: f64) -> ([f64; 3], [f64; 3]) {
+        // keep the band below nyquist
+        let freq = (band.freq as f64).clamp(10.0, sample_rate * 0.49);
+        let q = (band.q as f64).max(0.05);
+        let a = 10f64.powf(band.gain as f64 / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q);
+
+        let (b, a) = match band.kind {
+            BandKind::Peaking => (
+                [1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a],
+                [1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a],
+            ),
+            BandKind::LowShelf => {
+                let sq = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) - (a - 1.0) * cos + sq),
+                        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                        a * ((a + 1.0) - (a - 1.0) * cos - sq),
+                    ],
+                    [
+                        (a + 1.0) + (a - 1.0) * cos + sq,
+                        -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                        (a + 1.0) + (a - 1.0) * cos - sq,
+                    ],
+                )
+            }
+            BandKind::HighShelf => {
+                let sq = 2.0 * a.sqrt() * alpha;
+                (
+                    [
+                        a * ((a + 1.0) + (a - 1.0) * cos + sq),
+                        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                        a * ((a + 1.0) + (a - 1.0) * cos - sq),
+                    ],
+                    [
+                        (a + 1.0) - (a - 1.0) * cos + sq,
+                        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                        (a + 1.0) - (a - 1.0) * cos - sq,
+                    ],
+                )
+            }
+        };
+
+        let a0 = a[0];
+        (
+            [b[0] / a0, b[1] / a0, b[2] / a0],
+            [1.0, a[1] / a0, a[2] / a0],
+        )
+    }
+}
+
+/// A chain of biquads for every channel, run on the decode thread.
+pub struct Equalizer {
+    bands: Vec<EqBand>,
+    // [band][channel]
+    filters: Vec<Vec<Biquad>>,
+    channels: usize,
+}
+
+impl Equalizer {
+    pub fn new(bands: &[EqBand], sample_rate: u32, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+        let filters = bands
+            .iter()
+            .map(|band| {
+                let (b, a) = Biquad::design(band, sample_rate.max(1) as f64);
+                (0..channels)
+                    .map(|_| Biquad { b, a, z: [0.0; 2] })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            bands: bands.to_vec(),
+            filters,
+            channels,
+        }
+    }
+
+    pub fn bands(&self) -> &[EqBand] {
+        &self.bands
+    }
+
+    /// Move to new band settings. Filter state is kept when the layout of the
+    /// bands stays the same, so dragging a gain doesn't click.
+    pub fn set_bands(&mut self, bands: &[EqBand], sample_rate: u32) {
+        if bands.len() != self.bands.len() {
+            *self = Self::new(bands, sample_rate, self.channels as u16);
+            return;
+        }
+
+        for (band, filters) in bands.iter().zip(self.filters.iter_mut()) {
+            let (b, a) = Biquad::design(band, sample_rate.max(1) as f64);
+            for filter in filters.iter_mut() {
+                filter.b = b;
+                filter.a = a;
+            }
+        }
+        self.bands = bands.to_vec();
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                let mut value = *sample as f64;
+                for filters in self.filters.iter_mut() {
+                    value = filters[channel].process(value);
+                }
+                *sample = value as f32;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 48_000.0;
+
+    fn band(kind: BandKind, freq: f32, gain: f32) -> EqBand {
+        EqBand {
+            freq,
+            gain,
+            q: default_q(),
+            kind,
+        }
+    }
+
+    // |H(e^jw)| of the designed section in dB
+    fn response_db(band: &EqBand, freq: f64) -> f64 {
+        let (b, a) = Biquad::design(band, RATE);
+        let w = 2.0 * PI * freq / RATE;
+        let eval = |c: [f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            re.hypot(im)
+        };
+        20.0 * (eval(b) / eval(a)).log10()
+    }
+
+    fn noise(len: usize) -> Vec<f32> {
+        let mut seed = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as f32 / u32::MAX as f32 * 2.0 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zero_db_bands_leave_samples_alone() {
+        let mut bands = flat();
+        bands.push(band(BandKind::LowShelf, 100.0, 0.0));
+        bands.push(band(BandKind::HighShelf, 8_000.0, 0.0));
+        let mut eq = Equalizer::new(&bands, RATE as u32, 2);
+        let input = noise(48_000);
+        let mut output = input.clone();
+
+        eq.process(&mut output);
+
+        for (i, (a, b)) in input.iter().zip(&output).enumerate() {
+            assert!((a - b).abs() < 1e-6, "sample {}: {} became {}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn peaking_band_reaches_its_gain_at_its_frequency() {
+        for freq in [31.0, 1_000.0, 16_000.0] {
+            for gain in [-MAX_GAIN_DB, -3.0, 6.0, MAX_GAIN_DB] {
+                let response = response_db(&band(BandKind::Peaking, freq, gain), freq as f64);
+                assert!(
+                    (response - gain as f64).abs() < 0.1,
+                    "{} Hz at {} dB: {} dB",
+                    freq,
+                    gain,
+                    response
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shelves_reach_half_their_gain_at_their_frequency() {
+        for gain in [-MAX_GAIN_DB, 6.0, MAX_GAIN_DB] {
+            let low = band(BandKind::LowShelf, 200.0, gain);
+            let high = band(BandKind::HighShelf, 4_000.0, gain);
+            let gain = gain as f64;
+            for (shelf, at, plateau) in [(low, 200.0, 10.0), (high, 4_000.0, 20_000.0)] {
+                let mid = response_db(&shelf, at);
+                assert!((mid - gain / 2.0).abs() < 0.1, "{:?}: {} dB", shelf, mid);
+                let far = response_db(&shelf, plateau);
+                assert!((far - gain).abs() < 0.5, "{:?}: {} dB", shelf, far);
+            }
+        }
+    }
+
+    #[test]
+    fn equalizer_boosts_a_sine_at_the_band() {
+        let mut eq = Equalizer::new(&[band(BandKind::Peaking, 1_000.0, 6.0)], RATE as u32, 1);
+        let mut samples: Vec<f32> = (0..RATE as usize)
+            .map(|i| (2.0 * PI * 1_000.0 * i as f64 / RATE).sin() as f32 * 0.25)
+            .collect();
+
+        eq.process(&mut samples);
+
+        // past the first few milliseconds the filter has settled
+        let peak = samples[4_800..].iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        let gain = 20.0 * (peak as f64 / 0.25).log10();
+        assert!((gain - 6.0).abs() < 0.1, "{} dB", gain);
+    }
+}
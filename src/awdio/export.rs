@@ -0,0 +1,269 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::Deserialize;
+use strum::Display;
+
+use super::DecodeSource;
+use super::eq::EqBand;
+use super::flac::FlacWriter;
+use super::metadata::Metadata;
+use super::song::Song;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, ValueEnum, Display)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    #[strum(to_string = "FLAC")]
+    Flac,
+    #[strum(to_string = "WAV")]
+    Wav,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Flac => "flac",
+            Self::Wav => "wav",
+        }
+    }
+}
+
+/// How a song is written out, `None` keeps what the file has.
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub sample_rate: Option<u32>,
+    // fewer channels than the file downmixes
+    pub channels: Option<u16>,
+    // 16 or 24, lossy files come out at 16
+    pub bits_per_sample: Option<u16>,
+    // linear gain baked into the samples, the ReplayGain tags are carried
+    // over when there is none
+    pub gain: Option<f32>,
+    pub eq: Vec<EqBand>,
+}
+
+/// "Artist - Title.ext", with whatever a file system may not take replaced.
+pub fn file_name(song: &Song, format: ExportFormat) -> String {
+    let metadata = &song.metadata;
+    let stem = if metadata.title.is_empty() {
+        Path::new(&song.path)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("export")
+            .to_string()
+    } else if metadata.artist.is_empty() {
+        metadata.title.clone()
+    } else {
+        format!("{} - {}", metadata.artist, metadata.title)
+    };
+
+    let stem: String = stem
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    format!("{}.{}", stem.trim(), format.extension())
+}
+
+/// Decode `song` through the same pipeline playback uses and write it to
+/// `dest`, tagged from its metadata. A failed export leaves no file behind.
+pub fn export(
+    song: &Song,
+    dest: &Path,
+    options: &ExportOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let result = write(song, dest, options);
+    if result.is_err() {
+        let _ = std::fs::remove_file(dest);
+    }
+    result
+}
+
+fn write(
+    song: &Song,
+    dest: &Path,
+    options: &ExportOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut source = DecodeSource::open(&song.path)?;
+    let sample_rate = options.sample_rate.unwrap_or(source.sample_rate);
+    let channels = options.channels.unwrap_or(source.channels).clamp(1, 8);
+    let bits = options
+        .bits_per_sample
+        .unwrap_or(match source.bits_per_sample {
+            Some(bits) if bits > 16 => 24,
+            _ => 16,
+        });
+    if sample_rate == 0 {
+        return Err("Can't export at a sample rate of 0".into());
+    }
+    if bits != 16 && bits != 24 {
+        return Err(format!("Can't export {}-bit samples, only 16 or 24", bits).into());
+    }
+
+    source.attach(sample_rate, channels);
+    source.set_eq(&options.eq);
+    if let Some(gain) = options.gain {
+        source.set_gain(gain);
+    }
+
+    let mut writer = match options.format {
+        ExportFormat::Flac => Writer::Flac(FlacWriter::create(
+            dest,
+            sample_rate,
+            channels,
+            bits,
+            &vorbis_tags(song, options.gain.is_none()),
+        )?),
+        ExportFormat::Wav => Writer::Wav(WavWriter::create(
+            dest,
+            WavSpec {
+                channels,
+                sample_rate,
+                bits_per_sample: bits,
+                sample_format: SampleFormat::Int,
+            },
+        )?),
+    };
+
+    // symphonia scales by a power of two, so this gets lossless files back
+    // bit for bit
+    let scale = (1i64 << (bits - 1)) as f32;
+    let (min, max) = (-scale, scale - 1.0);
+    let mut quantized = Vec::new();
+    while let Some(samples) = source.next_output() {
+        quantized.clear();
+        quantized.extend(
+            samples
+                .iter()
+                .map(|sample| (sample * scale).round().clamp(min, max) as i32),
+        );
+        writer.write(&quantized)?;
+    }
+
+    match writer {
+        Writer::Flac(flac) => flac.finish()?,
+        Writer::Wav(wav) => {
+            wav.finalize()?;
+            append_info_chunk(dest, &song.metadata)?;
+        }
+    }
+    Ok(())
+}
+
+enum Writer {
+    Flac(FlacWriter),
+    Wav(WavWriter<BufWriter<File>>),
+}
+
+impl Writer {
+    fn write(&mut self, samples: &[i32]) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::Flac(flac) => flac.write(samples)?,
+            Self::Wav(wav) => {
+                for &sample in samples {
+                    wav.write_sample(sample)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Vorbis comments from the metadata, with the ReplayGain of `song` when
+/// its gain was not baked in.
+fn vorbis_tags(song: &Song, with_replaygain: bool) -> Vec<(String, String)> {
+    let metadata = &song.metadata;
+    let mut tags: Vec<(String, String)> = [
+        ("TITLE", metadata.title.clone()),
+        ("ARTIST", metadata.artist.clone()),
+        ("ALBUM", metadata.album.clone()),
+        ("ALBUMARTIST", metadata.album_artist.clone()),
+        ("GENRE", metadata.genre.clone()),
+        ("DATE", number(metadata.year)),
+        ("TRACKNUMBER", number(metadata.track_number)),
+        ("TRACKTOTAL", number(metadata.total_tracks)),
+        ("DISCNUMBER", number(metadata.disc_number)),
+        ("DISCTOTAL", number(metadata.total_discs)),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(key, value)| (key.to_string(), value))
+    .collect();
+
+    if with_replaygain {
+        let gain = &song.gain;
+        let replaygain = [
+            ("REPLAYGAIN_TRACK_GAIN", gain.track_gain, " dB"),
+            ("REPLAYGAIN_TRACK_PEAK", gain.track_peak, ""),
+            ("REPLAYGAIN_ALBUM_GAIN", gain.album_gain, " dB"),
+            ("REPLAYGAIN_ALBUM_PEAK", gain.album_peak, ""),
+        ];
+        for (key, value, unit) in replaygain {
+            if let Some(value) = value {
+                tags.push((key.to_string(), format!("{:.6}{}", value, unit)));
+            }
+        }
+    }
+
+    tags
+}
+
+fn number(value: u32) -> String {
+    if value == 0 {
+        String::new()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Add a RIFF `LIST`/`INFO` chunk after the samples of a finished WAV file
+/// and grow the RIFF size to cover it.
+fn append_info_chunk(path: &Path, metadata: &Metadata) -> io::Result<()> {
+    let fields = [
+        (b"INAM", metadata.title.clone()),
+        (b"IART", metadata.artist.clone()),
+        (b"IPRD", metadata.album.clone()),
+        (b"IGNR", metadata.genre.clone()),
+        (b"ICRD", number(metadata.year)),
+        (b"ITRK", number(metadata.track_number)),
+    ];
+
+    let mut info = b"INFO".to_vec();
+    for (id, value) in fields.iter().filter(|(_, value)| !value.is_empty()) {
+        // NUL terminated, padded to an even length
+        let mut text = value.as_bytes().to_vec();
+        text.push(0);
+        info.extend(*id);
+        info.extend((text.len() as u32).to_le_bytes());
+        if text.len() % 2 == 1 {
+            text.push(0);
+        }
+        info.extend(text);
+    }
+    if info.len() == 4 {
+        return Ok(());
+    }
+
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let end = file.seek(SeekFrom::End(0))?;
+    // the samples may end on an odd byte, chunks start on even ones
+    if end % 2 == 1 {
+        file.write_all(&[0])?;
+    }
+    file.write_all(b"LIST")?;
+    file.write_all(&(info.len() as u32).to_le_bytes())?;
+    file.write_all(&info)?;
+
+    let riff_size = file.seek(SeekFrom::End(0))? - 8;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&(riff_size as u32).to_le_bytes())?;
+    file.flush()
+}
@@ -0,0 +1,565 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Frames in every FLAC frame but the last, what the reference encoder
+/// uses at its default levels.
+pub const BLOCK_SIZE: usize = 4096;
+/// The highest sample rate the format allows.
+pub const MAX_SAMPLE_RATE: u32 = 655_350;
+
+const MAX_FIXED_ORDER: usize = 4;
+const MAX_PARTITION_ORDER: u32 = 8;
+// 15 is the escape code among 4-bit rice parameters
+const MAX_RICE_PARAMETER: u32 = 14;
+// the byte of STREAMINFO the total sample count starts in, after "fLaC",
+// the block header and the block size, frame size and format fields
+const TOTAL_SAMPLES_OFFSET: u64 = 4 + 4 + 13;
+
+/// Writes interleaved integer samples as a FLAC stream. Frames are coded
+/// with the fixed predictors and whichever stereo decorrelation fits best.
+pub struct FlacWriter {
+    file: BufWriter<File>,
+    channels: usize,
+    bits_per_sample: u32,
+    // interleaved samples waiting for a full block
+    pending: Vec<i32>,
+    frame_number: u64,
+    total_frames: u64,
+}
+
+impl FlacWriter {
+    /// Start a stream at `path` with `tags` as its Vorbis comments.
+    pub fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        bits_per_sample: u16,
+        tags: &[(String, String)],
+    ) -> io::Result<Self> {
+        if !(1..=8).contains(&channels) || !(4..=24).contains(&bits_per_sample) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "FLAC takes 1 to 8 channels of 4 to 24 bits",
+            ));
+        }
+        if !(1..=MAX_SAMPLE_RATE).contains(&sample_rate) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("FLAC takes sample rates up to {} Hz", MAX_SAMPLE_RATE),
+            ));
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(b"fLaC")?;
+
+        // STREAMINFO, the total sample count is filled in by `finish`
+        let mut info = BitWriter::default();
+        info.write(BLOCK_SIZE as u64, 16);
+        info.write(BLOCK_SIZE as u64, 16);
+        // smallest and largest frame, unknown
+        info.write(0, 24);
+        info.write(0, 24);
+        info.write(sample_rate as u64, 20);
+        info.write(channels as u64 - 1, 3);
+        info.write(bits_per_sample as u64 - 1, 5);
+        info.write(0, 36);
+        // no MD5 of the samples, decoders skip the check
+        info.write(0, 64);
+        info.write(0, 64);
+        write_metadata_block(&mut file, 0, false, &info.into_bytes())?;
+
+        write_metadata_block(&mut file, 4, true, &vorbis_comment(tags))?;
+
+        Ok(Self {
+            file,
+            channels: channels as usize,
+            bits_per_sample: bits_per_sample as u32,
+            pending: Vec::with_capacity(BLOCK_SIZE * channels as usize),
+            frame_number: 0,
+            total_frames: 0,
+        })
+    }
+
+    /// Take interleaved samples, every one within `bits_per_sample`.
+    pub fn write(&mut self, samples: &[i32]) -> io::Result<()> {
+        let block = BLOCK_SIZE * self.channels;
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let take = (block - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..take]);
+            samples = &samples[take..];
+
+            if self.pending.len() == block {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Code what is left and fill in the length of the stream.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.pending
+            .truncate(self.pending.len() / self.channels * self.channels);
+        if !self.pending.is_empty() {
+            self.write_frame()?;
+        }
+
+        // the 36 bits start in the low nibble of the byte that ends the
+        // bits per sample field
+        let total = self.total_frames.min((1 << 36) - 1);
+        let mut field = [0u8; 5];
+        field[0] = (((self.bits_per_sample - 1) & 0x0f) << 4) as u8 | (total >> 32) as u8;
+        field[1..].copy_from_slice(&(total as u32).to_be_bytes());
+
+        let mut file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(TOTAL_SAMPLES_OFFSET))?;
+        file.write_all(&field)?;
+        file.flush()
+    }
+
+    fn write_frame(&mut self) -> io::Result<()> {
+        let frames = self.pending.len() / self.channels;
+        let channels: Vec<Vec<i64>> = (0..self.channels)
+            .map(|channel| {
+                self.pending[channel..]
+                    .iter()
+                    .step_by(self.channels)
+                    .map(|&sample| sample as i64)
+                    .collect()
+            })
+            .collect();
+        self.pending.clear();
+
+        let (assignment, subframes) = decorrelate(channels, self.bits_per_sample);
+
+        let mut frame = BitWriter::default();
+        // sync code, fixed block size
+        frame.write(0b11_1111_1111_1110, 14);
+        frame.write(0, 1);
+        frame.write(0, 1);
+        // block size as a 16-bit field at the end of the header
+        frame.write(0b0111, 4);
+        // sample rate and size from STREAMINFO
+        frame.write(0, 4);
+        frame.write(assignment as u64, 4);
+        frame.write(0, 3);
+        frame.write(0, 1);
+        write_utf8_number(&mut frame, self.frame_number);
+        frame.write(frames as u64 - 1, 16);
+        let crc = crc8(frame.bytes());
+        frame.write(crc as u64, 8);
+
+        for (samples, bits) in subframes {
+            write_subframe(&mut frame, &samples, bits);
+        }
+        frame.align();
+        let crc = crc16(frame.bytes());
+        frame.write(crc as u64, 16);
+
+        self.file.write_all(&frame.into_bytes())?;
+        self.frame_number += 1;
+        self.total_frames += frames as u64;
+        Ok(())
+    }
+}
+
+/// Pick how a stereo frame is coded: independent, left/side, side/right or
+/// mid/side, by the size of the second order residual. Side channels take
+/// one bit more.
+fn decorrelate(channels: Vec<Vec<i64>>, bits: u32) -> (u8, Vec<(Vec<i64>, u32)>) {
+    if channels.len() != 2 {
+        let assignment = channels.len() as u8 - 1;
+        return (
+            assignment,
+            channels
+                .into_iter()
+                .map(|samples| (samples, bits))
+                .collect(),
+        );
+    }
+
+    let (left, right) = (&channels[0], &channels[1]);
+    let side: Vec<i64> = left.iter().zip(right).map(|(l, r)| l - r).collect();
+    // the bit `mid` drops is carried by `side`
+    let mid: Vec<i64> = left.iter().zip(right).map(|(l, r)| (l + r) >> 1).collect();
+
+    let cost = |samples: &[i64]| -> u64 {
+        samples
+            .windows(3)
+            .map(|w| (w[2] - 2 * w[1] + w[0]).unsigned_abs())
+            .sum()
+    };
+    let (l, r, s, m) = (cost(left), cost(right), cost(&side), cost(&mid));
+    let options = [(l + r, 1u8), (l + s, 8), (s + r, 9), (m + s, 10)];
+    let best = options
+        .iter()
+        .min_by_key(|(cost, _)| *cost)
+        .map_or(1, |(_, assignment)| *assignment);
+
+    let [left, right]: [Vec<i64>; 2] = channels.try_into().unwrap_or_default();
+    let subframes = match best {
+        8 => vec![(left, bits), (side, bits + 1)],
+        9 => vec![(side, bits + 1), (right, bits)],
+        10 => vec![(mid, bits), (side, bits + 1)],
+        _ => vec![(left, bits), (right, bits)],
+    };
+    (best, subframes)
+}
+
+/// Code one channel as a constant, the best fixed predictor or verbatim,
+/// whichever is smallest.
+fn write_subframe(out: &mut BitWriter, samples: &[i64], bits: u32) {
+    if samples.iter().all(|&sample| sample == samples[0]) {
+        out.write(0, 1);
+        out.write(0b000000, 6);
+        out.write(0, 1);
+        out.write_signed(samples[0], bits);
+        return;
+    }
+
+    let verbatim = samples.len() as u64 * bits as u64;
+    let best = (0..=MAX_FIXED_ORDER.min(samples.len() - 1))
+        .map(|order| {
+            let residual = fixed_residual(samples, order);
+            let (size, partition_order, parameters) =
+                rice_partitions(&residual, order, samples.len());
+            let size = size + order as u64 * bits as u64;
+            (size, order, residual, partition_order, parameters)
+        })
+        .min_by_key(|(size, ..)| *size);
+
+    match best {
+        Some((size, order, residual, partition_order, parameters)) if size < verbatim => {
+            out.write(0, 1);
+            out.write(0b001000 | order as u64, 6);
+            out.write(0, 1);
+            for &sample in &samples[..order] {
+                out.write_signed(sample, bits);
+            }
+
+            // partitioned rice with 4-bit parameters
+            out.write(0b00, 2);
+            out.write(partition_order as u64, 4);
+            let partition = samples.len() >> partition_order;
+            let mut start = 0;
+            for (i, &parameter) in parameters.iter().enumerate() {
+                let end = (i + 1) * partition - order;
+                out.write(parameter as u64, 4);
+                for &value in &residual[start..end] {
+                    out.write_rice(zigzag(value), parameter);
+                }
+                start = end;
+            }
+        }
+        _ => {
+            out.write(0, 1);
+            out.write(0b000001, 6);
+            out.write(0, 1);
+            for &sample in samples {
+                out.write_signed(sample, bits);
+            }
+        }
+    }
+}
+
+/// What is left of `samples` after the fixed predictor of `order`, without
+/// the first `order` warm-up samples.
+fn fixed_residual(samples: &[i64], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let s = |back: usize| samples[i - back];
+            match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            }
+        })
+        .collect()
+}
+
+/// The partition order and rice parameters that code `residual` in the
+/// fewest bits, with that count.
+fn rice_partitions(residual: &[i64], order: usize, block: usize) -> (u64, u32, Vec<u32>) {
+    let values: Vec<u64> = residual.iter().map(|&value| zigzag(value)).collect();
+    let mut best: Option<(u64, u32, Vec<u32>)> = None;
+
+    for partition_order in 0..=MAX_PARTITION_ORDER {
+        let partition = block >> partition_order;
+        if !block.is_multiple_of(1 << partition_order) || partition <= order {
+            break;
+        }
+
+        let mut size = 0;
+        let mut parameters = Vec::with_capacity(1 << partition_order);
+        let mut start = 0;
+        for i in 0..1usize << partition_order {
+            let end = (i + 1) * partition - order;
+            let (bits, parameter) = best_parameter(&values[start..end]);
+            size += 4 + bits;
+            parameters.push(parameter);
+            start = end;
+        }
+
+        if best
+            .as_ref()
+            .is_none_or(|(best_size, ..)| size < *best_size)
+        {
+            best = Some((size, partition_order, parameters));
+        }
+    }
+
+    best.unwrap_or((u64::MAX, 0, vec![0]))
+}
+
+/// The rice parameter that codes `values` in the fewest bits, with that count.
+fn best_parameter(values: &[u64]) -> (u64, u32) {
+    let sum: u64 = values.iter().sum();
+    let mean = sum / values.len().max(1) as u64;
+    // the optimum sits next to log2 of the mean
+    let guess = (64 - mean.leading_zeros()).min(MAX_RICE_PARAMETER);
+
+    (guess.saturating_sub(1)..=(guess + 1).min(MAX_RICE_PARAMETER))
+        .map(|parameter| {
+            let bits: u64 = values
+                .iter()
+                .map(|&value| (value >> parameter) + 1 + parameter as u64)
+                .sum();
+            (bits, parameter)
+        })
+        .min_by_key(|(bits, _)| *bits)
+        .unwrap_or((0, 0))
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn write_metadata_block(
+    out: &mut impl Write,
+    kind: u8,
+    is_last: bool,
+    body: &[u8],
+) -> io::Result<()> {
+    let length = (body.len() as u32).to_be_bytes();
+    out.write_all(&[
+        ((is_last as u8) << 7) | kind,
+        length[1],
+        length[2],
+        length[3],
+    ])?;
+    out.write_all(body)
+}
+
+/// A VORBIS_COMMENT block body, little endian unlike the rest of FLAC.
+fn vorbis_comment(tags: &[(String, String)]) -> Vec<u8> {
+    let vendor = concat!("echo-tui ", env!("CARGO_PKG_VERSION"));
+    let mut body = Vec::new();
+    body.extend((vendor.len() as u32).to_le_bytes());
+    body.extend(vendor.as_bytes());
+    body.extend((tags.len() as u32).to_le_bytes());
+    for (key, value) in tags {
+        let comment = format!("{}={}", key, value);
+        body.extend((comment.len() as u32).to_le_bytes());
+        body.extend(comment.as_bytes());
+    }
+    body
+}
+
+/// The frame number in the UTF-8 like coding of frame headers.
+fn write_utf8_number(out: &mut BitWriter, number: u64) {
+    if number < 0x80 {
+        out.write(number, 8);
+        return;
+    }
+
+    // continuation bytes carry six bits each
+    let mut continuation = 1;
+    while number >> (6 * continuation) >= 1 << (6 - continuation) {
+        continuation += 1;
+    }
+    let lead_marker = (0xff00u64 >> (continuation + 1)) & 0xff;
+    out.write(lead_marker | (number >> (6 * continuation)), 8);
+    for i in (0..continuation).rev() {
+        out.write(0x80 | ((number >> (6 * i)) & 0x3f), 8);
+    }
+}
+
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |mut crc, &byte| {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0u16, |mut crc, &byte| {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+/// Packs values most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    // bits not yet in `bytes`, right aligned
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u32) {
+        for chunk in (0..bits).step_by(32).rev() {
+            let width = (bits - chunk).min(32);
+            let part = (value >> chunk) & ((1u64 << width) - 1);
+            self.buffer = (self.buffer << width) | part;
+            self.count += width;
+            while self.count >= 8 {
+                self.count -= 8;
+                self.bytes.push((self.buffer >> self.count) as u8);
+            }
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64 & ((1u64 << bits) - 1), bits);
+    }
+
+    /// `value >> parameter` zeros and a one, then the low `parameter` bits.
+    fn write_rice(&mut self, value: u64, parameter: u32) {
+        let mut quotient = value >> parameter;
+        while quotient >= 32 {
+            self.write(0, 32);
+            quotient -= 32;
+        }
+        self.write(1, quotient as u32 + 1);
+        if parameter > 0 {
+            self.write(value & ((1 << parameter) - 1), parameter);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.write(0, 8 - self.count);
+        }
+    }
+
+    /// Everything written so far, when it ends on a byte boundary.
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn into_bytes(mut self) -> Vec<u8> {
+        self.align();
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    const RATE: u32 = 44_100;
+
+    // a sine on the left and noise on the right, neither a whole number of
+    // blocks long
+    fn signal(frames: usize, bits: u16) -> Vec<i32> {
+        let peak = ((1i64 << (bits - 1)) - 1) as f64;
+        let mut seed = 0x2545_f491u32;
+        (0..frames)
+            .flat_map(|i| {
+                let phase = i as f64 * 441.0 * std::f64::consts::TAU / RATE as f64;
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let noise = (seed as f64 / u32::MAX as f64 * 2.0 - 1.0) * peak;
+                [(phase.sin() * peak * 0.8) as i32, noise as i32]
+            })
+            .collect()
+    }
+
+    fn round_trip(name: &str, bits: u16) {
+        let dir = TempDir::new(name);
+        let path = dir.join("round_trip.flac");
+        let frames = BLOCK_SIZE * 3 + 1_234;
+        let samples = signal(frames, bits);
+        let mut writer = FlacWriter::create(&path, RATE, 2, bits, &[]).unwrap();
+        // odd sized writes, so blocks are cut across calls
+        for chunk in samples.chunks(1_000) {
+            writer.write(chunk).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        // STREAMINFO: 4 bytes of magic, 4 of block header, MD5 last
+        let md5 = &bytes[8 + 18..8 + 34];
+        assert!(md5.iter().all(|&b| b == 0), "MD5 is left unset");
+
+        let mss = MediaSourceStream::new(Box::new(File::open(&path).unwrap()), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let mut format = symphonia::default::get_probe()
+            .format(
+                &hint,
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .unwrap()
+            .format;
+        let params = format.default_track().unwrap().codec_params.clone();
+        assert_eq!(params.n_frames, Some(frames as u64));
+        assert_eq!(params.sample_rate, Some(RATE));
+        assert_eq!(params.bits_per_sample, Some(bits as u32));
+        assert_eq!(params.channels.map(|c| c.count()), Some(2));
+
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions { verify: true })
+            .unwrap();
+        let mut decoded = Vec::with_capacity(samples.len());
+        while let Ok(packet) = format.next_packet() {
+            let audio = decoder.decode(&packet).unwrap();
+            let mut buffer = SampleBuffer::<i32>::new(audio.capacity() as u64, *audio.spec());
+            buffer.copy_interleaved_ref(audio);
+            // the decoder hands samples out scaled to the full i32 range
+            decoded.extend(buffer.samples().iter().map(|s| s >> (32 - bits)));
+        }
+
+        assert_eq!(decoded.len(), samples.len());
+        assert!(decoded == samples, "decoded samples differ");
+    }
+
+    #[test]
+    fn sixteen_bit_round_trips() {
+        round_trip("echo_flac_16", 16);
+    }
+
+    #[test]
+    fn twenty_four_bit_round_trips() {
+        round_trip("echo_flac_24", 24);
+    }
+}
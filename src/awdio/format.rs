@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use strum::Display;
+use symphonia::core::codecs::{self, CodecType};
+use symphonia::core::io::MediaSourceStream;
+
+/// What a file turned out to hold once its content was probed, whatever its
+/// extension says.
+#[derive(Debug, Clone, Copy, PartialEq, Display)]
+pub enum AudioFormat {
+    #[strum(to_string = "MP3")]
+    Mp3,
+    #[strum(to_string = "FLAC")]
+    Flac,
+    #[strum(to_string = "Ogg Vorbis")]
+    Vorbis,
+    #[strum(to_string = "Opus")]
+    Opus,
+    #[strum(to_string = "WAV")]
+    Wav,
+    #[strum(to_string = "AIFF")]
+    Aiff,
+    #[strum(to_string = "M4A")]
+    M4a,
+}
+
+impl AudioFormat {
+    /// The usual extension, for files that come without one.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mp3 => "mp3",
+            Self::Flac => "flac",
+            Self::Vorbis => "ogg",
+            Self::Opus => "opus",
+            Self::Wav => "wav",
+            Self::Aiff => "aiff",
+            Self::M4a => "m4a",
+        }
+    }
+
+    /// Probe the content of `path` and make sure there is a decoder for its
+    /// first audio track.
+    pub fn probe(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+
+        let probed = symphonia::default::get_probe()
+            .format(
+                &Default::default(),
+                mss,
+                &Default::default(),
+                &Default::default(),
+            )
+            .map_err(|_| "Not an audio file".to_string())?;
+
+        let codec = probed
+            .format
+            .tracks()
+            .iter()
+            .map(|track| track.codec_params.codec)
+            .find(|&codec| codec != codecs::CODEC_TYPE_NULL)
+            .ok_or("No audio track found")?;
+
+        let format = Self::from_codec(codec, &magic(path))
+            .ok_or_else(|| format!("Unsupported codec {}", codec_name(codec)))?;
+        if symphonia::default::get_codecs().get_codec(codec).is_none() {
+            return Err(format!("No decoder for {}", format));
+        }
+        Ok(format)
+    }
+
+    /// Compressed codecs tell the format apart, PCM needs the container.
+    fn from_codec(codec: CodecType, magic: &[u8]) -> Option<Self> {
+        Some(match codec {
+            codecs::CODEC_TYPE_MP1 | codecs::CODEC_TYPE_MP2 | codecs::CODEC_TYPE_MP3 => Self::Mp3,
+            codecs::CODEC_TYPE_FLAC => Self::Flac,
+            codecs::CODEC_TYPE_VORBIS => Self::Vorbis,
+            codecs::CODEC_TYPE_OPUS => Self::Opus,
+            codecs::CODEC_TYPE_AAC | codecs::CODEC_TYPE_ALAC => Self::M4a,
+            codec if is_pcm(codec) => {
+                if magic.starts_with(b"FORM") {
+                    Self::Aiff
+                } else {
+                    Self::Wav
+                }
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// `{id}.ext` with the extension the file came with, or the usual one of
+/// `format` when it had none.
+pub fn library_file_name(id: i64, original: &Path, format: AudioFormat) -> String {
+    let extension = original
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| !extension.is_empty())
+        .map(str::to_lowercase)
+        .unwrap_or_else(|| format.extension().to_string());
+    format!("{}.{}", id, extension)
+}
+
+// plain and ADPCM samples, the two containers that carry them are told
+// apart by signature
+fn is_pcm(codec: CodecType) -> bool {
+    let name = codec_name(codec);
+    name.starts_with("pcm") || name.starts_with("adpcm")
+}
+
+fn codec_name(codec: CodecType) -> String {
+    symphonia::default::get_codecs()
+        .get_codec(codec)
+        .map(|descriptor| descriptor.short_name.to_string())
+        .unwrap_or_else(|| codec.to_string())
+}
+
+// the first bytes of the file, enough for a container signature
+fn magic(path: &Path) -> Vec<u8> {
+    let mut magic = Vec::with_capacity(12);
+    if let Ok(file) = File::open(path) {
+        let _ = file.take(12).read_to_end(&mut magic);
+    }
+    magic
+}
@@ -0,0 +1,504 @@
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
+
+use super::DecodeSource;
+use super::eq::Biquad;
+
+// ReplayGain 2.0 plays everything at -18 LUFS
+const REFERENCE_LUFS: f64 = -18.0;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+// albums are measured over a histogram of the blocks of their tracks, in
+// 0.1 LU steps from the absolute gate up to +5 LUFS
+const HISTOGRAM_STEP_LU: f64 = 0.1;
+const HISTOGRAM_BINS: usize = 750;
+// the limiter keeps a little room under full scale
+const LIMITER_CEILING: f32 = 0.98;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayGainMode {
+    #[default]
+    Off,
+    Track,
+    Album,
+}
+
+/// Gains in dB and sample peaks as stored in the `songs` table.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    /// The linear factor to play a track with. Album mode falls back to the
+    /// track gain, and the gain never pushes the known peak past full scale.
+    pub fn linear(&self, mode: ReplayGainMode, preamp_db: f32) -> f32 {
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => (self.track_gain, self.track_peak),
+            ReplayGainMode::Album => match self.album_gain {
+                Some(gain) => (Some(gain), self.album_peak),
+                None => (self.track_gain, self.track_peak),
+            },
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let linear = 10f32.powf((gain + preamp_db) / 20.0);
+        match peak {
+            Some(peak) if peak > 0.0 => linear.min(1.0 / peak),
+            _ => linear,
+        }
+    }
+}
+
+/// What import learned about the loudness of one file.
+pub struct Analysis {
+    pub gain: ReplayGain,
+    // how many gating blocks fell in each step of the histogram, empty when
+    // read from tags
+    histogram: Vec<u32>,
+}
+
+impl Analysis {
+    /// A scan stored by an earlier import, to measure its album with.
+    pub fn stored(gain: ReplayGain, histogram: &[u8]) -> Self {
+        Self {
+            gain,
+            histogram: histogram
+                .chunks_exact(4)
+                .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect(),
+        }
+    }
+
+    /// The histogram the way the `songs` table keeps it, `None` for tracks
+    /// that came with tags.
+    pub fn histogram_bytes(&self) -> Option<Vec<u8>> {
+        (!self.histogram.is_empty()).then(|| {
+            self.histogram
+                .iter()
+                .flat_map(|n| n.to_le_bytes())
+                .collect()
+        })
+    }
+}
+
+/// Read the ReplayGain tags of a file, or measure it with an EBU R128 scan
+/// when it has none.
+pub fn analyze(path: &str) -> Option<Analysis> {
+    if let Some(gain) = read_tags(path) {
+        return Some(Analysis {
+            gain,
+            histogram: Vec::new(),
+        });
+    }
+
+    let (blocks, peak) = scan(path)?;
+    let loudness = integrated_loudness(&blocks)?;
+
+    Some(Analysis {
+        gain: ReplayGain {
+            track_gain: Some((REFERENCE_LUFS - loudness) as f32),
+            track_peak: Some(peak),
+            ..Default::default()
+        },
+        histogram: histogram(&blocks),
+    })
+}
+
+/// Album gain and peak over every scanned track of one album. Tracks that
+/// came with tags carry no histogram and are left out.
+pub fn album_gain(tracks: &[&Analysis]) -> Option<(f32, f32)> {
+    let mut histogram = vec![0u64; HISTOGRAM_BINS];
+    for track in tracks {
+        for (total, count) in histogram.iter_mut().zip(&track.histogram) {
+            *total += *count as u64;
+        }
+    }
+    let loudness = histogram_loudness(&histogram)?;
+
+    let peak = tracks
+        .iter()
+        .filter(|track| !track.histogram.is_empty())
+        .filter_map(|track| track.gain.track_peak)
+        .fold(0.0, f32::max);
+
+    Some(((REFERENCE_LUFS - loudness) as f32, peak))
+}
+
+fn read_tags(path: &str) -> Option<ReplayGain> {
+    let file = std::fs::File::open(path).ok()?;
+    let mss = symphonia::core::io::MediaSourceStream::new(Box::new(file), Default::default());
+    let mut probed = symphonia::default::get_probe()
+        .format(
+            &Default::default(),
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )
+        .ok()?;
+
+    let mut gain = ReplayGain::default();
+    // tags in front of the container (id3v2), then the container's own
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        read_revision(revision, &mut gain);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        read_revision(revision, &mut gain);
+    }
+
+    gain.track_gain.is_some().then_some(gain)
+}
+
+fn read_revision(revision: &MetadataRevision, gain: &mut ReplayGain) {
+    for tag in revision.tags() {
+        let value = tag.value.to_string();
+        let value = value.trim().trim_end_matches("dB").trim();
+        let Ok(value) = value.parse::<f32>() else {
+            continue;
+        };
+
+        match tag.std_key {
+            Some(StandardTagKey::ReplayGainTrackGain) => gain.track_gain = Some(value),
+            Some(StandardTagKey::ReplayGainTrackPeak) => gain.track_peak = Some(value),
+            Some(StandardTagKey::ReplayGainAlbumGain) => gain.album_gain = Some(value),
+            Some(StandardTagKey::ReplayGainAlbumPeak) => gain.album_peak = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// Decode a whole file through the K-weighting filter. Returns the power of
+/// every gating block and the sample peak.
+fn scan(path: &str) -> Option<(Vec<f64>, f32)> {
+    let mut source = DecodeSource::open(path).ok()?;
+    let mut meter = Meter::new(source.channels.max(1) as usize, source.sample_rate as f64);
+    while let Some(samples) = source.next_samples() {
+        meter.push(&samples);
+    }
+    Some(meter.finish())
+}
+
+/// Sums the K-weighted power of interleaved samples in 100ms steps.
+struct Meter {
+    channels: usize,
+    filters: Vec<KWeighting>,
+    weights: Vec<f64>,
+    step_frames: usize,
+    steps: Vec<f64>,
+    step_sum: f64,
+    step_len: usize,
+    peak: f32,
+}
+
+impl Meter {
+    fn new(channels: usize, sample_rate: f64) -> Self {
+        Self {
+            channels,
+            filters: (0..channels)
+                .map(|_| KWeighting::new(sample_rate))
+                .collect(),
+            weights: (0..channels)
+                .map(|channel| channel_weight(channel, channels))
+                .collect(),
+            // four steps make one 400ms block
+            step_frames: (sample_rate / 10.0).round() as usize,
+            steps: Vec::new(),
+            step_sum: 0.0,
+            step_len: 0,
+            peak: 0.0,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.peak = self.peak.max(sample.abs());
+                let weighted = self.filters[channel].process(*sample as f64);
+                self.step_sum += self.weights[channel] * weighted * weighted;
+            }
+
+            self.step_len += 1;
+            if self.step_len == self.step_frames {
+                self.steps.push(self.step_sum);
+                self.step_sum = 0.0;
+                self.step_len = 0;
+            }
+        }
+    }
+
+    /// The power of every gating block and the sample peak.
+    fn finish(self) -> (Vec<f64>, f32) {
+        let block_frames = (self.step_frames * 4) as f64;
+        let blocks = self
+            .steps
+            .windows(4)
+            .map(|window| window.iter().sum::<f64>() / block_frames)
+            .collect();
+        (blocks, self.peak)
+    }
+}
+
+/// Gated loudness of a set of blocks, as in ITU-R BS.1770.
+fn integrated_loudness(blocks: &[f64]) -> Option<f64> {
+    let above_absolute: Vec<f64> = blocks
+        .iter()
+        .copied()
+        .filter(|power| lufs(*power) > ABSOLUTE_GATE_LUFS)
+        .collect();
+    if above_absolute.is_empty() {
+        return None;
+    }
+
+    let relative_gate = lufs(mean(&above_absolute)) + RELATIVE_GATE_LU;
+    let gated: Vec<f64> = above_absolute
+        .into_iter()
+        .filter(|power| lufs(*power) > relative_gate)
+        .collect();
+    if gated.is_empty() {
+        return None;
+    }
+
+    Some(lufs(mean(&gated)))
+}
+
+/// How many blocks fall in each step above the absolute gate.
+fn histogram(blocks: &[f64]) -> Vec<u32> {
+    let mut histogram = vec![0; HISTOGRAM_BINS];
+    for power in blocks {
+        let loudness = lufs(*power);
+        if loudness > ABSOLUTE_GATE_LUFS {
+            let bin = ((loudness - ABSOLUTE_GATE_LUFS) / HISTOGRAM_STEP_LU) as usize;
+            histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        }
+    }
+    histogram
+}
+
+/// Gated loudness of a histogram, every block taken at the middle of its
+/// step. Off from the exact blocks by a fraction of a step at most.
+fn histogram_loudness(histogram: &[u64]) -> Option<f64> {
+    let step_lufs = |bin: usize| ABSOLUTE_GATE_LUFS + (bin as f64 + 0.5) * HISTOGRAM_STEP_LU;
+    let mean_above = |gate: f64| {
+        let (sum, count) = histogram
+            .iter()
+            .enumerate()
+            .filter(|(bin, _)| step_lufs(*bin) > gate)
+            .fold((0.0, 0), |(sum, count), (bin, n)| {
+                (sum + power(step_lufs(bin)) * *n as f64, count + n)
+            });
+        (count > 0).then(|| sum / count as f64)
+    };
+
+    let relative_gate = lufs(mean_above(ABSOLUTE_GATE_LUFS)?) + RELATIVE_GATE_LU;
+    mean_above(relative_gate).map(lufs)
+}
+
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.max(f64::MIN_POSITIVE).log10()
+}
+
+fn power(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Surrounds count a bit more, the LFE not at all. Channels follow the
+/// usual L, R, C, LFE, Ls, Rs order.
+fn channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channel, channels) {
+        (3, 6..) => 0.0,
+        (4 | 5, 6..) => 1.41,
+        _ => 1.0,
+    }
+}
+
+/// The BS.1770 pre-filter: a high shelf followed by a high pass, derived
+/// for any sample rate.
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let f0 = 1681.974450955533;
+        let gain_db = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        };
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+/// Applies the gain of a track on the decode thread. The limiter grabs any
+/// frame that would go over the ceiling at once and lets go over ~50ms.
+pub struct GainStage {
+    pub gain: f32,
+    envelope: f32,
+    release: f32,
+}
+
+impl GainStage {
+    pub fn new(gain: f32, sample_rate: u32) -> Self {
+        let release_frames = sample_rate.max(1) as f32 * 0.05;
+        Self {
+            gain,
+            envelope: 1.0,
+            release: 1.0 - (-1.0 / release_frames).exp(),
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32], channels: usize) {
+        for frame in samples.chunks_mut(channels.max(1)) {
+            let peak = frame
+                .iter()
+                .fold(0.0f32, |peak, sample| peak.max((sample * self.gain).abs()));
+
+            let wanted = if peak > LIMITER_CEILING {
+                LIMITER_CEILING / peak
+            } else {
+                1.0
+            };
+            self.envelope = if wanted < self.envelope {
+                wanted
+            } else {
+                self.envelope + (wanted - self.envelope) * self.release
+            };
+
+            let gain = self.gain * self.envelope;
+            for sample in frame.iter_mut() {
+                // the envelope is exact up to rounding, which can still land
+                // a hair over
+                *sample = (*sample * gain).clamp(-LIMITER_CEILING, LIMITER_CEILING);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 48_000.0;
+
+    // five seconds of a 997 Hz sine at -20 dBFS on the channels `on` marks
+    fn sine_loudness(on: &[bool]) -> f64 {
+        let amplitude = 10f64.powf(-20.0 / 20.0);
+        let mut meter = Meter::new(on.len(), RATE);
+        let samples: Vec<f32> = (0..RATE as usize * 5)
+            .flat_map(|i| {
+                let sample = (i as f64 * 997.0 * 2.0 * PI / RATE).sin() * amplitude;
+                on.iter()
+                    .map(move |&on| if on { sample as f32 } else { 0.0 })
+            })
+            .collect();
+        meter.push(&samples);
+        integrated_loudness(&meter.finish().0).unwrap()
+    }
+
+    #[test]
+    fn sine_at_minus_20_dbfs_measures_minus_23_lufs() {
+        for on in [[true, false], [false, true]] {
+            let loudness = sine_loudness(&on);
+            assert!((loudness + 23.0).abs() < 0.1, "{:?}: {} LUFS", on, loudness);
+        }
+        // the power of both channels adds up
+        let both = sine_loudness(&[true, true]);
+        assert!((both + 20.0).abs() < 0.1, "both: {} LUFS", both);
+    }
+
+    #[test]
+    fn silence_has_no_loudness() {
+        let mut meter = Meter::new(2, RATE);
+        meter.push(&vec![0.0; RATE as usize * 2 * 2]);
+        assert_eq!(integrated_loudness(&meter.finish().0), None);
+    }
+
+    #[test]
+    fn histogram_measures_like_the_blocks() {
+        let amplitude = 10f64.powf(-20.0 / 20.0);
+        let mut meter = Meter::new(1, RATE);
+        // a loud and a quiet half, so the relative gate has work to do
+        let samples: Vec<f32> = (0..RATE as usize * 10)
+            .map(|i| {
+                let level = if i < RATE as usize * 5 { 1.0 } else { 0.2 };
+                ((i as f64 * 997.0 * 2.0 * PI / RATE).sin() * amplitude * level) as f32
+            })
+            .collect();
+        meter.push(&samples);
+        let (blocks, _) = meter.finish();
+
+        let exact = integrated_loudness(&blocks).unwrap();
+        let counts: Vec<u64> = histogram(&blocks).into_iter().map(u64::from).collect();
+        let binned = histogram_loudness(&counts).unwrap();
+        assert!(
+            (exact - binned).abs() < HISTOGRAM_STEP_LU,
+            "{} vs {}",
+            exact,
+            binned
+        );
+    }
+
+    #[test]
+    fn limiter_stays_under_its_ceiling() {
+        let mut stage = GainStage::new(8.0, RATE as u32);
+        let mut seed = 0x9e37_79b9u32;
+        // loud and quiet stretches, so the limiter lets go in between
+        let mut samples: Vec<f32> = (0..RATE as usize * 4)
+            .map(|i| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                let level = if (i / 12_000) % 2 == 0 { 1.0 } else { 0.01 };
+                (seed as f32 / u32::MAX as f32 * 2.0 - 1.0) * level
+            })
+            .collect();
+
+        for chunk in samples.chunks_mut(1_023 * 2) {
+            stage.process(chunk, 2);
+        }
+
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak <= LIMITER_CEILING, "peak {}", peak);
+        assert!(peak > LIMITER_CEILING * 0.9, "peak {}", peak);
+    }
+}
@@ -1,4 +1,9 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
 use audiotags::Tag;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataRevision, StandardTagKey};
 
 #[derive(Debug, Default, Clone, serde::Serialize)]
 pub struct Metadata {
@@ -44,8 +49,14 @@ impl Metadata {
         }
     }
 
+    /// Tags of the file at `path`. audiotags reads ID3, FLAC and MP4 tags by
+    /// extension, everything else, or a file named after the wrong format, is
+    /// read through symphonia.
     pub fn from_path(path: &str) -> Result<Metadata, audiotags::Error> {
-        let tag = Tag::new().read_from_path(path)?;
+        let tag = match Tag::new().read_from_path(path) {
+            Ok(tag) => tag,
+            Err(e) => return Self::from_symphonia(path).ok_or(e),
+        };
 
         Ok(Metadata {
             title: tag.title().unwrap_or("Unknown").to_string(),
@@ -62,6 +73,118 @@ impl Metadata {
         })
     }
 
+    /// Vorbis comments, RIFF INFO chunks and ID3 tags in front of any
+    /// container, as symphonia finds them. `None` when the content isn't audio.
+    fn from_symphonia(path: &str) -> Option<Metadata> {
+        let file = std::fs::File::open(path).ok()?;
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let mut probed = symphonia::default::get_probe()
+            .format(
+                &Default::default(),
+                mss,
+                &Default::default(),
+                &Default::default(),
+            )
+            .ok()?;
+
+        let mut metadata = Metadata {
+            title: "Unknown".into(),
+            artist: "Unknown".into(),
+            album: "Unknown".into(),
+            genre: "Unknown".into(),
+            album_artist: "Unknown".into(),
+            ..Default::default()
+        };
+        // tags in front of the container first, the container's own win
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            metadata.apply(revision);
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            metadata.apply(revision);
+        }
+        // symphonia stops at the samples, INFO chunks often come after them
+        if let Ok(info) = read_riff_info(path) {
+            metadata.apply_riff_info(&info);
+        }
+        Some(metadata)
+    }
+
+    fn apply_riff_info(&mut self, info: &[([u8; 4], String)]) {
+        for (id, value) in info {
+            match id {
+                b"INAM" => self.title = value.clone(),
+                b"IART" => self.artist = value.clone(),
+                b"IPRD" => self.album = value.clone(),
+                b"IGNR" => self.genre = value.clone(),
+                b"ICRD" => {
+                    if let Some(year) = value.get(..4).and_then(|year| year.parse().ok()) {
+                        self.year = year;
+                    }
+                }
+                b"ITRK" | b"IPRT" => self.track_number = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+    }
+
+    fn apply(&mut self, revision: &MetadataRevision) {
+        // "3/12" style numbers carry the total along
+        let number = |value: &str| -> (u32, u32) {
+            let mut parts = value
+                .split('/')
+                .map(|part| part.trim().parse().unwrap_or(0));
+            (
+                parts.next().unwrap_or_default(),
+                parts.next().unwrap_or_default(),
+            )
+        };
+
+        for tag in revision.tags() {
+            let Some(key) = tag.std_key else {
+                continue;
+            };
+            let value = tag.value.to_string();
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            match key {
+                StandardTagKey::TrackTitle => self.title = value.into(),
+                StandardTagKey::Artist => self.artist = value.into(),
+                StandardTagKey::Album => self.album = value.into(),
+                StandardTagKey::AlbumArtist => self.album_artist = value.into(),
+                StandardTagKey::Genre => self.genre = value.into(),
+                StandardTagKey::Date | StandardTagKey::OriginalDate => {
+                    if let Some(year) = value.get(..4).and_then(|year| year.parse().ok()) {
+                        self.year = year;
+                    }
+                }
+                StandardTagKey::TrackNumber => {
+                    let (track, total) = number(value);
+                    self.track_number = track;
+                    if total > 0 {
+                        self.total_tracks = total;
+                    }
+                }
+                StandardTagKey::TrackTotal => self.total_tracks = number(value).0,
+                StandardTagKey::DiscNumber => {
+                    let (disc, total) = number(value);
+                    self.disc_number = disc;
+                    if total > 0 {
+                        self.total_discs = total;
+                    }
+                }
+                StandardTagKey::DiscTotal => self.total_discs = number(value).0,
+                _ => {}
+            }
+        }
+
+        if !revision.visuals().is_empty() {
+            self.cover = Some("OK".into());
+        }
+    }
+
     pub fn update_file(&self, path: &str) -> Result<(), audiotags::Error> {
         let mut tag = Tag::default().read_from_path(path)?;
 
@@ -88,3 +211,51 @@ impl Metadata {
         Ok(())
     }
 }
+
+/// The fields of every `LIST`/`INFO` chunk of a WAV file, wherever they sit.
+/// Nothing for files that aren't RIFF WAVE.
+fn read_riff_info(path: &str) -> std::io::Result<Vec<([u8; 4], String)>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut header = [0u8; 12];
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Ok(Vec::new());
+    }
+
+    let mut fields = Vec::new();
+    let mut chunk = [0u8; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        // chunks are padded to an even length
+        let padded = len + len % 2;
+        if &chunk[..4] != b"LIST" {
+            file.seek(SeekFrom::Current(padded as i64))?;
+            continue;
+        }
+
+        let mut list = vec![0u8; len as usize];
+        file.read_exact(&mut list)?;
+        file.seek(SeekFrom::Current((padded - len) as i64))?;
+        if !list.starts_with(b"INFO") {
+            continue;
+        }
+
+        let mut rest = &list[4..];
+        while rest.len() >= 8 {
+            let id = [rest[0], rest[1], rest[2], rest[3]];
+            let len = u32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            let Some(text) = rest.get(8..8 + len) else {
+                break;
+            };
+            let value = String::from_utf8_lossy(text)
+                .trim_end_matches('\0')
+                .trim()
+                .to_string();
+            if !value.is_empty() {
+                fields.push((id, value));
+            }
+            rest = rest.get(8 + len + len % 2..).unwrap_or_default();
+        }
+    }
+    Ok(fields)
+}
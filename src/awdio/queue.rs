@@ -0,0 +1,60 @@
+use crate::awdio::song::Song;
+
+#[derive(Debug, Default)]
+pub struct PlayQueue {
+    pub songs: Vec<Song>,
+    pub position: Option<usize>,
+    pub origin: String,
+}
+
+impl PlayQueue {
+    /// Replace the queue with `songs` and start from `start`.
+    pub fn load(&mut self, songs: Vec<Song>, start: usize, origin: impl Into<String>) {
+        self.position = if songs.is_empty() {
+            None
+        } else {
+            Some(start.min(songs.len() - 1))
+        };
+        self.songs = songs;
+        self.origin = origin.into();
+    }
+
+    pub fn clear(&mut self) {
+        self.songs.clear();
+        self.position = None;
+        self.origin.clear();
+    }
+
+    pub fn current(&self) -> Option<&Song> {
+        self.position.and_then(|pos| self.songs.get(pos))
+    }
+
+    pub fn peek_next(&self) -> Option<&Song> {
+        self.position.and_then(|pos| self.songs.get(pos + 1))
+    }
+
+    /// Move to the next song, returns `None` once the end of the queue is reached.
+    pub fn advance(&mut self) -> Option<&Song> {
+        let next = self.position? + 1;
+        if next >= self.songs.len() {
+            return None;
+        }
+        self.position = Some(next);
+        self.songs.get(next)
+    }
+
+    /// Move to the previous song, stays on the first one.
+    pub fn retreat(&mut self) -> Option<&Song> {
+        let prev = self.position?.saturating_sub(1);
+        self.position = Some(prev);
+        self.songs.get(prev)
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+}
@@ -0,0 +1,62 @@
+/// Milliseconds a transport action fades over unless configured otherwise.
+pub const DEFAULT_RAMP_MS: u32 = 10;
+
+/// The gain the output callback plays at. It moves towards its target one
+/// step per frame, so pausing, seeking or loading a file never cuts into a
+/// waveform and clicks.
+pub struct Ramp {
+    gain: f32,
+    // gain change per frame, infinite when ramps are turned off
+    step: f32,
+}
+
+impl Ramp {
+    /// Starts silent, the first samples fade in.
+    pub fn new(frames: u32) -> Self {
+        let mut ramp = Self {
+            gain: 0.0,
+            step: f32::INFINITY,
+        };
+        ramp.set_frames(frames);
+        ramp
+    }
+
+    pub fn set_frames(&mut self, frames: u32) {
+        self.step = if frames == 0 {
+            f32::INFINITY
+        } else {
+            1.0 / frames as f32
+        };
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.gain == 0.0
+    }
+
+    /// Drop to silence at once, for when there is nothing left to fade.
+    pub fn silence(&mut self) {
+        self.gain = 0.0;
+    }
+
+    /// Frames it takes to fade all the way out from here.
+    pub fn frames_to_silence(&self) -> usize {
+        (self.gain / self.step).ceil() as usize
+    }
+
+    /// Scale interleaved `samples` while moving the gain towards `target`.
+    pub fn apply(&mut self, samples: &mut [f32], channels: usize, target: f32) {
+        for frame in samples.chunks_mut(channels.max(1)) {
+            if self.gain < target {
+                self.gain = (self.gain + self.step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - self.step).max(target);
+            }
+            if self.gain == 1.0 {
+                continue;
+            }
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+    }
+}
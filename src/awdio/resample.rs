@@ -0,0 +1,191 @@
+use rubato::{
+    Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
+};
+
+const CHUNK_FRAMES: usize = 1024;
+
+/// Turns decoded samples from the layout of a file into the layout of the
+/// output stream: channels are up/down-mixed first, then resampled.
+pub struct Converter {
+    in_channels: usize,
+    out_channels: usize,
+    matrix: Vec<Vec<f32>>,
+    resampler: Option<SincFixedIn<f32>>,
+    // per-channel input waiting for a full resampler chunk
+    pending: Vec<Vec<f32>>,
+}
+
+impl Converter {
+    pub fn new(in_rate: u32, in_channels: u16, out_rate: u32, out_channels: u16) -> Self {
+        let in_channels = in_channels.max(1) as usize;
+        let out_channels = out_channels.max(1) as usize;
+
+        let resampler = if in_rate == out_rate || in_rate == 0 || out_rate == 0 {
+            None
+        } else {
+            let parameters = SincInterpolationParameters {
+                sinc_len: 128,
+                f_cutoff: 0.95,
+                oversampling_factor: 128,
+                interpolation: SincInterpolationType::Linear,
+                window: WindowFunction::BlackmanHarris2,
+            };
+            SincFixedIn::new(
+                out_rate as f64 / in_rate as f64,
+                1.0,
+                parameters,
+                CHUNK_FRAMES,
+                out_channels,
+            )
+            .ok()
+        };
+
+        Self {
+            in_channels,
+            out_channels,
+            matrix: mix_matrix(in_channels, out_channels),
+            resampler,
+            pending: vec![Vec::new(); out_channels],
+        }
+    }
+
+    /// Convert one decoded packet, may return fewer frames than it was given
+    /// while the resampler fills up.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mixed = self.remix(input);
+        if self.resampler.is_none() {
+            return mixed;
+        }
+
+        for frame in mixed.chunks(self.out_channels) {
+            for (channel, sample) in frame.iter().enumerate() {
+                self.pending[channel].push(*sample);
+            }
+        }
+
+        let mut output = Vec::new();
+        while self.pending[0].len() >= CHUNK_FRAMES {
+            let chunk: Vec<Vec<f32>> = self
+                .pending
+                .iter_mut()
+                .map(|channel| channel.drain(..CHUNK_FRAMES).collect())
+                .collect();
+            self.resample_into(&chunk, false, &mut output);
+        }
+
+        output
+    }
+
+    /// Push out whatever is still buffered, at the end of a file.
+    pub fn flush(&mut self) -> Vec<f32> {
+        let mut output = Vec::new();
+        if self.resampler.is_none() || self.pending[0].is_empty() {
+            return output;
+        }
+
+        let chunk = std::mem::replace(&mut self.pending, vec![Vec::new(); self.out_channels]);
+        self.resample_into(&chunk, true, &mut output);
+        output
+    }
+
+    /// Drop buffered input, after a seek.
+    pub fn reset(&mut self) {
+        for channel in self.pending.iter_mut() {
+            channel.clear();
+        }
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.reset();
+        }
+    }
+
+    fn resample_into(&mut self, chunk: &[Vec<f32>], is_partial: bool, output: &mut Vec<f32>) {
+        let Some(resampler) = self.resampler.as_mut() else {
+            return;
+        };
+
+        let result = if is_partial {
+            resampler.process_partial(Some(chunk), None)
+        } else {
+            resampler.process(chunk, None)
+        };
+
+        if let Ok(resampled) = result {
+            let frames = resampled.first().map(|c| c.len()).unwrap_or(0);
+            output.reserve(frames * self.out_channels);
+            for i in 0..frames {
+                for channel in resampled.iter() {
+                    output.push(channel[i]);
+                }
+            }
+        }
+    }
+
+    fn remix(&self, input: &[f32]) -> Vec<f32> {
+        if self.in_channels == self.out_channels {
+            return input.to_vec();
+        }
+
+        let mut output = Vec::with_capacity(input.len() / self.in_channels * self.out_channels);
+        for frame in input.chunks_exact(self.in_channels) {
+            for gains in self.matrix.iter() {
+                output.push(frame.iter().zip(gains).map(|(s, g)| s * g).sum());
+            }
+        }
+        output
+    }
+}
+
+/// Gain of every input channel for every output channel, `[out][in]`.
+/// Channels follow the usual L, R, C, LFE, Ls, Rs order.
+fn mix_matrix(in_channels: usize, out_channels: usize) -> Vec<Vec<f32>> {
+    const SIDE: f32 = std::f32::consts::FRAC_1_SQRT_2;
+    let mut matrix = vec![vec![0.0; in_channels]; out_channels];
+
+    match (in_channels, out_channels) {
+        // mono goes to every speaker
+        (1, _) => {
+            for gains in matrix.iter_mut() {
+                gains[0] = 1.0;
+            }
+        }
+        // everything folds into one
+        (_, 1) => {
+            for gain in matrix[0].iter_mut() {
+                *gain = 1.0 / in_channels as f32;
+            }
+        }
+        // surround to stereo: center and surrounds are shared, lfe is dropped
+        (i, 2) if i > 2 => {
+            let (left, right) = matrix.split_at_mut(1);
+            let gains = left[0].iter_mut().zip(right[0].iter_mut());
+            for (channel, (l, r)) in gains.enumerate() {
+                match channel {
+                    0 => *l = 1.0,
+                    1 => *r = 1.0,
+                    2 => {
+                        *l = SIDE;
+                        *r = SIDE;
+                    }
+                    3 => {}
+                    c if c % 2 == 0 => *l = SIDE,
+                    _ => *r = SIDE,
+                }
+            }
+            // keep the loudest sum within range
+            for gains in matrix.iter_mut() {
+                let total: f32 = gains.iter().sum();
+                if total > 1.0 {
+                    gains.iter_mut().for_each(|g| *g /= total);
+                }
+            }
+        }
+        // otherwise the shared channels map straight through
+        _ => {
+            for (channel, gains) in matrix.iter_mut().enumerate().take(in_channels) {
+                gains[channel] = 1.0;
+            }
+        }
+    }
+
+    matrix
+}
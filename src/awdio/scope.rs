@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+
+use super::spectrum::Spectrum;
+
+/// Frames the oscilloscope and the stereo scope look at.
+pub const SCOPE_FRAMES: usize = 2048;
+/// Spectra the waterfall keeps, one per analyzer update.
+pub const HISTORY_ROWS: usize = 128;
+
+// a VU meter integrates over ~300ms, the analyzer updates every ~30ms
+const VU_SMOOTHING: f32 = 0.1;
+// a PPM falls ~20 dB in 1.5s
+const PPM_FALL: f32 = 0.955;
+// updates a clip stays lit, about two seconds
+const CLIP_HOLD: u32 = 66;
+
+/// Levels of one channel, linear with 1.0 at full scale.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Meter {
+    pub rms: f32,
+    pub peak: f32,
+    // updates since the channel last hit full scale
+    pub clip_age: Option<u32>,
+}
+
+impl Meter {
+    pub fn is_clipping(&self) -> bool {
+        self.clip_age.is_some()
+    }
+
+    fn update(&mut self, samples: impl Iterator<Item = f32>) {
+        let (mut sum, mut count, mut peak) = (0.0, 0, 0.0f32);
+        for sample in samples {
+            sum += sample * sample;
+            count += 1;
+            peak = peak.max(sample.abs());
+        }
+        let rms = if count > 0 {
+            (sum / count as f32).sqrt()
+        } else {
+            0.0
+        };
+
+        self.rms += (rms - self.rms) * VU_SMOOTHING;
+        self.peak = peak.max(self.peak * PPM_FALL);
+        self.clip_age = if peak >= 1.0 {
+            Some(0)
+        } else {
+            self.clip_age
+                .map(|age| age + 1)
+                .filter(|age| *age < CLIP_HOLD)
+        };
+    }
+}
+
+/// Everything the visualizers draw, filled by the analyzer thread from what
+/// actually played.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub spectrum: Spectrum,
+    // the latest `SCOPE_FRAMES` frames, interleaved
+    pub samples: VecDeque<f32>,
+    pub channels: usize,
+    pub meters: Vec<Meter>,
+    // past spectrum bars, newest first
+    pub history: VecDeque<Vec<f32>>,
+}
+
+impl Scope {
+    /// Take in interleaved samples that just played.
+    pub fn push(&mut self, samples: &[f32], channels: usize) {
+        let channels = channels.max(1);
+        if channels != self.channels {
+            self.channels = channels;
+            self.samples.clear();
+            self.meters = vec![Meter::default(); channels];
+        }
+
+        self.samples.extend(samples);
+        let excess = self.samples.len().saturating_sub(SCOPE_FRAMES * channels);
+        self.samples.drain(..excess);
+
+        for (channel, meter) in self.meters.iter_mut().enumerate() {
+            meter.update(samples.iter().skip(channel).step_by(channels).copied());
+        }
+    }
+
+    /// Nothing played since the last update, the traces go flat and the
+    /// meters fall.
+    pub fn idle(&mut self) {
+        self.samples.clear();
+        for meter in self.meters.iter_mut() {
+            meter.update(std::iter::empty());
+        }
+    }
+
+    pub fn set_spectrum(&mut self, spectrum: Spectrum) {
+        self.history.push_front(spectrum.bars.clone());
+        self.history.truncate(HISTORY_ROWS);
+        self.spectrum = spectrum;
+    }
+
+    /// The mono sum of every frame, oldest first.
+    pub fn mono(&self) -> Vec<f32> {
+        let channels = self.channels.max(1);
+        self.samples
+            .iter()
+            .copied()
+            .collect::<Vec<f32>>()
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect()
+    }
+
+    /// Left and right of every frame, a mono track plays on both.
+    pub fn stereo(&self) -> Vec<(f32, f32)> {
+        let channels = self.channels.max(1);
+        self.samples
+            .iter()
+            .copied()
+            .collect::<Vec<f32>>()
+            .chunks_exact(channels)
+            .map(|frame| (frame[0], frame[channels.min(2) - 1]))
+            .collect()
+    }
+}
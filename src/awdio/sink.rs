@@ -0,0 +1,380 @@
+use std::any::Any;
+use std::fs::File;
+use std::io::BufWriter;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use cpal::traits::{DeviceTrait, StreamTrait};
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::awdio::device::{self, OutputTarget, SinkKind};
+
+// how much audio the null and WAV sinks take per period
+const PERIOD_MS: u64 = 10;
+// frames converted at a time for devices that don't take f32
+const SCRATCH_FRAMES: usize = 1024;
+
+/// Sample rate and channel count of an output stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StreamFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// Fills a buffer of interleaved samples, called from the thread the sink
+/// plays on.
+pub type Render = Box<dyn FnMut(&mut [f32]) + Send + 'static>;
+
+/// Keeps a started sink pulling samples until it is dropped.
+pub type OutputStream = Box<dyn Any>;
+
+/// Somewhere played samples go, a sound card or not.
+pub trait OutputSink {
+    /// Readable name for the header, like "host · device".
+    fn name(&self) -> String;
+
+    /// The format closest to `wanted` that this sink can play.
+    fn negotiate(&self, wanted: StreamFormat) -> StreamFormat;
+
+    /// Start pulling samples through `render`.
+    fn start(
+        &self,
+        format: StreamFormat,
+        render: Render,
+    ) -> Result<OutputStream, Box<dyn std::error::Error>>;
+}
+
+/// The sink `target` points at. Only the device sink needs a sound card.
+pub fn open(target: &OutputTarget) -> Result<Box<dyn OutputSink>, Box<dyn std::error::Error>> {
+    Ok(match target.sink {
+        SinkKind::Device => {
+            let (device, name) = device::resolve(target)?;
+            Box::new(CpalSink { device, name })
+        }
+        SinkKind::Null => Box::new(NullSink),
+        SinkKind::Wav => Box::new(WavSink {
+            path: target.wav_path(),
+        }),
+    })
+}
+
+pub struct CpalSink {
+    device: cpal::Device,
+    name: String,
+}
+
+impl CpalSink {
+    /// The supported config closest to `wanted`, with the sample format it
+    /// takes. Rate and layout count first, then how fine the format is.
+    fn pick(&self, wanted: StreamFormat) -> Option<(StreamFormat, cpal::SampleFormat)> {
+        let ranges = self.device.supported_output_configs().ok()?;
+
+        ranges
+            .filter_map(|range| {
+                let format_rank = format_rank(range.sample_format())?;
+                let rate = wanted
+                    .sample_rate
+                    .clamp(range.min_sample_rate().0, range.max_sample_rate().0);
+                // same layout first, stereo next, anything else last
+                let channel_rank = if range.channels() == wanted.channels {
+                    0
+                } else if range.channels() == 2 {
+                    1
+                } else {
+                    2
+                };
+                Some((
+                    (channel_rank, rate.abs_diff(wanted.sample_rate), format_rank),
+                    StreamFormat {
+                        sample_rate: rate,
+                        channels: range.channels(),
+                    },
+                    range.sample_format(),
+                ))
+            })
+            .min_by_key(|(rank, _, _)| *rank)
+            .map(|(_, format, sample_format)| (format, sample_format))
+    }
+
+    fn build<T>(
+        &self,
+        config: &cpal::StreamConfig,
+        mut render: Render,
+    ) -> Result<cpal::Stream, cpal::BuildStreamError>
+    where
+        T: cpal::SizedSample + cpal::FromSample<f32>,
+    {
+        // the engine renders f32, other formats are converted on the way out
+        // through a buffer sized here, the callback must not allocate
+        let mut buffer = vec![0.0f32; SCRATCH_FRAMES * config.channels.max(1) as usize];
+        self.device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                for chunk in data.chunks_mut(buffer.len()) {
+                    let scratch = &mut buffer[..chunk.len()];
+                    render(scratch);
+                    for (out, sample) in chunk.iter_mut().zip(scratch.iter()) {
+                        *out = T::from_sample(*sample);
+                    }
+                }
+            },
+            |err| eprintln!("Stream error: {}", err),
+            None,
+        )
+    }
+}
+
+/// Lower is better, `None` for formats there is no conversion to.
+fn format_rank(format: cpal::SampleFormat) -> Option<u8> {
+    use cpal::SampleFormat::*;
+    Some(match format {
+        F32 => 0,
+        F64 => 1,
+        I32 | U32 => 2,
+        I24 => 3,
+        I64 | U64 => 4,
+        I16 | U16 => 5,
+        I8 | U8 => 6,
+        _ => return None,
+    })
+}
+
+impl OutputSink for CpalSink {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Pick the stream config closest to what a file wants among the ones
+    /// the device actually supports, in any sample format.
+    fn negotiate(&self, wanted: StreamFormat) -> StreamFormat {
+        self.pick(wanted)
+            .map(|(format, _)| format)
+            .unwrap_or(wanted)
+    }
+
+    fn start(
+        &self,
+        format: StreamFormat,
+        render: Render,
+    ) -> Result<OutputStream, Box<dyn std::error::Error>> {
+        let config = cpal::StreamConfig {
+            channels: format.channels,
+            sample_rate: cpal::SampleRate(format.sample_rate),
+            buffer_size: cpal::BufferSize::Default,
+        };
+        // `format` came out of `negotiate`, picking again lands on the same
+        // config
+        let sample_format = self
+            .pick(format)
+            .map_or(cpal::SampleFormat::F32, |(_, sample_format)| sample_format);
+
+        let stream = match sample_format {
+            cpal::SampleFormat::F32 => self.build::<f32>(&config, render),
+            cpal::SampleFormat::F64 => self.build::<f64>(&config, render),
+            cpal::SampleFormat::I32 => self.build::<i32>(&config, render),
+            cpal::SampleFormat::U32 => self.build::<u32>(&config, render),
+            cpal::SampleFormat::I24 => self.build::<cpal::I24>(&config, render),
+            cpal::SampleFormat::I64 => self.build::<i64>(&config, render),
+            cpal::SampleFormat::U64 => self.build::<u64>(&config, render),
+            cpal::SampleFormat::I16 => self.build::<i16>(&config, render),
+            cpal::SampleFormat::U16 => self.build::<u16>(&config, render),
+            cpal::SampleFormat::I8 => self.build::<i8>(&config, render),
+            cpal::SampleFormat::U8 => self.build::<u8>(&config, render),
+            other => return Err(format!("Can't play {} samples", other).into()),
+        }?;
+        stream.play()?;
+
+        Ok(Box::new(stream))
+    }
+}
+
+/// Throws every sample away, at the pace a sound card would play them.
+pub struct NullSink;
+
+impl OutputSink for NullSink {
+    fn name(&self) -> String {
+        "null".into()
+    }
+
+    fn negotiate(&self, wanted: StreamFormat) -> StreamFormat {
+        wanted
+    }
+
+    fn start(
+        &self,
+        format: StreamFormat,
+        render: Render,
+    ) -> Result<OutputStream, Box<dyn std::error::Error>> {
+        Ok(Box::new(ClockedStream::start(format, render, |_| {})?))
+    }
+}
+
+/// Writes every sample to a 32-bit float WAV file, at the pace a sound card
+/// would play them. The file is finished once the stream stops.
+pub struct WavSink {
+    path: std::path::PathBuf,
+}
+
+impl OutputSink for WavSink {
+    fn name(&self) -> String {
+        format!("wav · {}", self.path.display())
+    }
+
+    fn negotiate(&self, wanted: StreamFormat) -> StreamFormat {
+        wanted
+    }
+
+    fn start(
+        &self,
+        format: StreamFormat,
+        render: Render,
+    ) -> Result<OutputStream, Box<dyn std::error::Error>> {
+        let spec = WavSpec {
+            channels: format.channels,
+            sample_rate: format.sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer: Option<WavWriter<BufWriter<File>>> =
+            Some(WavWriter::create(&self.path, spec)?);
+
+        let stream = ClockedStream::start(format, render, move |samples| {
+            let Some(wav) = writer.as_mut() else {
+                return;
+            };
+            // a full disk stops the recording, not the playback
+            if samples
+                .iter()
+                .any(|&sample| wav.write_sample(sample).is_err())
+            {
+                writer = None;
+            }
+        })?;
+        Ok(Box::new(stream))
+    }
+}
+
+/// Pulls one period of samples at a time on its own thread, paced by the
+/// wall clock the way a sound card would pace its callback.
+struct ClockedStream {
+    is_stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ClockedStream {
+    fn start(
+        format: StreamFormat,
+        mut render: Render,
+        mut write: impl FnMut(&[f32]) + Send + 'static,
+    ) -> std::io::Result<Self> {
+        let is_stopped = Arc::new(AtomicBool::new(false));
+        let channels = format.channels.max(1) as usize;
+        let frames = (format.sample_rate as u64 * PERIOD_MS / 1000).max(1) as usize;
+        let period = Duration::from_millis(PERIOD_MS);
+
+        let stop = is_stopped.clone();
+        let thread = std::thread::Builder::new()
+            .name("echo-sink".into())
+            .spawn(move || {
+                let mut buffer = vec![0.0; frames * channels];
+                let mut deadline = Instant::now();
+                while !stop.load(Ordering::Relaxed) {
+                    render(&mut buffer);
+                    write(&buffer);
+
+                    // keep to the clock even when a period ran late
+                    deadline += period;
+                    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                }
+            })?;
+
+        Ok(Self {
+            is_stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ClockedStream {
+    fn drop(&mut self) {
+        self.is_stopped.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+    use std::sync::Mutex;
+
+    const FORMAT: StreamFormat = StreamFormat {
+        sample_rate: 8_000,
+        channels: 2,
+    };
+
+    #[test]
+    fn null_sink_pulls_at_the_pace_of_its_format() {
+        let rendered = Arc::new(Mutex::new(Vec::new()));
+        let log = rendered.clone();
+        let stream = NullSink
+            .start(
+                FORMAT,
+                Box::new(move |data: &mut [f32]| log.lock().unwrap().push(data.len())),
+            )
+            .unwrap();
+
+        std::thread::sleep(Duration::from_millis(200));
+        drop(stream);
+        let after_drop = rendered.lock().unwrap().len();
+        std::thread::sleep(Duration::from_millis(50));
+
+        let rendered = rendered.lock().unwrap();
+        let period = (FORMAT.sample_rate as u64 * PERIOD_MS / 1000) as usize * 2;
+        assert!(rendered.iter().all(|&len| len == period));
+        // 200ms is 20 periods, give or take a slow scheduler
+        assert!(
+            (15..=25).contains(&rendered.len()),
+            "{} periods",
+            rendered.len()
+        );
+        assert_eq!(rendered.len(), after_drop, "pulled after it was dropped");
+    }
+
+    #[test]
+    fn wav_sink_writes_every_rendered_sample_and_finishes_the_file() {
+        let dir = TempDir::new("echo_sink_wav");
+        let path = dir.join("out.wav");
+        let sink = WavSink { path: path.clone() };
+        let mut next = 0u32;
+        let stream = sink
+            .start(
+                FORMAT,
+                Box::new(move |data: &mut [f32]| {
+                    for sample in data.iter_mut() {
+                        *sample = (next % 1_000) as f32 / 1_000.0;
+                        next += 1;
+                    }
+                }),
+            )
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        drop(stream);
+
+        let mut reader = hound::WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!((spec.sample_rate, spec.channels), (8_000, 2));
+        assert_eq!(spec.sample_format, SampleFormat::Float);
+        let samples: Vec<f32> = reader.samples::<f32>().map(Result::unwrap).collect();
+        assert!(!samples.is_empty());
+        // the header the sink finished with covers every sample written
+        assert_eq!(samples.len() as u32, reader.duration() * 2);
+        for (i, sample) in samples.iter().enumerate() {
+            assert_eq!(*sample, (i % 1_000) as f32 / 1_000.0);
+        }
+    }
+}
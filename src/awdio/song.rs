@@ -1,21 +1,36 @@
+use crate::awdio::loudness::ReplayGain;
 use crate::awdio::metadata::{self, Metadata};
 
 #[derive(Debug, Default, Clone)]
 pub struct Song {
+    // row in the library, songs played from a bare path have none
+    pub id: Option<i64>,
     pub metadata: metadata::Metadata,
     pub path: String,
+    pub gain: ReplayGain,
+    // EQ preset pinned to this song
+    pub eq_preset: Option<String>,
 }
 
 impl Song {
     pub fn new(path: String) -> Self {
         Song {
+            id: None,
             metadata: metadata::Metadata::from_path(&path).unwrap(),
             path,
+            gain: ReplayGain::default(),
+            eq_preset: None,
         }
     }
 
     pub fn new_temp(path: String, metadata: Metadata) -> Self {
-        Song { metadata, path }
+        Song {
+            id: None,
+            metadata,
+            path,
+            gain: ReplayGain::default(),
+            eq_preset: None,
+        }
     }
 
     pub fn ref_array(&self) -> [&String; 3] {
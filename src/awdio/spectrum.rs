@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+/// Mono samples every analysis looks at, about 90ms at 44.1kHz.
+pub const FFT_SIZE: usize = 4096;
+
+// the bottom of the display, anything quieter is no bar at all
+const FLOOR_DB: f32 = -72.0;
+// how much of the way to a new level a bar moves per update
+const ATTACK: f32 = 0.6;
+const DECAY: f32 = 0.15;
+// updates a peak marker stays put before it starts to fall
+const PEAK_HOLD: u32 = 16;
+const PEAK_FALL: f32 = 0.02;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectrumSettings {
+    pub bars: usize,
+    pub min_freq: f32,
+    pub max_freq: f32,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            bars: 48,
+            min_freq: 30.0,
+            max_freq: 16000.0,
+        }
+    }
+}
+
+/// Bar levels and peak markers in `0.0..=1.0` of the display height, lowest
+/// band first.
+#[derive(Debug, Clone, Default)]
+pub struct Spectrum {
+    pub bars: Vec<f32>,
+    pub peaks: Vec<f32>,
+}
+
+/// Turns mono samples into log-spaced bands in dB, smoothed over time.
+pub struct Analyzer {
+    settings: SpectrumSettings,
+    sample_rate: u32,
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    // what a full scale sine adds up to through the window
+    window_gain: f32,
+    // [lo, hi) FFT bins of every band
+    bands: Vec<(usize, usize)>,
+    spectrum: Spectrum,
+    peak_ages: Vec<u32>,
+    buffer: Vec<Complex<f32>>,
+}
+
+impl Analyzer {
+    pub fn new(settings: SpectrumSettings, sample_rate: u32) -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let window: Vec<f32> = (0..FFT_SIZE)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        let window_gain = window.iter().sum::<f32>() / 2.0;
+
+        let bars = settings.bars.max(1);
+        Self {
+            settings,
+            sample_rate,
+            fft,
+            window,
+            window_gain,
+            bands: band_bins(&settings, sample_rate),
+            spectrum: Spectrum {
+                bars: vec![0.0; bars],
+                peaks: vec![0.0; bars],
+            },
+            peak_ages: vec![0; bars],
+            buffer: Vec::with_capacity(FFT_SIZE),
+        }
+    }
+
+    /// Whether this analyzer was built for `settings` at `sample_rate`.
+    pub fn fits(&self, settings: SpectrumSettings, sample_rate: u32) -> bool {
+        self.settings == settings && self.sample_rate == sample_rate
+    }
+
+    /// Analyze the latest `FFT_SIZE` mono samples.
+    pub fn process(&mut self, mono: &[f32]) -> Spectrum {
+        self.buffer.clear();
+        self.buffer.extend(
+            mono.iter()
+                .zip(self.window.iter())
+                .map(|(sample, window)| Complex {
+                    re: sample * window,
+                    im: 0.0,
+                }),
+        );
+        self.buffer.resize(FFT_SIZE, Complex::default());
+        self.fft.process(&mut self.buffer);
+
+        let levels: Vec<f32> = self
+            .bands
+            .iter()
+            .map(|&(lo, hi)| {
+                let magnitude = self.buffer[lo..hi]
+                    .iter()
+                    .map(|bin| bin.norm())
+                    .fold(0.0, f32::max);
+                let db = 20.0 * (magnitude / self.window_gain).max(1e-9).log10();
+                ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+            })
+            .collect();
+
+        self.update(&levels)
+    }
+
+    /// Let every bar fall towards silence, for when nothing plays.
+    pub fn decay(&mut self) -> Spectrum {
+        let silence = vec![0.0; self.bands.len()];
+        self.update(&silence)
+    }
+
+    fn update(&mut self, levels: &[f32]) -> Spectrum {
+        let spectrum = &mut self.spectrum;
+        for (i, &level) in levels.iter().enumerate() {
+            let bar = &mut spectrum.bars[i];
+            let speed = if level > *bar { ATTACK } else { DECAY };
+            *bar += (level - *bar) * speed;
+
+            let peak = &mut spectrum.peaks[i];
+            if *bar >= *peak {
+                *peak = *bar;
+                self.peak_ages[i] = 0;
+            } else if self.peak_ages[i] < PEAK_HOLD {
+                self.peak_ages[i] += 1;
+            } else {
+                *peak = (*peak - PEAK_FALL).max(*bar);
+            }
+        }
+
+        spectrum.clone()
+    }
+}
+
+/// Split `min_freq..max_freq` into bands of equal width on a log scale. A
+/// band always covers at least one bin, low bands may share one.
+fn band_bins(settings: &SpectrumSettings, sample_rate: u32) -> Vec<(usize, usize)> {
+    let nyquist = sample_rate.max(2) as f32 / 2.0;
+    let max_freq = settings.max_freq.clamp(1.0, nyquist);
+    let min_freq = settings.min_freq.clamp(1.0, max_freq / 2.0);
+    let bars = settings.bars.max(1);
+    let bin_of = |freq: f32| (freq * FFT_SIZE as f32 / sample_rate.max(1) as f32) as usize;
+
+    (0..bars)
+        .map(|i| {
+            let edge = |i: usize| min_freq * (max_freq / min_freq).powf(i as f32 / bars as f32);
+            let lo = bin_of(edge(i)).clamp(1, FFT_SIZE / 2 - 1);
+            let hi = bin_of(edge(i + 1)).clamp(lo + 1, FFT_SIZE / 2);
+            (lo, hi)
+        })
+        .collect()
+}
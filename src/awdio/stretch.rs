@@ -0,0 +1,357 @@
+use std::f32::consts::PI;
+
+use serde::Deserialize;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+
+// WSOLA analysis window and how far a frame may move to line up
+const WINDOW_SECONDS: f64 = 0.02;
+const TOLERANCE_SECONDS: f64 = 0.005;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpeedMode {
+    /// Time-stretch, the pitch stays where it was.
+    #[default]
+    Stretch,
+    /// Play the samples faster or slower, the pitch moves along like tape.
+    Varispeed,
+}
+
+/// Changes the playback speed of interleaved samples in the layout of the
+/// output stream. Every frame that goes out stands for `speed` frames of
+/// the track.
+pub enum SpeedStage {
+    Stretch(Wsola),
+    Varispeed(Varispeed),
+}
+
+impl SpeedStage {
+    /// `None` at normal speed, nothing needs to run then.
+    pub fn new(speed: f32, mode: SpeedMode, sample_rate: u32, channels: u16) -> Option<Self> {
+        if speed == 1.0 {
+            return None;
+        }
+
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED) as f64;
+        let channels = channels.max(1) as usize;
+        Some(match mode {
+            SpeedMode::Stretch => Self::Stretch(Wsola::new(speed, sample_rate, channels)),
+            SpeedMode::Varispeed => Self::Varispeed(Varispeed::new(speed, channels)),
+        })
+    }
+
+    pub fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        match self {
+            Self::Stretch(wsola) => wsola.process(samples),
+            Self::Varispeed(varispeed) => varispeed.process(samples),
+        }
+    }
+
+    /// Play out whatever is still held back, at the end of the stream.
+    pub fn flush(&mut self) -> Vec<f32> {
+        match self {
+            Self::Stretch(wsola) => wsola.flush(),
+            Self::Varispeed(varispeed) => varispeed.flush(),
+        }
+    }
+
+    /// Output frames still held back from the samples fed so far.
+    pub fn latency_frames(&self) -> u64 {
+        match self {
+            Self::Stretch(wsola) => wsola.latency_frames(),
+            Self::Varispeed(varispeed) => varispeed.latency_frames(),
+        }
+    }
+}
+
+/// Waveform similarity overlap-add. Hann windows overlap by half in the
+/// output and are read `speed` times further apart in the input, each one
+/// nudged to where it lines up best with the one before.
+pub struct Wsola {
+    speed: f64,
+    channels: usize,
+    window: Vec<f32>,
+    // output frames per window, half the window
+    hop: usize,
+    tolerance: usize,
+    input: Vec<f32>,
+    // where the next window should be read, in frames into `input`
+    nominal: f64,
+    // where the input naturally went on after the last window
+    follow: Option<usize>,
+    // second half of the last window, waiting for the next one
+    overlap: Vec<f32>,
+    // frames fed and played out since the last flush
+    fed: u64,
+    emitted: u64,
+}
+
+impl Wsola {
+    fn new(speed: f64, sample_rate: u32, channels: usize) -> Self {
+        let hop = ((sample_rate as f64 * WINDOW_SECONDS / 2.0) as usize).max(16);
+        let size = hop * 2;
+        let window = (0..size)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / size as f32).cos())
+            .collect();
+
+        Self {
+            speed,
+            channels,
+            window,
+            hop,
+            tolerance: (sample_rate as f64 * TOLERANCE_SECONDS) as usize,
+            input: Vec::new(),
+            nominal: 0.0,
+            follow: None,
+            overlap: vec![0.0; hop * channels],
+            fed: 0,
+            emitted: 0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.fed += (samples.len() / self.channels) as u64;
+        let output = self.stretch(samples);
+        self.emitted += (output.len() / self.channels) as u64;
+        output
+    }
+
+    fn stretch(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+
+        let channels = self.channels;
+        let size = self.window.len();
+        let mut output = Vec::new();
+
+        loop {
+            let frames = self.input.len() / channels;
+            let nominal = self.nominal.round() as usize;
+            if nominal + self.tolerance + size > frames {
+                break;
+            }
+
+            let start = match self.follow {
+                Some(follow) => self.best_match(follow, nominal),
+                None => nominal,
+            };
+
+            // no window came before the first, it doesn't fade in
+            let first = self.follow.is_none();
+            let frame = &self.input[start * channels..(start + size) * channels];
+            for (i, (sample, overlap)) in frame[..self.hop * channels]
+                .iter()
+                .zip(self.overlap.iter())
+                .enumerate()
+            {
+                let weight = if first {
+                    1.0
+                } else {
+                    self.window[i / channels]
+                };
+                output.push(overlap + sample * weight);
+            }
+            for (i, sample) in frame[self.hop * channels..].iter().enumerate() {
+                self.overlap[i] = sample * self.window[self.hop + i / channels];
+            }
+
+            let follow = start + self.hop;
+            self.nominal += self.hop as f64 * self.speed;
+
+            // nothing before the next search range is looked at again
+            let keep_from = follow.min((self.nominal as usize).saturating_sub(self.tolerance));
+            self.input.drain(..keep_from * channels);
+            self.nominal -= keep_from as f64;
+            self.follow = Some(follow - keep_from);
+        }
+
+        output
+    }
+
+    /// The start within the tolerance of `nominal` whose first half looks
+    /// most like what naturally followed the previous window.
+    fn best_match(&self, follow: usize, nominal: usize) -> usize {
+        let lo = nominal.saturating_sub(self.tolerance);
+        let hi = nominal + self.tolerance;
+
+        let mono = |frame: usize| -> f32 {
+            let at = frame * self.channels;
+            self.input[at..at + self.channels].iter().sum()
+        };
+        let target: Vec<f32> = (0..self.hop).map(|i| mono(follow + i)).collect();
+        let score = |start: usize, step: usize| -> f32 {
+            let (mut dot, mut energy) = (0.0, 0.0);
+            for i in (0..self.hop).step_by(step) {
+                let sample = mono(start + i);
+                dot += sample * target[i];
+                energy += sample * sample;
+            }
+            dot / energy.max(f32::EPSILON).sqrt()
+        };
+
+        let best = |starts: &mut dyn Iterator<Item = usize>, step: usize| {
+            starts
+                .map(|start| (start, score(start, step)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(start, _)| start)
+        };
+
+        // a coarse pass over every other offset, then the neighbours
+        let coarse = best(&mut (lo..=hi).step_by(2), 2).unwrap_or(nominal);
+        best(
+            &mut (coarse.saturating_sub(1).max(lo)..=(coarse + 1).min(hi)),
+            1,
+        )
+        .unwrap_or(coarse)
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        // pad so the last real frames make it through a full window
+        let padding = (self.window.len() + self.tolerance) * self.channels;
+        let mut output = self.stretch(&vec![0.0; padding]);
+        output.extend_from_slice(&self.overlap);
+        // but only play out as much as the real frames stand for
+        let owed = (self.fed as f64 / self.speed).round() as u64;
+        let frames = owed.saturating_sub(self.emitted) as usize;
+        output.truncate(frames * self.channels);
+
+        self.overlap.fill(0.0);
+        self.input.clear();
+        self.nominal = 0.0;
+        self.follow = None;
+        self.fed = 0;
+        self.emitted = 0;
+        output
+    }
+
+    fn latency_frames(&self) -> u64 {
+        let frames = (self.input.len() / self.channels) as f64;
+        ((frames - self.nominal).max(0.0) / self.speed) as u64 + self.hop as u64
+    }
+}
+
+/// Reads the input `speed` frames per output frame, interpolating between
+/// neighbours.
+pub struct Varispeed {
+    speed: f64,
+    channels: usize,
+    input: Vec<f32>,
+    // read position in frames into `input`
+    position: f64,
+}
+
+impl Varispeed {
+    fn new(speed: f64, channels: usize) -> Self {
+        Self {
+            speed,
+            channels,
+            input: Vec::new(),
+            position: 0.0,
+        }
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(samples);
+
+        let channels = self.channels;
+        let frames = self.input.len() / channels;
+        let mut output = Vec::new();
+
+        while (self.position as usize) + 1 < frames {
+            let index = self.position as usize;
+            let frac = (self.position - index as f64) as f32;
+            for channel in 0..channels {
+                let a = self.input[index * channels + channel];
+                let b = self.input[(index + 1) * channels + channel];
+                output.push(a + (b - a) * frac);
+            }
+            self.position += self.speed;
+        }
+
+        let consumed = (self.position as usize).min(frames);
+        self.input.drain(..consumed * channels);
+        self.position -= consumed as f64;
+
+        output
+    }
+
+    fn flush(&mut self) -> Vec<f32> {
+        let output = self.process(&vec![0.0; self.channels]);
+        self.input.clear();
+        self.position = 0.0;
+        output
+    }
+
+    fn latency_frames(&self) -> u64 {
+        let frames = (self.input.len() / self.channels) as f64;
+        ((frames - self.position).max(0.0) / self.speed) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    fn sine(frames: usize, channels: usize) -> Vec<f32> {
+        (0..frames)
+            .flat_map(|i| {
+                let sample = (i as f32 * 440.0 * 2.0 * PI / RATE as f32).sin() * 0.5;
+                std::iter::repeat_n(sample, channels)
+            })
+            .collect()
+    }
+
+    fn run(stage: &mut SpeedStage, input: &[f32], chunk: usize) -> Vec<f32> {
+        let mut output: Vec<f32> = input.chunks(chunk).flat_map(|c| stage.process(c)).collect();
+        output.extend(stage.flush());
+        output
+    }
+
+    #[test]
+    fn output_length_follows_the_speed() {
+        let frames = RATE as usize;
+        let input = sine(frames, 2);
+        for mode in [SpeedMode::Stretch, SpeedMode::Varispeed] {
+            for speed in [MIN_SPEED, 0.75, 1.5, 2.0, MAX_SPEED] {
+                let mut stage = SpeedStage::new(speed, mode, RATE, 2).unwrap();
+                let output = run(&mut stage, &input, 1_000);
+                let wanted = frames as f32 / speed;
+                let got = (output.len() / 2) as f32;
+                assert!(
+                    (got - wanted).abs() <= 1.0,
+                    "{:?} at {}: {} frames",
+                    mode,
+                    speed,
+                    got
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn input_shorter_than_a_window_comes_out_on_flush() {
+        let frames = 300;
+        for speed in [MIN_SPEED, 1.5, MAX_SPEED] {
+            let mut wsola = Wsola::new(speed as f64, RATE, 2);
+            assert!(frames < wsola.window.len());
+
+            assert!(wsola.process(&vec![0.5; frames * 2]).is_empty());
+            let output = wsola.flush();
+
+            assert_eq!(output.len() / 2, (frames as f32 / speed).round() as usize);
+            // slowed down there is too little to repeat, it plays out once
+            let played = output.iter().filter(|&&s| (s - 0.5).abs() < 1e-3).count() / 2;
+            let wanted = (frames as f32 / speed).min(frames as f32) as usize;
+            assert!(
+                played >= wanted - 1,
+                "at {}: {} of {} frames",
+                speed,
+                played,
+                wanted
+            );
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use super::DecodeSource;
+
+/// Columns an overview is stored at, drawing merges them to fit.
+pub const WAVEFORM_COLUMNS: usize = 1024;
+
+// frames decoded into one block, the length of a track is not known up front
+const BLOCK_FRAMES: usize = 256;
+
+/// Lowest and highest sample of every slice of a track, from the start to
+/// the end.
+#[derive(Debug, Clone, Default)]
+pub struct Waveform {
+    pub path: String,
+    pub peaks: Vec<(f32, f32)>,
+}
+
+impl Waveform {
+    /// The overview of the file at `path`, read from `cache_dir` if it was
+    /// worked out before. Songs with an `id` are cached there afterwards.
+    pub fn load(path: &str, id: Option<i64>, cache_dir: &Path) -> Option<Self> {
+        let cache = id.map(|id| cache_file(cache_dir, id));
+        if let Some(peaks) = cache.as_deref().and_then(read_cache) {
+            return Some(Self {
+                path: path.to_string(),
+                peaks,
+            });
+        }
+
+        let peaks = analyze(path)?;
+        if let Some(cache) = cache {
+            // a missing cache only costs another scan next time
+            let _ = write_cache(&cache, &peaks);
+        }
+        Some(Self {
+            path: path.to_string(),
+            peaks,
+        })
+    }
+
+    /// An overview of `path` that is still being worked out.
+    pub fn pending(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            peaks: Vec::new(),
+        }
+    }
+
+    /// Merge the stored columns into `width` columns.
+    pub fn columns(&self, width: usize) -> Vec<(f32, f32)> {
+        let len = self.peaks.len();
+        if len == 0 || width == 0 {
+            return Vec::new();
+        }
+
+        (0..width)
+            .map(|column| {
+                let start = column * len / width;
+                let end = ((column + 1) * len / width).max(start + 1).min(len);
+                self.peaks[start..end]
+                    .iter()
+                    .fold((0.0f32, 0.0f32), |(lo, hi), &(min, max)| {
+                        (lo.min(min), hi.max(max))
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Decode the whole file and keep the extremes of every block, then fold
+/// the blocks into `WAVEFORM_COLUMNS` columns.
+fn analyze(path: &str) -> Option<Vec<(f32, f32)>> {
+    let mut source = DecodeSource::open(path).ok()?;
+    let channels = source.channels.max(1) as usize;
+
+    let mut blocks: Vec<(f32, f32)> = Vec::new();
+    let (mut min, mut max, mut frames) = (0.0f32, 0.0f32, 0);
+    while let Some(samples) = source.next_samples() {
+        for frame in samples.chunks_exact(channels) {
+            for &sample in frame {
+                min = min.min(sample);
+                max = max.max(sample);
+            }
+            frames += 1;
+            if frames == BLOCK_FRAMES {
+                blocks.push((min, max));
+                (min, max, frames) = (0.0, 0.0, 0);
+            }
+        }
+    }
+    if frames > 0 {
+        blocks.push((min, max));
+    }
+    if blocks.is_empty() {
+        return None;
+    }
+
+    let waveform = Waveform {
+        path: path.to_string(),
+        peaks: blocks,
+    };
+    Some(waveform.columns(WAVEFORM_COLUMNS.min(waveform.peaks.len())))
+}
+
+fn cache_file(cache_dir: &Path, id: i64) -> PathBuf {
+    cache_dir.join(format!("{}.peaks", id))
+}
+
+/// Pairs of little endian f32, min first.
+fn read_cache(path: &Path) -> Option<Vec<(f32, f32)>> {
+    let bytes = std::fs::read(path).ok()?;
+    if bytes.is_empty() || bytes.len() % 8 != 0 {
+        return None;
+    }
+
+    let float = |bytes: &[u8]| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    Some(
+        bytes
+            .chunks_exact(8)
+            .map(|pair| (float(&pair[..4]), float(&pair[4..])))
+            .collect(),
+    )
+}
+
+fn write_cache(path: &Path, peaks: &[(f32, f32)]) -> std::io::Result<()> {
+    let bytes: Vec<u8> = peaks
+        .iter()
+        .flat_map(|(min, max)| min.to_le_bytes().into_iter().chain(max.to_le_bytes()))
+        .collect();
+    std::fs::write(path, bytes)
+}
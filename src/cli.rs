@@ -0,0 +1,153 @@
+use std::path::PathBuf;
+
+use clap::{
+    Parser, Subcommand,
+    builder::{PossibleValuesParser, TypedValueParser},
+};
+use sqlx::SqlitePool;
+
+use crate::{
+    app::EqState,
+    awdio::{
+        device::SinkKind,
+        export::{self, ExportFormat},
+        flac,
+    },
+    config::UiConfig,
+    db::library::Library,
+    ignite::Paths,
+    result::{EchoReport, EchoResult},
+};
+
+/// A music player for the terminal.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Where playback goes, overrides `sink` of the `[output]` section.
+    #[arg(long, value_enum)]
+    pub sink: Option<SinkKind>,
+
+    /// The file the wav sink writes, implies `--sink wav`.
+    #[arg(long)]
+    pub wav_path: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write a library song to a FLAC or WAV file, on top of the `[export]`
+    /// section.
+    Export {
+        /// Id of the song in the library.
+        id: i64,
+
+        #[arg(long, value_enum)]
+        format: Option<ExportFormat>,
+
+        /// Folder the file is written to.
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        /// Sample rate to resample to.
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=flac::MAX_SAMPLE_RATE as i64))]
+        rate: Option<u32>,
+
+        /// Channels to mix down to.
+        #[arg(long, value_parser = clap::value_parser!(u16).range(1..=8))]
+        channels: Option<u16>,
+
+        /// 16 or 24 bit samples.
+        #[arg(
+            long,
+            value_parser = PossibleValuesParser::new(["16", "24"]).map(|bits| bits.parse::<u16>().unwrap())
+        )]
+        bits: Option<u16>,
+
+        /// Bake in the ReplayGain of the `[playback]` section.
+        #[arg(long)]
+        replaygain: bool,
+
+        /// Bake in the EQ preset the song is pinned to, or the `[eq]` one.
+        #[arg(long)]
+        eq: bool,
+    },
+}
+
+impl Cli {
+    /// Put the flags on top of what the config file says.
+    pub fn apply(&self, config: &mut UiConfig) {
+        if self.sink.is_none() && self.wav_path.is_none() {
+            return;
+        }
+
+        let output = config.output.get_or_insert_default();
+        if let Some(path) = &self.wav_path {
+            output.sink = SinkKind::Wav;
+            output.wav_path = Some(path.clone());
+        }
+        if let Some(sink) = self.sink {
+            output.sink = sink;
+        }
+    }
+}
+
+impl Command {
+    /// Run the command without starting the interface, returns the file that
+    /// was written.
+    pub async fn run(
+        &self,
+        config: &UiConfig,
+        pool: &SqlitePool,
+        paths: &Paths,
+    ) -> EchoResult<PathBuf> {
+        let Command::Export {
+            id,
+            format,
+            out,
+            rate,
+            channels,
+            bits,
+            replaygain,
+            eq,
+        } = self;
+
+        let song = Library::get_song(pool, *id)
+            .await?
+            .ok_or_else(|| EchoReport::Audio(format!("No song with id {}", id)))?;
+
+        let export_config = config.export();
+        let mut options = export_config.options();
+        options.format = format.unwrap_or(options.format);
+        options.sample_rate = rate.or(options.sample_rate);
+        options.channels = channels.or(options.channels);
+        options.bits_per_sample = bits.or(options.bits_per_sample);
+        if *replaygain || export_config.replaygain {
+            let playback = config.playback();
+            options.gain = Some(
+                song.gain
+                    .linear(playback.replaygain, playback.replaygain_preamp),
+            );
+        }
+        if *eq || export_config.eq {
+            let mut eq = EqState::from_config(&config.eq());
+            eq.is_enabled = true;
+            options.eq = eq.bands_for(&song);
+        }
+
+        let dir = out
+            .clone()
+            .unwrap_or_else(|| export_config.dir(&paths.exports));
+        let dest = dir.join(export::file_name(&song, options.format));
+        let export = dest.clone();
+        tokio::task::spawn_blocking(move || {
+            export::export(&song, &export, &options).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|e| EchoReport::Audio(e.to_string()))?
+        .map_err(EchoReport::Audio)?;
+
+        Ok(dest)
+    }
+}
@@ -1,8 +1,24 @@
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::NaiveTime;
 
 use ratatui::style::Color;
 use serde::{Deserialize, Deserializer};
 
+use crate::awdio::{
+    crossfade::{Crossfade, CrossfadeCurve},
+    device::OutputTarget,
+    eq::EqBand,
+    export::{ExportFormat, ExportOptions},
+    loudness::ReplayGainMode,
+    ramp::DEFAULT_RAMP_MS,
+    spectrum::SpectrumSettings,
+    stretch::SpeedMode,
+};
+use crate::db::library::import::ImportMode;
+
 #[derive(Debug, Deserialize)]
 pub struct Colors {
     #[serde(default = "default_bg", deserialize_with = "prefix_hex_code")]
@@ -54,6 +70,220 @@ pub struct Animations {
     pub timestamp_bar: String,
 }
 
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Playback {
+    /// Seconds the end of a track overlaps the start of the next one, 0 is gapless.
+    #[serde(default)]
+    pub crossfade_seconds: f32,
+
+    #[serde(default)]
+    pub crossfade_curve: CrossfadeCurve,
+
+    /// `off`, `track` or `album` loudness normalization.
+    #[serde(default)]
+    pub replaygain: ReplayGainMode,
+
+    /// Extra dB on top of the ReplayGain adjustment.
+    #[serde(default)]
+    pub replaygain_preamp: f32,
+
+    /// Playback speed from 0.5 to 3.0, normal speed when missing.
+    #[serde(default)]
+    pub speed: Option<f32>,
+
+    /// `stretch` keeps the pitch, `varispeed` moves it along with the speed.
+    #[serde(default)]
+    pub speed_mode: SpeedMode,
+
+    /// Milliseconds pause, resume, seek, stop and track changes fade over,
+    /// 10 when missing. 0 cuts at once.
+    #[serde(default)]
+    pub ramp_ms: Option<u32>,
+}
+
+impl Playback {
+    pub fn crossfade(&self) -> Crossfade {
+        Crossfade {
+            seconds: self.crossfade_seconds,
+            curve: self.crossfade_curve,
+        }
+    }
+
+    pub fn ramp(&self) -> Duration {
+        Duration::from_millis(self.ramp_ms.unwrap_or(DEFAULT_RAMP_MS).min(1000) as u64)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Timer {
+    /// Seconds the sleep timer fades out over before it pauses, 30 when missing.
+    #[serde(default)]
+    pub sleep_fade_seconds: Option<f32>,
+
+    /// `HH:MM` the alarm goes off at, 07:00 when missing.
+    #[serde(default)]
+    pub alarm_time: Option<String>,
+
+    /// Name of the playlist the alarm starts. The alarm is armed at startup
+    /// when it is set.
+    #[serde(default)]
+    pub alarm_playlist: Option<String>,
+
+    /// Seconds the alarm fades in over, 60 when missing.
+    #[serde(default)]
+    pub alarm_fade_seconds: Option<f32>,
+
+    /// Volume the fade-in ends at, 0.3 when missing.
+    #[serde(default)]
+    pub alarm_volume: Option<f32>,
+}
+
+impl Timer {
+    pub fn sleep_fade(&self) -> Duration {
+        Duration::from_secs_f32(self.sleep_fade_seconds.unwrap_or(30.0).max(0.0))
+    }
+
+    pub fn alarm_time(&self) -> NaiveTime {
+        self.alarm_time
+            .as_deref()
+            .and_then(|time| NaiveTime::parse_from_str(time.trim(), "%H:%M").ok())
+            .unwrap_or_else(|| NaiveTime::from_hms_opt(7, 0, 0).unwrap_or_default())
+    }
+
+    pub fn alarm_fade(&self) -> Duration {
+        Duration::from_secs_f32(self.alarm_fade_seconds.unwrap_or(60.0).max(0.0))
+    }
+
+    pub fn alarm_volume(&self) -> f32 {
+        self.alarm_volume.unwrap_or(0.3).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SpectrumConfig {
+    /// Bars the analyzer shows, 48 when missing.
+    #[serde(default)]
+    pub bars: Option<usize>,
+
+    /// Lowest frequency in Hz, 30 when missing.
+    #[serde(default)]
+    pub min_freq: Option<f32>,
+
+    /// Highest frequency in Hz, 16000 when missing.
+    #[serde(default)]
+    pub max_freq: Option<f32>,
+}
+
+impl SpectrumConfig {
+    pub fn settings(&self) -> SpectrumSettings {
+        let default = SpectrumSettings::default();
+        SpectrumSettings {
+            bars: self.bars.unwrap_or(default.bars).clamp(1, 512),
+            min_freq: self.min_freq.unwrap_or(default.min_freq),
+            max_freq: self.max_freq.unwrap_or(default.max_freq),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportConfig {
+    /// File format exports are written in, flac when missing.
+    #[serde(default)]
+    pub format: ExportFormat,
+
+    /// Folder exports are written to, `exports` in the data folder when
+    /// missing.
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+
+    /// Sample rate exports are resampled to, the rate of the file when
+    /// missing.
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+
+    /// Channels exports are mixed down to, the layout of the file when
+    /// missing.
+    #[serde(default)]
+    pub channels: Option<u16>,
+
+    /// 16 or 24, the sample size of the file when missing.
+    #[serde(default)]
+    pub bits_per_sample: Option<u16>,
+
+    /// Bake the ReplayGain of the `[playback]` section into the samples.
+    #[serde(default)]
+    pub replaygain: bool,
+
+    /// Bake the equalizer a song plays with into the samples.
+    #[serde(default)]
+    pub eq: bool,
+}
+
+impl ExportConfig {
+    pub fn dir(&self, default: &Path) -> PathBuf {
+        self.dir.clone().unwrap_or_else(|| default.to_path_buf())
+    }
+
+    /// The options without gain or EQ, those depend on the song.
+    pub fn options(&self) -> ExportOptions {
+        ExportOptions {
+            format: self.format,
+            sample_rate: self.sample_rate,
+            channels: self.channels,
+            bits_per_sample: self.bits_per_sample,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LibraryConfig {
+    /// `move` or `copy` imports into the songs folder, `reference` indexes
+    /// them where they are. move when missing.
+    #[serde(default)]
+    pub mode: ImportMode,
+
+    /// Folders `reference` mode indexes at startup and on `i`, `~` is the
+    /// home folder.
+    #[serde(default)]
+    pub roots: Vec<PathBuf>,
+
+    /// Keep the library in sync with its folders while echo runs, true when
+    /// missing.
+    #[serde(default)]
+    pub watch: Option<bool>,
+}
+
+impl LibraryConfig {
+    pub fn roots(&self) -> Vec<PathBuf> {
+        let home = directories::BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+        self.roots
+            .iter()
+            .map(|root| match (root.strip_prefix("~"), &home) {
+                (Ok(rest), Some(home)) => home.join(rest),
+                _ => root.clone(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EqConfig {
+    /// Whether the equalizer starts switched on.
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// The preset picked at startup, `flat` when missing.
+    #[serde(default)]
+    pub preset: Option<String>,
+
+    /// Named band sets, e.g. `bass = [{ freq = 80, gain = 4, kind = "low_shelf" }]`.
+    #[serde(default)]
+    pub presets: HashMap<String, Vec<EqBand>>,
+}
+
+// the sections echo added are named fields, a flattened map would try every
+// table as every section and trip over keys two of them share
 #[derive(Debug, Deserialize)]
 pub struct UiConfig {
     #[serde(flatten)]
@@ -61,6 +291,64 @@ pub struct UiConfig {
 
     #[serde(flatten)]
     pub animations: HashMap<String, Animations>,
+
+    #[serde(default)]
+    pub playback: Option<Playback>,
+
+    #[serde(default)]
+    pub output: Option<OutputTarget>,
+
+    #[serde(default)]
+    pub eq: Option<EqConfig>,
+
+    #[serde(default)]
+    pub timer: Option<Timer>,
+
+    #[serde(default)]
+    pub spectrum: Option<SpectrumConfig>,
+
+    #[serde(default)]
+    pub export: Option<ExportConfig>,
+
+    #[serde(default)]
+    pub library: Option<LibraryConfig>,
+}
+
+impl UiConfig {
+    /// The `[playback]` section, every field falls back to its default.
+    pub fn playback(&self) -> Playback {
+        self.playback.clone().unwrap_or_default()
+    }
+
+    /// The `[output]` section, the system default device when missing.
+    pub fn output(&self) -> OutputTarget {
+        self.output.clone().unwrap_or_default()
+    }
+
+    /// The `[timer]` section, every field falls back to its default.
+    pub fn timer(&self) -> Timer {
+        self.timer.clone().unwrap_or_default()
+    }
+
+    /// The `[spectrum]` section, every field falls back to its default.
+    pub fn spectrum(&self) -> SpectrumConfig {
+        self.spectrum.clone().unwrap_or_default()
+    }
+
+    /// The `[export]` section, every field falls back to its default.
+    pub fn export(&self) -> ExportConfig {
+        self.export.clone().unwrap_or_default()
+    }
+
+    /// The `[library]` section, imports move files when missing.
+    pub fn library(&self) -> LibraryConfig {
+        self.library.clone().unwrap_or_default()
+    }
+
+    /// The `[eq]` section, only the built-in `flat` preset when missing.
+    pub fn eq(&self) -> EqConfig {
+        self.eq.clone().unwrap_or_default()
+    }
 }
 
 fn default_timestamp_bar() -> String {
@@ -128,3 +416,41 @@ where
     let s = String::deserialize(deserializer)?;
     Ok(hex_to_color(&s))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::awdio::loudness::ReplayGainMode;
+
+    #[test]
+    fn sections_can_share_key_names() {
+        let config: UiConfig = toml::from_str(
+            r##"
+            [colors]
+            fg = "#ffffff"
+
+            [animations]
+            dot = 3
+
+            [playback]
+            replaygain = "track"
+
+            [export]
+            replaygain = true
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(config.playback().replaygain, ReplayGainMode::Track);
+        assert!(config.export().replaygain);
+        assert!(config.colors.contains_key("colors"));
+    }
+
+    #[test]
+    fn missing_sections_fall_back_to_defaults() {
+        let config: UiConfig = toml::from_str("[colors]\n").unwrap();
+
+        assert_eq!(config.playback().replaygain, ReplayGainMode::Off);
+        assert!(config.library().roots.is_empty());
+    }
+}
@@ -18,49 +18,6 @@ pub async fn init_db(path: &str) -> EchoResult<SqlitePool> {
     Ok(pool)
 }
 
-pub async fn insert_song(
-    pool: &SqlitePool,
-    metadata: &Metadata,
-    file_path: &str,
-) -> EchoResult<i64> {
-    let title = if metadata.title.is_empty() {
-        "Unknown Title"
-    } else {
-        &metadata.title
-    };
-
-    let id = sqlx::query!(
-        "INSERT INTO songs (title, artist, album, year, genre, track_number, total_tracks, disc_number, total_discs, album_artist, file_path) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-        title,
-        metadata.artist,
-        metadata.album,
-        metadata.year,
-        metadata.genre,
-        metadata.track_number,
-        metadata.total_tracks,
-        metadata.disc_number,
-        metadata.total_discs,
-        metadata.album_artist,
-        file_path,
-    )
-    .execute(pool)
-    .await?
-    .last_insert_rowid();
-
-    Ok(id)
-}
-
-pub async fn update_song_path(pool: &SqlitePool, song_id: i64, new_path: &str) -> EchoResult<()> {
-    sqlx::query!(
-        "UPDATE songs SET file_path = ? WHERE id = ?",
-        new_path,
-        song_id
-    )
-    .execute(pool)
-    .await?;
-    Ok(())
-}
-
 pub async fn update_song_metadata(
     pool: &SqlitePool,
     file_path: &str,
@@ -176,3 +133,54 @@ pub async fn get_playlist_song_paths(
     .await?;
     Ok(rows)
 }
+
+#[derive(Debug, Clone)]
+pub struct Bookmark {
+    pub id: i64,
+    pub name: String,
+    // seconds into the song
+    pub position: f64,
+}
+
+pub async fn get_bookmarks(pool: &SqlitePool, song_path: &str) -> EchoResult<Vec<Bookmark>> {
+    let rows = sqlx::query!(
+        "SELECT id AS \"id!\", name, position FROM bookmarks WHERE song_path = ? ORDER BY position",
+        song_path
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| Bookmark {
+            id: r.id,
+            name: r.name,
+            position: r.position,
+        })
+        .collect())
+}
+
+pub async fn add_bookmark(
+    pool: &SqlitePool,
+    song_path: &str,
+    name: &str,
+    position: f64,
+) -> EchoResult<i64> {
+    let id = sqlx::query!(
+        "INSERT INTO bookmarks (song_path, name, position) VALUES (?, ?, ?)",
+        song_path,
+        name,
+        position,
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+    Ok(id)
+}
+
+pub async fn delete_bookmark(pool: &SqlitePool, bookmark_id: i64) -> EchoResult<()> {
+    sqlx::query!("DELETE FROM bookmarks WHERE id = ?", bookmark_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
@@ -1,12 +1,75 @@
 use crate::{
-    awdio::{metadata::Metadata, song::Song},
-    result::EchoResult,
+    awdio::{
+        loudness::{self, Analysis, ReplayGain},
+        metadata::Metadata,
+        song::Song,
+    },
+    result::{EchoReport, EchoResult},
 };
 use sqlx::sqlite::SqlitePool;
+use unicode_normalization::{UnicodeNormalization, char::is_combining_mark};
+
+pub mod doctor;
+pub mod import;
+pub mod watch;
 
 #[derive(Debug, Clone)]
 pub struct Library;
 
+struct SongRow {
+    id: i64,
+    title: Option<String>,
+    artist: Option<String>,
+    album: Option<String>,
+    year: Option<i64>,
+    genre: Option<String>,
+    track_number: Option<i64>,
+    total_tracks: Option<i64>,
+    disc_number: Option<i64>,
+    total_discs: Option<i64>,
+    album_artist: Option<String>,
+    file_path: String,
+    has_cover: Option<bool>,
+    track_gain: Option<f64>,
+    track_peak: Option<f64>,
+    album_gain: Option<f64>,
+    album_peak: Option<f64>,
+    eq_preset: Option<String>,
+}
+
+impl From<SongRow> for Song {
+    fn from(row: SongRow) -> Self {
+        let metadata = Metadata::new(
+            row.title.unwrap_or_default(),
+            row.artist.unwrap_or_default(),
+            row.album.unwrap_or_default(),
+            row.year.unwrap_or_default() as u32,
+            row.genre.unwrap_or_default(),
+            row.track_number.unwrap_or_default() as u32,
+            row.total_tracks.unwrap_or_default() as u32,
+            row.disc_number.unwrap_or_default() as u32,
+            row.total_discs.unwrap_or_default() as u32,
+            row.album_artist.unwrap_or_default(),
+            if row.has_cover.unwrap_or(false) {
+                Some("internal".into())
+            } else {
+                None
+            },
+        );
+
+        let mut song = Song::new_temp(row.file_path, metadata);
+        song.gain = ReplayGain {
+            track_gain: row.track_gain.map(|v| v as f32),
+            track_peak: row.track_peak.map(|v| v as f32),
+            album_gain: row.album_gain.map(|v| v as f32),
+            album_peak: row.album_peak.map(|v| v as f32),
+        };
+        song.id = Some(row.id);
+        song.eq_preset = row.eq_preset;
+        song
+    }
+}
+
 impl Library {
     pub async fn get_songs_from_db(
         pool: &SqlitePool,
@@ -16,46 +79,451 @@ impl Library {
         let limit = (stop - start) as i64;
         let offset = start as i64;
 
-        let rows = sqlx::query!(
-            "SELECT title, artist, 
-            album, year, 
-            genre, track_number, 
-            total_tracks, disc_number, 
+        let rows = sqlx::query_as!(
+            SongRow,
+            "SELECT id AS \"id!\", title, artist,
+            album, year,
+            genre, track_number,
+            total_tracks, disc_number,
             total_discs, album_artist,
-            file_path, has_cover 
-            FROM songs ORDER BY id LIMIT ? OFFSET ?",
+            file_path, has_cover,
+            track_gain, track_peak,
+            album_gain, album_peak,
+            eq_preset
+            FROM songs WHERE file_path != 'PENDING' AND is_missing = 0
+            ORDER BY id LIMIT ? OFFSET ?",
             limit,
             offset
         )
         .fetch_all(pool)
         .await?;
 
-        let song_list = rows
-            .into_iter()
-            .map(|row| {
-                let metadata = Metadata::new(
-                    row.title.clone().unwrap_or_default(),
-                    row.artist.clone().unwrap_or_default(),
-                    row.album.clone().unwrap_or_default(),
-                    row.year.unwrap_or_default() as u32,
-                    row.genre.clone().unwrap_or_default(),
-                    row.track_number.unwrap_or_default() as u32,
-                    row.total_tracks.unwrap_or_default() as u32,
-                    row.disc_number.unwrap_or_default() as u32,
-                    row.total_discs.unwrap_or_default() as u32,
-                    row.album_artist.clone().unwrap_or_default(),
-                    if row.has_cover.unwrap_or(false) {
-                        Some("internal".into())
-                    } else {
-                        None
-                    },
-                );
-
-                let path = row.file_path;
-                Song::new_temp(path, metadata)
-            })
-            .collect::<Vec<Song>>();
-
-        Ok(song_list)
+        Ok(rows.into_iter().map(Song::from).collect())
+    }
+
+    /// Songs whose title, artist, album, album artist or genre have words
+    /// starting with every word of `query`, best matches first.
+    pub async fn search(pool: &SqlitePool, query: &str, limit: usize) -> EchoResult<Vec<Song>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let limit = limit as i64;
+
+        let rows = sqlx::query_as!(
+            SongRow,
+            "SELECT s.id AS \"id!\", s.title, s.artist,
+            s.album, s.year,
+            s.genre, s.track_number,
+            s.total_tracks, s.disc_number,
+            s.total_discs, s.album_artist,
+            s.file_path, s.has_cover,
+            s.track_gain, s.track_peak,
+            s.album_gain, s.album_peak,
+            s.eq_preset
+            FROM songs_fts JOIN songs s ON s.id = songs_fts.rowid
+            WHERE songs_fts MATCH ? AND s.file_path != 'PENDING' AND s.is_missing = 0
+            ORDER BY bm25(songs_fts) LIMIT ?",
+            query,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Song::from).collect())
+    }
+
+    /// Every song of `album` by `album_artist`, in disc and track order.
+    /// Nothing for the placeholder untagged songs share.
+    pub async fn get_album_songs(
+        pool: &SqlitePool,
+        album: &str,
+        album_artist: &str,
+    ) -> EchoResult<Vec<Song>> {
+        if !is_album(album) {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as!(
+            SongRow,
+            "SELECT id AS \"id!\", title, artist,
+            album, year,
+            genre, track_number,
+            total_tracks, disc_number,
+            total_discs, album_artist,
+            file_path, has_cover,
+            track_gain, track_peak,
+            album_gain, album_peak,
+            eq_preset
+            FROM songs WHERE album = ? AND album_artist IS ?
+            AND file_path != 'PENDING' AND is_missing = 0
+            ORDER BY disc_number, track_number, id",
+            album,
+            album_artist
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(Song::from).collect())
+    }
+
+    /// The song stored under `id`, if there is one.
+    pub async fn get_song(pool: &SqlitePool, id: i64) -> EchoResult<Option<Song>> {
+        let row = sqlx::query_as!(
+            SongRow,
+            "SELECT id AS \"id!\", title, artist,
+            album, year,
+            genre, track_number,
+            total_tracks, disc_number,
+            total_discs, album_artist,
+            file_path, has_cover,
+            track_gain, track_peak,
+            album_gain, album_peak,
+            eq_preset
+            FROM songs WHERE id = ? AND file_path != 'PENDING'",
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(Song::from))
+    }
+
+    /// Fill in the stored ReplayGain and EQ preset of songs that were built
+    /// from a path.
+    pub async fn fill_playback_info(pool: &SqlitePool, songs: &mut [Song]) -> EchoResult<()> {
+        for song in songs.iter_mut() {
+            let row = sqlx::query!(
+                "SELECT id AS \"id!\", track_gain, track_peak, album_gain, album_peak, eq_preset
+                FROM songs WHERE file_path = ?",
+                song.path
+            )
+            .fetch_optional(pool)
+            .await?;
+
+            if let Some(row) = row {
+                song.gain = ReplayGain {
+                    track_gain: row.track_gain.map(|v| v as f32),
+                    track_peak: row.track_peak.map(|v| v as f32),
+                    album_gain: row.album_gain.map(|v| v as f32),
+                    album_peak: row.album_peak.map(|v| v as f32),
+                };
+                song.id = Some(row.id);
+                song.eq_preset = row.eq_preset;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pin an EQ preset to the song at `path`, `None` goes back to the
+    /// global preset.
+    pub async fn set_eq_preset(
+        pool: &SqlitePool,
+        path: &str,
+        preset: Option<&str>,
+    ) -> EchoResult<()> {
+        sqlx::query!(
+            "UPDATE songs SET eq_preset = ? WHERE file_path = ?",
+            preset,
+            path
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Read or measure the loudness of freshly imported songs and store
+    /// track and album gain. `songs` holds `(id, file_path)`.
+    pub async fn scan_loudness(pool: &SqlitePool, songs: Vec<(i64, String)>) -> EchoResult<()> {
+        // decoding whole files is heavy, keep it off the async workers
+        let scanned = tokio::task::spawn_blocking(move || {
+            songs
+                .into_iter()
+                .filter_map(|(id, path)| loudness::analyze(&path).map(|analysis| (id, analysis)))
+                .collect::<Vec<(i64, Analysis)>>()
+        })
+        .await
+        .map_err(|e| EchoReport::Audio(e.to_string()))?;
+
+        let mut albums: Vec<(String, Option<String>)> = Vec::new();
+        for (id, analysis) in scanned {
+            let gain = analysis.gain;
+            let histogram = analysis.histogram_bytes();
+            let is_measured = histogram.is_some();
+            sqlx::query!(
+                "UPDATE songs SET track_gain = ?, track_peak = ?, album_gain = ?, album_peak = ?,
+                loudness_histogram = ?
+                WHERE id = ?",
+                gain.track_gain,
+                gain.track_peak,
+                gain.album_gain,
+                gain.album_peak,
+                histogram,
+                id
+            )
+            .execute(pool)
+            .await?;
+
+            // tags bring their own album gain
+            if !is_measured {
+                continue;
+            }
+            let row = sqlx::query!("SELECT album, album_artist FROM songs WHERE id = ?", id)
+                .fetch_one(pool)
+                .await?;
+            let album = (row.album.unwrap_or_default(), row.album_artist);
+            if is_album(&album.0) && !albums.contains(&album) {
+                albums.push(album);
+            }
+        }
+
+        // the album gain covers the whole album, songs scanned in an earlier
+        // batch included, from the histograms they left behind
+        for (album, album_artist) in albums {
+            let rows = sqlx::query!(
+                "SELECT id AS \"id!\", track_peak, loudness_histogram AS \"histogram!\"
+                FROM songs WHERE album = ? AND album_artist IS ?
+                AND loudness_histogram IS NOT NULL
+                AND file_path != 'PENDING' AND is_missing = 0",
+                album,
+                album_artist
+            )
+            .fetch_all(pool)
+            .await?;
+
+            let analyses: Vec<Analysis> = rows
+                .iter()
+                .map(|row| {
+                    let gain = ReplayGain {
+                        track_peak: row.track_peak.map(|peak| peak as f32),
+                        ..Default::default()
+                    };
+                    Analysis::stored(gain, &row.histogram)
+                })
+                .collect();
+            let Some((album_gain, album_peak)) =
+                loudness::album_gain(&analyses.iter().collect::<Vec<_>>())
+            else {
+                continue;
+            };
+
+            for row in rows {
+                sqlx::query!(
+                    "UPDATE songs SET album_gain = ?, album_peak = ? WHERE id = ?",
+                    album_gain,
+                    album_peak,
+                    row.id
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Songs without an album tag share a placeholder, they aren't one album.
+pub fn is_album(album: &str) -> bool {
+    !matches!(album.trim(), "" | "Unknown" | "UNKNOWN ALBUM")
+}
+
+/// Every word of what was typed as a quoted prefix, so FTS5 syntax in it is
+/// taken literally. `None` when there is no word.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = search_terms(query)
+        .iter()
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// The words of `text` as the search index tokenizes them, folded.
+pub fn search_terms(text: &str) -> Vec<String> {
+    words(text)
+        .map(|(_, word)| word.chars().flat_map(fold).collect())
+        .collect()
+}
+
+/// The words of `text` with the byte they start at. Like the index's
+/// unicode61 tokenizer, anything but letters, numbers and the marks on them
+/// separates words, so "AC/DC" is two.
+pub fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let is_token = |c: char| c.is_alphanumeric() || is_combining_mark(c);
+    let mut rest = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = rest.find(|(_, c)| is_token(*c))?;
+        let mut end = text.len();
+        while let Some(&(i, c)) = rest.peek() {
+            if !is_token(c) {
+                end = i;
+                break;
+            }
+            rest.next();
+        }
+        Some((start, &text[start..end]))
+    })
+}
+
+/// `c` lowercased and without diacritics, the way `remove_diacritics 2`
+/// folds it.
+pub fn fold(c: char) -> impl Iterator<Item = char> {
+    c.to_lowercase().nfd().filter(|c| !is_combining_mark(*c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestLibrary, sine_wav};
+    use std::path::Path;
+
+    async fn insert(pool: &SqlitePool, path: &Path, album: &str, album_artist: &str) -> i64 {
+        let path = path.to_str().unwrap();
+        sqlx::query!(
+            "INSERT INTO songs (title, album, album_artist, file_path) VALUES (?, ?, ?, ?)",
+            path,
+            album,
+            album_artist,
+            path
+        )
+        .execute(pool)
+        .await
+        .unwrap()
+        .last_insert_rowid()
+    }
+
+    async fn album_gain(pool: &SqlitePool, id: i64) -> Option<f64> {
+        sqlx::query_scalar!("SELECT album_gain FROM songs WHERE id = ?", id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn album_gain_covers_songs_scanned_earlier() {
+        let TestLibrary { dir, pool, .. } = TestLibrary::new("echo_library_album_gain").await;
+        let (loud, quiet, namesake, lone) = (
+            dir.join("loud.wav"),
+            dir.join("quiet.wav"),
+            dir.join("namesake.wav"),
+            dir.join("lone.wav"),
+        );
+        sine_wav(&loud, 48_000, 2.0, 0.5);
+        // within 10 LU, so the relative gate keeps it in the album
+        sine_wav(&quiet, 48_000, 2.0, 0.25);
+        sine_wav(&namesake, 48_000, 2.0, 0.5);
+        sine_wav(&lone, 48_000, 2.0, 0.5);
+        let first = insert(&pool, &loud, "Album", "Band").await;
+        let second = insert(&pool, &quiet, "Album", "Band").await;
+        let other_band = insert(&pool, &namesake, "Album", "Other Band").await;
+        let untagged = insert(&pool, &lone, "Unknown", "Unknown").await;
+
+        for (id, path) in [
+            (first, &loud),
+            (second, &quiet),
+            (other_band, &namesake),
+            (untagged, &lone),
+        ] {
+            Library::scan_loudness(&pool, vec![(id, path.to_string_lossy().to_string())])
+                .await
+                .unwrap();
+            // later batches measure the album without decoding this again
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let gain = album_gain(&pool, first).await.expect("no album gain");
+        assert_eq!(album_gain(&pool, second).await, Some(gain));
+        // the quiet song alone would need more gain
+        let quiet_gain = sqlx::query_scalar!("SELECT track_gain FROM songs WHERE id = ?", second)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(quiet_gain - gain > 3.0, "{} vs {}", quiet_gain, gain);
+        // an album of the same name by someone else is measured on its own
+        let other_gain = album_gain(&pool, other_band).await.expect("no album gain");
+        assert!(other_gain < gain, "{} vs {}", other_gain, gain);
+        assert_eq!(album_gain(&pool, untagged).await, None);
+    }
+
+    #[tokio::test]
+    async fn search_folds_case_and_diacritics_like_the_index() {
+        let TestLibrary {
+            dir: _dir, pool, ..
+        } = TestLibrary::new("echo_library_search").await;
+        for (title, artist) in [("Halo", "Beyoncé"), ("Thunderstruck", "AC/DC")] {
+            sqlx::query!(
+                "INSERT INTO songs (title, artist, file_path) VALUES (?, ?, ?)",
+                title,
+                artist,
+                title
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        for (query, title) in [
+            ("beyonce", "Halo"),
+            ("BEYONCÉ", "Halo"),
+            ("ac/dc", "Thunderstruck"),
+        ] {
+            let found = Library::search(&pool, query, 10).await.unwrap();
+            let titles: Vec<&str> = found
+                .iter()
+                .map(|song| song.metadata.title.as_str())
+                .collect();
+            assert_eq!(titles, [title], "{}", query);
+        }
+    }
+
+    #[test]
+    fn terms_split_and_fold_like_unicode61() {
+        assert_eq!(search_terms("AC/DC"), ["ac", "dc"]);
+        assert_eq!(search_terms("Beyoncé"), ["beyonce"]);
+        // the same letter spelled with a combining accent
+        assert_eq!(search_terms("Beyonce\u{301}"), ["beyonce"]);
+        assert_eq!(search_terms(" -- "), Vec::<String>::new());
+        assert_eq!(
+            fts_query("ac/dc \"x"),
+            Some("\"ac\"* \"dc\"* \"x\"*".to_string())
+        );
+        assert_eq!(fts_query("?!"), None);
+    }
+
+    #[tokio::test]
+    async fn album_songs_are_by_the_album_artist() {
+        let TestLibrary {
+            dir: _dir, pool, ..
+        } = TestLibrary::new("echo_library_album_songs").await;
+        for (path, album, album_artist) in [
+            ("a1", "Greatest Hits", "Queen"),
+            ("a2", "Greatest Hits", "Queen"),
+            ("b1", "Greatest Hits", "ABBA"),
+            ("u1", "Unknown", "Unknown"),
+            ("u2", "Unknown", "Unknown"),
+        ] {
+            sqlx::query!(
+                "INSERT INTO songs (title, album, album_artist, file_path) VALUES (?, ?, ?, ?)",
+                path,
+                album,
+                album_artist,
+                path
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let queen = Library::get_album_songs(&pool, "Greatest Hits", "Queen")
+            .await
+            .unwrap();
+        let paths: Vec<&str> = queen.iter().map(|song| song.path.as_str()).collect();
+        assert_eq!(paths, ["a1", "a2"]);
+        let untagged = Library::get_album_songs(&pool, "Unknown", "Unknown")
+            .await
+            .unwrap();
+        assert!(untagged.is_empty());
     }
 }
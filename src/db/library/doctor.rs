@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use sqlx::SqlitePool;
+
+use crate::{
+    awdio::{format::AudioFormat, metadata::Metadata},
+    db::{
+        self,
+        library::{
+            Library,
+            import::{self, ImportMode, ImportResult},
+            watch,
+        },
+    },
+    result::{EchoReport, EchoResult},
+};
+
+/// Something the songs table, the playlists and the files on disk disagree
+/// on.
+#[derive(Debug, Clone)]
+pub enum Issue {
+    /// A row whose file is gone, with the file that looks like it.
+    Missing {
+        id: i64,
+        title: String,
+        path: String,
+        found: Option<PathBuf>,
+    },
+    /// A row an import left behind before its file was in place.
+    Pending { id: i64, title: String },
+    /// A playlist entry with neither a row nor a file.
+    Dangling {
+        playlist_id: i64,
+        playlist: String,
+        path: String,
+    },
+    /// A file in the songs folder without a row.
+    Unindexed { path: PathBuf },
+}
+
+/// What can be done about an issue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fix {
+    /// Point the row at the file that was found.
+    Relocate,
+    /// Drop the row or the playlist entry.
+    Delete,
+    /// Import the file.
+    Import,
+}
+
+impl Issue {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Missing { .. } => "MISSING",
+            Self::Pending { .. } => "PENDING",
+            Self::Dangling { .. } => "PLAYLIST",
+            Self::Unindexed { .. } => "NO ROW",
+        }
+    }
+
+    pub fn subject(&self) -> String {
+        match self {
+            Self::Missing { title, .. } | Self::Pending { title, .. } => title.clone(),
+            Self::Dangling { path, .. } => Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.clone()),
+            Self::Unindexed { path } => path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn detail(&self) -> String {
+        match self {
+            Self::Missing {
+                found: Some(found), ..
+            } => format!("found at {}", found.display()),
+            Self::Missing { path, .. } => path.clone(),
+            Self::Pending { .. } => "import never finished".into(),
+            Self::Dangling { playlist, .. } => format!("in {}", playlist),
+            Self::Unindexed { path } => path.display().to_string(),
+        }
+    }
+
+    pub fn fixes(&self) -> Vec<Fix> {
+        match self {
+            Self::Missing { found: Some(_), .. } => vec![Fix::Relocate, Fix::Delete],
+            Self::Missing { .. } | Self::Pending { .. } | Self::Dangling { .. } => {
+                vec![Fix::Delete]
+            }
+            Self::Unindexed { .. } => vec![Fix::Import],
+        }
+    }
+}
+
+// an unindexed audio file a missing row may have become
+struct Candidate {
+    path: PathBuf,
+    metadata: Metadata,
+    hash: Option<String>,
+}
+
+/// Check every row, playlist entry and file of the songs folder. Missing
+/// files are looked for under the songs folder and `roots`, by content hash
+/// first and by title, artist and album after.
+pub async fn diagnose(
+    pool: &SqlitePool,
+    songs_dir: &Path,
+    roots: &[PathBuf],
+) -> EchoResult<Vec<Issue>> {
+    let rows = sqlx::query!(
+        "SELECT id AS \"id!\", title, artist, album, file_path, file_hash
+        FROM songs ORDER BY id"
+    )
+    .fetch_all(pool)
+    .await?;
+    let indexed: HashSet<String> = rows.iter().map(|row| row.file_path.clone()).collect();
+
+    let mut pending = Vec::new();
+    let mut missing = Vec::new();
+    for row in rows {
+        if row.file_path == "PENDING" {
+            pending.push(Issue::Pending {
+                id: row.id,
+                title: row.title.unwrap_or_default(),
+            });
+        } else if !Path::new(&row.file_path).exists() {
+            missing.push(row);
+        }
+    }
+
+    let mut dirs = vec![songs_dir.to_path_buf()];
+    dirs.extend(roots.iter().cloned());
+    // hashing is only worth it when there is a hash to compare with
+    let needs_hash = missing.iter().any(|row| row.file_hash.is_some());
+    let candidates = tokio::task::spawn_blocking(move || {
+        let mut seen = HashSet::new();
+        dirs.iter()
+            .flat_map(|dir| import::collect_files(dir).unwrap_or_default())
+            .filter(|file| seen.insert(file.clone()) && !is_indexed(file, &indexed))
+            .filter(|file| AudioFormat::probe(file).is_ok())
+            .map(|file| Candidate {
+                metadata: file
+                    .to_str()
+                    .and_then(|path| Metadata::from_path(path).ok())
+                    .unwrap_or_default(),
+                hash: needs_hash.then(|| import::file_hash(&file).ok()).flatten(),
+                path: file,
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|e| EchoReport::ImportError(e.to_string()))?;
+
+    let mut issues = Vec::new();
+    let mut taken = vec![false; candidates.len()];
+    for row in missing {
+        let title = row.title.unwrap_or_default();
+        let artist = row.artist.unwrap_or_default();
+        let album = row.album.unwrap_or_default();
+        let is_free = |i: &usize| !taken[*i];
+        let by_hash = (0..candidates.len())
+            .filter(is_free)
+            .find(|&i| row.file_hash.is_some() && candidates[i].hash == row.file_hash);
+        let by_tags = || {
+            (0..candidates.len()).filter(is_free).find(|&i| {
+                let metadata = &candidates[i].metadata;
+                !title.is_empty()
+                    && metadata.title == title
+                    && metadata.artist == artist
+                    && metadata.album == album
+            })
+        };
+        let found = by_hash.or_else(by_tags);
+        if let Some(i) = found {
+            taken[i] = true;
+        }
+
+        issues.push(Issue::Missing {
+            id: row.id,
+            title,
+            path: row.file_path,
+            found: found.map(|i| candidates[i].path.clone()),
+        });
+    }
+    issues.extend(pending);
+
+    let entries = sqlx::query!(
+        "SELECT p.id AS \"id!\", p.name, ps.song_path FROM playlist_songs ps
+        JOIN playlists p ON p.id = ps.playlist_id
+        WHERE ps.song_path NOT IN (SELECT file_path FROM songs)
+        ORDER BY p.id, ps.order_index"
+    )
+    .fetch_all(pool)
+    .await?;
+    issues.extend(
+        entries
+            .into_iter()
+            .filter(|entry| !Path::new(&entry.song_path).exists())
+            .map(|entry| Issue::Dangling {
+                playlist_id: entry.id,
+                playlist: entry.name,
+                path: entry.song_path,
+            }),
+    );
+
+    issues.extend(
+        candidates
+            .into_iter()
+            .zip(taken)
+            .filter(|(candidate, taken)| !taken && candidate.path.starts_with(songs_dir))
+            .map(|(candidate, _)| Issue::Unindexed {
+                path: candidate.path,
+            }),
+    );
+
+    Ok(issues)
+}
+
+/// Apply `fix` to `issue`, what was done for the report line. `None` when
+/// the fix doesn't apply to it.
+pub async fn fix(
+    pool: &SqlitePool,
+    issue: &Issue,
+    fix: Fix,
+    songs_dir: &Path,
+    mode: ImportMode,
+) -> EchoResult<Option<String>> {
+    if !issue.fixes().contains(&fix) {
+        return Ok(None);
+    }
+
+    let done = match issue {
+        Issue::Missing {
+            title,
+            path,
+            found: Some(found),
+            ..
+        } if fix == Fix::Relocate => {
+            watch::move_rows(pool, Path::new(path), found).await?;
+            format!("RELOCATED: {} → {}", title, found.display())
+        }
+        Issue::Missing {
+            id, title, path, ..
+        } => {
+            delete_song(pool, *id, Some(path)).await?;
+            format!("DELETED: {}", title)
+        }
+        Issue::Pending { id, title } => {
+            delete_song(pool, *id, None).await?;
+            format!("DELETED: {}", title)
+        }
+        Issue::Dangling {
+            playlist_id,
+            playlist,
+            path,
+        } => {
+            db::remove_song_from_playlist(pool, *playlist_id, path).await?;
+            format!("REMOVED FROM {}: {}", playlist, issue.subject())
+        }
+        Issue::Unindexed { path } => match import::import_file(pool, path, songs_dir, mode).await {
+            ImportResult::Imported { id, title, path } => {
+                Library::scan_loudness(pool, vec![(id, path.to_string_lossy().to_string())])
+                    .await?;
+                format!("IMPORTED: {}", title)
+            }
+            ImportResult::Skipped(e) | ImportResult::Failed(e) => {
+                return Err(EchoReport::ImportError(e));
+            }
+        },
+    };
+
+    Ok(Some(done))
+}
+
+/// Drop a row, with the playlist entries and bookmarks of its file.
+async fn delete_song(pool: &SqlitePool, id: i64, path: Option<&str>) -> EchoResult<()> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!("DELETE FROM songs WHERE id = ?", id)
+        .execute(&mut *tx)
+        .await?;
+    if let Some(path) = path {
+        sqlx::query!("DELETE FROM playlist_songs WHERE song_path = ?", path)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!("DELETE FROM bookmarks WHERE song_path = ?", path)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+// moved in files are stored as they are named, referenced ones canonical
+fn is_indexed(file: &Path, indexed: &HashSet<String>) -> bool {
+    let canonical = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    [file, canonical.as_path()]
+        .iter()
+        .any(|path| path.to_str().is_some_and(|path| indexed.contains(path)))
+}
@@ -0,0 +1,595 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tokio::fs;
+
+use crate::{
+    app::{LogLevel, Report},
+    awdio::{
+        format::{self, AudioFormat},
+        metadata::Metadata,
+    },
+    db::library::Library,
+    result::{EchoReport, EchoResult},
+};
+
+/// What importing does with a file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportMode {
+    /// Moved into the songs folder as `{id}.ext`.
+    #[default]
+    Move,
+    /// Copied into the songs folder as `{id}.ext`, the original stays.
+    Copy,
+    /// Indexed where it is by absolute path, the file is left untouched.
+    Reference,
+}
+
+/// How far the running import got, the import tab draws it as a gauge.
+#[derive(Debug, Clone, Default)]
+pub struct ImportProgress {
+    pub is_running: bool,
+    pub done: usize,
+    pub total: usize,
+    // file name of the song being imported
+    pub current: String,
+    pub imported: usize,
+    // every file that didn't make it, with the reason
+    pub failures: Vec<(PathBuf, String)>,
+}
+
+impl ImportProgress {
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.done as f64 / self.total as f64).clamp(0.0, 1.0)
+    }
+}
+
+/// What became of one file.
+#[derive(Debug, Clone)]
+pub enum ImportResult {
+    Imported {
+        id: i64,
+        title: String,
+        path: PathBuf,
+    },
+    // not audio, or already one of the library's own files
+    Skipped(String),
+    Failed(String),
+}
+
+/// Import every audio file under `sources`, subfolders included, the way
+/// `mode` says. Progress goes to `progress` and `reporter` as it happens,
+/// the loudness of the new songs is scanned once all of them are in.
+pub async fn import(
+    pool: &SqlitePool,
+    sources: &[PathBuf],
+    songs_dir: &Path,
+    mode: ImportMode,
+    progress: &Arc<Mutex<ImportProgress>>,
+    reporter: &Sender<Report>,
+) -> Vec<(PathBuf, ImportResult)> {
+    let report = |log: String, report: Option<EchoReport>, level: LogLevel| {
+        let _ = reporter.send(Report {
+            log: Some(log),
+            report,
+            level,
+        });
+    };
+
+    {
+        let Ok(mut progress) = progress.lock() else {
+            return Vec::new();
+        };
+        if progress.is_running {
+            report("AN IMPORT IS ALREADY RUNNING".into(), None, LogLevel::WARN);
+            return Vec::new();
+        }
+        *progress = ImportProgress {
+            is_running: true,
+            ..Default::default()
+        };
+    }
+
+    let dirs = sources.to_vec();
+    let collected = tokio::task::spawn_blocking(move || {
+        dirs.into_iter()
+            .map(|dir| {
+                let files = collect_files(&dir);
+                (dir, files)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+    let mut files = Vec::new();
+    match collected {
+        Ok(collected) => {
+            // an unreadable source doesn't hold up the others
+            for (dir, collected) in collected {
+                match collected {
+                    Ok(collected) => files.extend(collected),
+                    Err(e) => report(
+                        format!("Import error: {}: {}", dir.display(), e),
+                        Some(EchoReport::Io(e)),
+                        LogLevel::ERR,
+                    ),
+                }
+            }
+        }
+        Err(e) => {
+            report(
+                format!("Import error: {}", e),
+                Some(EchoReport::ImportError(e.to_string())),
+                LogLevel::ERR,
+            );
+            set(progress, |progress| progress.is_running = false);
+            return Vec::new();
+        }
+    }
+
+    let total = files.len();
+    set(progress, |progress| progress.total = total);
+    report(
+        format!(
+            "IMPORTING: {} FILES FROM {}",
+            total,
+            sources
+                .iter()
+                .map(|source| source.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        None,
+        LogLevel::INFO,
+    );
+
+    let mut results = Vec::with_capacity(total);
+    for file in files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        set(progress, |progress| progress.current = name.clone());
+
+        let result = if mode != ImportMode::Reference && is_library_file(&file, songs_dir) {
+            ImportResult::Skipped("already in the library".into())
+        } else {
+            import_file(pool, &file, songs_dir, mode).await
+        };
+
+        set(progress, |progress| {
+            progress.done += 1;
+            match &result {
+                ImportResult::Imported { .. } => progress.imported += 1,
+                ImportResult::Failed(e) => progress.failures.push((file.clone(), e.clone())),
+                ImportResult::Skipped(_) => {}
+            }
+        });
+        if let ImportResult::Failed(e) = &result {
+            report(
+                format!("Import failed: {}: {}", name, e),
+                Some(EchoReport::ImportError(e.clone())),
+                LogLevel::ERR,
+            );
+        }
+        results.push((file, result));
+    }
+
+    let (imported, failed) = match progress.lock() {
+        Ok(mut progress) => {
+            progress.is_running = false;
+            progress.current.clear();
+            (progress.imported, progress.failures.len())
+        }
+        Err(_) => (0, 0),
+    };
+    report(
+        format!("IMPORTED: {} OF {} · {} FAILED", imported, total, failed),
+        None,
+        if failed > 0 {
+            LogLevel::WARN
+        } else {
+            LogLevel::INFO
+        },
+    );
+
+    let songs = results
+        .iter()
+        .filter_map(|(_, result)| match result {
+            ImportResult::Imported { id, path, .. } => {
+                Some((*id, path.to_string_lossy().to_string()))
+            }
+            _ => None,
+        })
+        .collect();
+    if let Err(e) = Library::scan_loudness(pool, songs).await {
+        report(
+            format!("Loudness scan error: {}", e),
+            Some(e),
+            LogLevel::ERR,
+        );
+    }
+
+    results
+}
+
+/// Add one file to the library, moved or copied to `songs_dir` as
+/// `{id}.ext` or referenced where it is. A moved or copied file only gets
+/// its row once it is in place, a failure leaves neither behind.
+pub async fn import_file(
+    pool: &SqlitePool,
+    file: &Path,
+    songs_dir: &Path,
+    mode: ImportMode,
+) -> ImportResult {
+    let reference = match mode {
+        ImportMode::Reference => match std::fs::canonicalize(file) {
+            Ok(path) => Some(path),
+            Err(e) => return ImportResult::Failed(e.to_string()),
+        },
+        ImportMode::Move | ImportMode::Copy => None,
+    };
+    // rescanning only has to look at the new files
+    let source = reference.as_deref().unwrap_or(file);
+    if is_indexed(pool, source).await {
+        if reference.is_some() {
+            let _ = found_again(pool, source).await;
+        }
+        return ImportResult::Skipped("already in the library".into());
+    }
+    // a copy would leave the original in the songs folder, to be imported
+    // again by every rescan
+    let mode = if mode == ImportMode::Copy && is_under(file, songs_dir) {
+        ImportMode::Move
+    } else {
+        mode
+    };
+
+    let path = file.to_path_buf();
+    let probed = tokio::task::spawn_blocking(move || {
+        let format = AudioFormat::probe(&path)?;
+        let metadata = path
+            .to_str()
+            .and_then(|path| Metadata::from_path(path).ok())
+            .unwrap_or_default();
+        Ok::<_, String>((format, metadata))
+    })
+    .await;
+
+    let (format, mut metadata) = match probed {
+        Ok(Ok(probed)) => probed,
+        Ok(Err(e)) => return ImportResult::Skipped(e),
+        Err(e) => return ImportResult::Failed(e.to_string()),
+    };
+
+    // untagged files are named after the file
+    if metadata.title.is_empty() || metadata.title == "Unknown" {
+        metadata.title = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+    }
+
+    let stored = match &reference {
+        Some(path) => insert_in_place(pool, &metadata, path)
+            .await
+            .map(|id| (id, path.clone())),
+        None => insert_and_move(pool, &metadata, file, songs_dir, format, mode).await,
+    };
+
+    match stored {
+        Ok((id, path)) => {
+            // the library's own copy carries the title it is listed under,
+            // referenced files are the user's and stay as they are
+            if reference.is_none()
+                && let Some(path) = path.to_str()
+            {
+                let _ = metadata.update_file(path);
+            }
+            // without a hash the doctor can still match the file by its tags
+            let _ = store_hash(pool, id, &path).await;
+            ImportResult::Imported {
+                id,
+                title: metadata.title,
+                path,
+            }
+        }
+        Err(e) => ImportResult::Failed(e.to_string()),
+    }
+}
+
+async fn insert_in_place(pool: &SqlitePool, metadata: &Metadata, path: &Path) -> EchoResult<i64> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| EchoReport::ImportError(format!("Invalid path: {}", path.display())))?;
+
+    let id = sqlx::query!(
+        "INSERT INTO songs (title, artist, album, year, genre, track_number, total_tracks, disc_number, total_discs, album_artist, file_path) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        metadata.title,
+        metadata.artist,
+        metadata.album,
+        metadata.year,
+        metadata.genre,
+        metadata.track_number,
+        metadata.total_tracks,
+        metadata.disc_number,
+        metadata.total_discs,
+        metadata.album_artist,
+        path,
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(id)
+}
+
+async fn insert_and_move(
+    pool: &SqlitePool,
+    metadata: &Metadata,
+    file: &Path,
+    songs_dir: &Path,
+    format: AudioFormat,
+    mode: ImportMode,
+) -> EchoResult<(i64, PathBuf)> {
+    // the row stays PENDING while its file is on the way, the library doctor
+    // lists it if echo doesn't live to finish
+    let id = sqlx::query!(
+        "INSERT INTO songs (title, artist, album, year, genre, track_number, total_tracks, disc_number, total_discs, album_artist, file_path) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 'PENDING')",
+        metadata.title,
+        metadata.artist,
+        metadata.album,
+        metadata.year,
+        metadata.genre,
+        metadata.track_number,
+        metadata.total_tracks,
+        metadata.disc_number,
+        metadata.total_discs,
+        metadata.album_artist,
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    let dest = songs_dir.join(format::library_file_name(id, file, format));
+    let Some(dest_str) = dest.to_str() else {
+        let _ = delete_pending(pool, id).await;
+        return Err(EchoReport::ImportError(format!(
+            "Invalid path: {}",
+            dest.display()
+        )));
+    };
+
+    let is_copy = mode == ImportMode::Copy;
+    let placed = if is_copy {
+        fs::copy(file, &dest).await.map(|_| ())
+    } else {
+        move_file(file, &dest).await
+    };
+    if let Err(e) = placed {
+        let _ = delete_pending(pool, id).await;
+        return Err(e.into());
+    }
+
+    let stored = sqlx::query!("UPDATE songs SET file_path = ? WHERE id = ?", dest_str, id)
+        .execute(pool)
+        .await;
+    if let Err(e) = stored {
+        let _ = if is_copy {
+            fs::remove_file(&dest).await
+        } else {
+            move_file(&dest, file).await
+        };
+        let _ = delete_pending(pool, id).await;
+        return Err(e.into());
+    }
+
+    Ok((id, dest))
+}
+
+async fn delete_pending(pool: &SqlitePool, id: i64) -> EchoResult<()> {
+    sqlx::query!(
+        "DELETE FROM songs WHERE id = ? AND file_path = 'PENDING'",
+        id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Rename, or copy and delete when `to` is on another file system.
+async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to).await?;
+    if let Err(e) = fs::remove_file(from).await {
+        let _ = fs::remove_file(to).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Remember the content of the file `id` is stored at, the library doctor
+/// finds a moved file again by it.
+pub(super) async fn store_hash(pool: &SqlitePool, id: i64, path: &Path) -> EchoResult<()> {
+    let file = path.to_path_buf();
+    let hash = tokio::task::spawn_blocking(move || file_hash(&file))
+        .await
+        .map_err(|e| EchoReport::ImportError(e.to_string()))??;
+    sqlx::query!("UPDATE songs SET file_hash = ? WHERE id = ?", hash, id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// SHA-256 of the whole file, in hex.
+pub(super) fn file_hash(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Every file under `root` in name order, the files of a folder before its
+/// subfolders. Links to folders are not followed, unreadable subfolders are
+/// left out.
+pub(super) fn collect_files(root: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if dir == root => return Err(e),
+            Err(_) => continue,
+        };
+        let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok()).collect();
+        entries.sort_by_key(|entry| entry.file_name());
+
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                // hidden folders hold files that aren't songs yet, like
+                // downloads in progress
+                if !entry.file_name().to_string_lossy().starts_with('.') {
+                    subdirs.push(entry.path());
+                }
+            } else if !file_type.is_symlink() || entry.path().is_file() {
+                files.push(entry.path());
+            }
+        }
+        // popped from the back, so push in reverse to walk them in order
+        dirs.extend(subdirs.into_iter().rev());
+    }
+    Ok(files)
+}
+
+async fn is_indexed(pool: &SqlitePool, path: &Path) -> bool {
+    let Some(path) = path.to_str() else {
+        return false;
+    };
+    sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM songs WHERE file_path = ?)",
+        path
+    )
+    .fetch_one(pool)
+    .await
+    .is_ok_and(|exists| exists != 0)
+}
+
+/// A referenced file a rescan came across is no longer missing.
+async fn found_again(pool: &SqlitePool, path: &Path) -> EchoResult<()> {
+    let path = path.to_string_lossy();
+    sqlx::query!(
+        "UPDATE songs SET is_missing = 0 WHERE file_path = ? AND is_missing = 1",
+        path
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Whether `file` is somewhere inside `dir`, whichever way the two were
+/// spelled.
+fn is_under(file: &Path, dir: &Path) -> bool {
+    let canonical =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    canonical(file).starts_with(canonical(dir))
+}
+
+/// Files the library already moved in are named after their id.
+pub(super) fn is_library_file(file: &Path, songs_dir: &Path) -> bool {
+    let canonical =
+        |path: &Path| std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    file.file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.parse::<i64>().is_ok())
+        && file.parent().map(canonical) == Some(canonical(songs_dir))
+}
+
+fn set(progress: &Arc<Mutex<ImportProgress>>, update: impl FnOnce(&mut ImportProgress)) {
+    if let Ok(mut progress) = progress.lock() {
+        update(&mut progress);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestLibrary, song_wav};
+
+    async fn rows(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar!("SELECT COUNT(*) FROM songs")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn copy_of_a_file_in_the_songs_folder_is_imported_once() {
+        let TestLibrary {
+            dir: _dir,
+            songs,
+            pool,
+        } = TestLibrary::new("echo_import_copy").await;
+        song_wav(&songs.join("dropped.wav"));
+        let progress = Arc::new(Mutex::new(ImportProgress::default()));
+        let (reporter, _reports) = std::sync::mpsc::channel();
+
+        for _ in 0..3 {
+            import(
+                &pool,
+                std::slice::from_ref(&songs),
+                &songs,
+                ImportMode::Copy,
+                &progress,
+                &reporter,
+            )
+            .await;
+        }
+
+        assert_eq!(rows(&pool).await, 1);
+        assert_eq!(std::fs::read_dir(&songs).unwrap().count(), 1);
+        assert!(!songs.join("dropped.wav").exists());
+    }
+
+    #[tokio::test]
+    async fn copy_from_outside_keeps_the_original() {
+        let TestLibrary { dir, songs, pool } = TestLibrary::new("echo_import_copy_outside").await;
+        let original = dir.join("outside.wav");
+        song_wav(&original);
+
+        let result = import_file(&pool, &original, &songs, ImportMode::Copy).await;
+
+        let ImportResult::Imported { path, .. } = result else {
+            panic!("not imported: {:?}", result);
+        };
+        assert!(original.exists() && path.exists());
+        assert!(path.starts_with(&songs));
+    }
+
+    #[tokio::test]
+    async fn indexed_files_are_skipped() {
+        let TestLibrary { dir, songs, pool } = TestLibrary::new("echo_import_indexed").await;
+        let original = dir.join("referenced.wav");
+        song_wav(&original);
+
+        let first = import_file(&pool, &original, &songs, ImportMode::Reference).await;
+        let again = import_file(&pool, &original, &songs, ImportMode::Reference).await;
+
+        assert!(matches!(first, ImportResult::Imported { .. }));
+        assert!(matches!(again, ImportResult::Skipped(_)));
+        assert_eq!(rows(&pool).await, 1);
+    }
+}
@@ -0,0 +1,501 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use sqlx::SqlitePool;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+use crate::{
+    app::{LogLevel, Report},
+    awdio::metadata::Metadata,
+    db::library::{
+        Library,
+        import::{self, ImportMode, ImportResult},
+    },
+    download,
+    result::{EchoReport, EchoResult},
+};
+
+// files are written in many small steps, a folder has to be quiet this long
+// before its changes are picked up
+const SETTLE: Duration = Duration::from_secs(2);
+// a big sync would otherwise flood the report line with one entry per file
+const PROGRESS_EVERY: Duration = Duration::from_millis(250);
+
+/// Keeps the library in sync with its folders until it is dropped.
+pub struct LibraryWatcher {
+    _watcher: RecommendedWatcher,
+    dirs: Vec<PathBuf>,
+    task: JoinHandle<()>,
+}
+
+impl fmt::Debug for LibraryWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LibraryWatcher")
+            .field("dirs", &self.dirs)
+            .finish()
+    }
+}
+
+impl Drop for LibraryWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// What changed on disk since the last sync.
+#[derive(Debug, Default)]
+struct Changes {
+    renames: Vec<(PathBuf, PathBuf)>,
+    paths: BTreeSet<PathBuf>,
+}
+
+impl Changes {
+    fn add(&mut self, event: Event) {
+        match event.kind {
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                self.renames
+                    .push((event.paths[0].clone(), event.paths[1].clone()));
+            }
+            // reading a file changes nothing
+            EventKind::Access(_) => {}
+            _ => self.paths.extend(event.paths),
+        }
+    }
+}
+
+/// What one sync did, for the report line.
+#[derive(Debug, Default)]
+struct Summary {
+    added: usize,
+    updated: usize,
+    moved: u64,
+    missing: u64,
+}
+
+impl Summary {
+    fn is_empty(&self) -> bool {
+        self.added == 0 && self.updated == 0 && self.moved == 0 && self.missing == 0
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [
+            (self.added as u64, "NEW"),
+            (self.updated as u64, "UPDATED"),
+            (self.moved, "MOVED"),
+            (self.missing, "MISSING"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", count, label))
+        .collect();
+        write!(f, "LIBRARY: {}", parts.join(" · "))
+    }
+}
+
+/// Watch `dirs` and everything below them. New files are imported the way
+/// `mode` says, retagged ones are read again, deleted ones are marked
+/// missing and renamed ones follow their file.
+pub fn watch(
+    pool: SqlitePool,
+    dirs: &[PathBuf],
+    songs_dir: PathBuf,
+    mode: ImportMode,
+    reporter: Sender<Report>,
+) -> EchoResult<LibraryWatcher> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = tx.send(event);
+    })
+    .map_err(|e| EchoReport::ImportError(e.to_string()))?;
+
+    let mut watched = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        // referenced rows hold canonical paths, events come relative to
+        // what is watched
+        let dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+        match watcher.watch(&dir, RecursiveMode::Recursive) {
+            Ok(()) => watched.push(dir),
+            Err(e) => {
+                let _ = reporter.send(Report {
+                    log: Some(format!("Can't watch {}: {}", dir.display(), e)),
+                    report: Some(EchoReport::ImportError(e.to_string())),
+                    level: LogLevel::WARN,
+                });
+            }
+        }
+    }
+
+    // downloads are imported by the download itself once they are complete
+    let staging = download::staging_dir(
+        &std::fs::canonicalize(&songs_dir).unwrap_or_else(|_| songs_dir.clone()),
+    );
+    let task = tokio::spawn(run(rx, pool, songs_dir, staging, mode, reporter));
+
+    Ok(LibraryWatcher {
+        _watcher: watcher,
+        dirs: watched,
+        task,
+    })
+}
+
+async fn run(
+    mut rx: UnboundedReceiver<notify::Result<Event>>,
+    pool: SqlitePool,
+    songs_dir: PathBuf,
+    staging: PathBuf,
+    mode: ImportMode,
+    reporter: Sender<Report>,
+) {
+    while let Some(event) = rx.recv().await {
+        let mut changes = Changes::default();
+        let mut is_closed = false;
+        let mut next = Some(event);
+        loop {
+            if let Some(Ok(event)) = next
+                && !event.paths.iter().all(|path| path.starts_with(&staging))
+            {
+                changes.add(event);
+            }
+            next = match tokio::time::timeout(SETTLE, rx.recv()).await {
+                Ok(Some(event)) => Some(event),
+                Ok(None) => {
+                    is_closed = true;
+                    break;
+                }
+                Err(_) => break,
+            };
+        }
+
+        sync(&pool, changes, &songs_dir, mode, &reporter).await;
+        if is_closed {
+            return;
+        }
+    }
+}
+
+async fn sync(
+    pool: &SqlitePool,
+    changes: Changes,
+    songs_dir: &Path,
+    mode: ImportMode,
+    reporter: &Sender<Report>,
+) {
+    let report = |log: String, report: Option<EchoReport>, level: LogLevel| {
+        let _ = reporter.send(Report {
+            log: Some(log),
+            report,
+            level,
+        });
+    };
+
+    let mut summary = Summary::default();
+    let mut paths = changes.paths;
+    for (from, to) in changes.renames {
+        match move_rows(pool, &from, &to).await {
+            Ok(0) => {
+                // a file that wasn't in the library, e.g. a temporary one
+                // renamed over a song by a tag editor
+                paths.insert(to);
+            }
+            Ok(moved) => summary.moved += moved,
+            Err(e) => report(format!("Library sync error: {}", e), Some(e), LogLevel::ERR),
+        }
+    }
+
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(import::collect_files(&path).unwrap_or_default());
+        } else if path.is_file() {
+            files.push(path);
+        } else {
+            match mark_missing(pool, &path).await {
+                Ok(missing) => summary.missing += missing,
+                Err(e) => report(format!("Library sync error: {}", e), Some(e), LogLevel::ERR),
+            }
+        }
+    }
+    files.sort();
+    files.dedup();
+
+    let total = files.len();
+    let mut added = Vec::new();
+    let mut last_progress: Option<Instant> = None;
+    for (done, file) in files.into_iter().enumerate() {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if last_progress.is_none_or(|at| at.elapsed() >= PROGRESS_EVERY) {
+            last_progress = Some(Instant::now());
+            report(
+                format!("INDEXING {}/{}: {}", done + 1, total, name),
+                None,
+                LogLevel::INFO,
+            );
+        }
+
+        match refresh(pool, &file).await {
+            Ok(Some(updated)) => {
+                summary.updated += updated as usize;
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                report(format!("Library sync error: {}", e), Some(e), LogLevel::ERR);
+                continue;
+            }
+        }
+
+        if mode != ImportMode::Reference && import::is_library_file(&file, songs_dir) {
+            continue;
+        }
+        match import::import_file(pool, &file, songs_dir, mode).await {
+            ImportResult::Imported { id, path, .. } => {
+                summary.added += 1;
+                added.push((id, path.to_string_lossy().to_string()));
+            }
+            ImportResult::Failed(e) => report(
+                format!("Import failed: {}: {}", name, e),
+                Some(EchoReport::ImportError(e)),
+                LogLevel::ERR,
+            ),
+            ImportResult::Skipped(_) => {}
+        }
+    }
+
+    if !summary.is_empty() {
+        report(summary.to_string(), None, LogLevel::INFO);
+    }
+    if let Err(e) = Library::scan_loudness(pool, added).await {
+        report(
+            format!("Loudness scan error: {}", e),
+            Some(e),
+            LogLevel::ERR,
+        );
+    }
+}
+
+/// Read the tags of an indexed file again, whether its row changed. `None`
+/// when `file` has no row.
+async fn refresh(pool: &SqlitePool, file: &Path) -> EchoResult<Option<bool>> {
+    // moved in files are stored as they are named, referenced ones canonical
+    let canonical = std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    let (Some(path), Some(canonical)) = (file.to_str(), canonical.to_str()) else {
+        return Ok(None);
+    };
+    let Some(row) = sqlx::query!(
+        "SELECT id AS \"id!\", title, file_path, file_hash FROM songs WHERE file_path IN (?, ?)",
+        path,
+        canonical
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let stored = row.file_path.clone();
+    let metadata = tokio::task::spawn_blocking(move || Metadata::from_path(&stored).ok())
+        .await
+        .map_err(|e| EchoReport::ImportError(e.to_string()))?;
+    // a file that can't be read right now keeps its row as it is
+    let Some(mut metadata) = metadata else {
+        return Ok(Some(false));
+    };
+    if metadata.title.is_empty() || metadata.title == "Unknown" {
+        metadata.title = row.title.unwrap_or_default();
+    }
+
+    // only rows that actually changed count, echo's own moves and tag
+    // writes come back as events too
+    let updated = sqlx::query!(
+        "UPDATE songs SET title = ?, artist = ?, album = ?, year = ?, genre = ?, track_number = ?, total_tracks = ?, disc_number = ?, total_discs = ?, album_artist = ?, is_missing = 0
+        WHERE id = ? AND (title IS NOT ? OR artist IS NOT ? OR album IS NOT ? OR year IS NOT ? OR genre IS NOT ? OR track_number IS NOT ? OR total_tracks IS NOT ? OR disc_number IS NOT ? OR total_discs IS NOT ? OR album_artist IS NOT ? OR is_missing != 0)",
+        metadata.title,
+        metadata.artist,
+        metadata.album,
+        metadata.year,
+        metadata.genre,
+        metadata.track_number,
+        metadata.total_tracks,
+        metadata.disc_number,
+        metadata.total_discs,
+        metadata.album_artist,
+        row.id,
+        metadata.title,
+        metadata.artist,
+        metadata.album,
+        metadata.year,
+        metadata.genre,
+        metadata.track_number,
+        metadata.total_tracks,
+        metadata.disc_number,
+        metadata.total_discs,
+        metadata.album_artist,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    // hashing reads the whole file, only worth it when the file changed
+    if updated > 0 || row.file_hash.is_none() {
+        let _ = import::store_hash(pool, row.id, Path::new(&row.file_path)).await;
+    }
+    Ok(Some(updated > 0))
+}
+
+/// Point the rows of `from`, or of the files under it, and their playlist
+/// entries and bookmarks at `to`.
+pub(super) async fn move_rows(pool: &SqlitePool, from: &Path, to: &Path) -> EchoResult<u64> {
+    let (Some(from), Some(to)) = (from.to_str(), to.to_str()) else {
+        return Ok(0);
+    };
+    let under = format!("{}/%", escape_like(from));
+    // sqlite counts characters, not bytes
+    let rest = from.chars().count() as i64 + 1;
+
+    let mut tx = pool.begin().await?;
+    let moved = sqlx::query!(
+        "UPDATE songs SET file_path = ? || substr(file_path, ?), is_missing = 0
+        WHERE file_path = ? OR file_path LIKE ? ESCAPE '\\'",
+        to,
+        rest,
+        from,
+        under
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    sqlx::query!(
+        "UPDATE playlist_songs SET song_path = ? || substr(song_path, ?)
+        WHERE song_path = ? OR song_path LIKE ? ESCAPE '\\'",
+        to,
+        rest,
+        from,
+        under
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "UPDATE bookmarks SET song_path = ? || substr(song_path, ?)
+        WHERE song_path = ? OR song_path LIKE ? ESCAPE '\\'",
+        to,
+        rest,
+        from,
+        under
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(moved)
+}
+
+/// Mark the row of `path`, or the rows of the files that were under it.
+async fn mark_missing(pool: &SqlitePool, path: &Path) -> EchoResult<u64> {
+    let Some(path) = path.to_str() else {
+        return Ok(0);
+    };
+    let under = format!("{}/%", escape_like(path));
+
+    let missing = sqlx::query!(
+        "UPDATE songs SET is_missing = 1
+        WHERE is_missing = 0 AND (file_path = ? OR file_path LIKE ? ESCAPE '\\')",
+        path,
+        under
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(missing)
+}
+
+fn escape_like(path: &str) -> String {
+    path.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TestLibrary, song_wav};
+
+    #[tokio::test]
+    async fn downloads_in_progress_are_left_to_the_download() {
+        let TestLibrary {
+            dir: _dir,
+            songs,
+            pool,
+        } = TestLibrary::new("echo_watch_staging").await;
+        let staging = download::staging_dir(&songs);
+        std::fs::create_dir_all(&staging).unwrap();
+        let (reporter, _reports) = std::sync::mpsc::channel();
+        let _watcher = watch(
+            pool.clone(),
+            std::slice::from_ref(&songs),
+            songs.clone(),
+            ImportMode::Move,
+            reporter,
+        )
+        .unwrap();
+
+        song_wav(&staging.join("downloading.wav"));
+        song_wav(&songs.join("dropped.wav"));
+        tokio::time::sleep(SETTLE * 2).await;
+
+        let rows: Vec<String> = sqlx::query_scalar!("SELECT file_path FROM songs")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(rows.len(), 1, "{:?}", rows);
+        assert!(!rows[0].contains(".downloads"));
+        assert!(staging.join("downloading.wav").exists());
+    }
+
+    #[tokio::test]
+    async fn unchanged_rows_keep_their_hash() {
+        let TestLibrary {
+            dir: _dir,
+            songs,
+            pool,
+        } = TestLibrary::new("echo_watch_hash").await;
+        let file = songs.join("song.wav");
+        song_wav(&file);
+        let ImportResult::Imported { id, path, .. } =
+            import::import_file(&pool, &file, &songs, ImportMode::Move).await
+        else {
+            panic!("not imported");
+        };
+        let hash = |pool: SqlitePool| async move {
+            sqlx::query_scalar!("SELECT file_hash FROM songs WHERE id = ?", id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+
+        // a stand-in, only a new hash would replace it
+        sqlx::query!("UPDATE songs SET file_hash = 'kept' WHERE id = ?", id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(refresh(&pool, &path).await.unwrap(), Some(false));
+        assert_eq!(hash(pool.clone()).await.as_deref(), Some("kept"));
+
+        sqlx::query!("UPDATE songs SET file_hash = NULL WHERE id = ?", id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        refresh(&pool, &path).await.unwrap();
+        assert!(hash(pool.clone()).await.is_some_and(|hash| hash != "kept"));
+    }
+}
@@ -4,9 +4,19 @@ use tokio::process::Command;
 
 use crate::result::{EchoReport, EchoResult};
 
+/// Where downloads are written before they are imported, inside the songs
+/// folder so the import can rename them into place. The library watcher
+/// leaves it alone.
+pub fn staging_dir(songs_dir: &Path) -> PathBuf {
+    songs_dir.join(".downloads")
+}
+
 /// Download audio from a YouTube URL as MP3 using yt-dlp.
 /// Returns the path to the downloaded file.
 pub async fn download_mp3(url: &str, output_dir: &Path) -> EchoResult<PathBuf> {
+    tokio::fs::create_dir_all(output_dir).await.map_err(|e| {
+        EchoReport::DownloadError(format!("Can't create {}: {}", output_dir.display(), e))
+    })?;
     let output_template = output_dir.join("%(title)s.%(ext)s");
 
     let output = Command::new("yt-dlp")
@@ -1,34 +1,80 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use tokio::fs;
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
+};
+use ratatui::layout::Position;
 
-use crate::app::{DownloadState, LogLevel, PlaylistSubTab, Report};
-use crate::awdio::AudioPlayer;
-use crate::awdio::metadata::Metadata;
+use crate::app::{DownloadState, LogLevel, PlaylistSubTab, Popup, Report, Visualizer};
+use crate::awdio::device::{self, OutputTarget};
+use crate::awdio::export;
 use crate::awdio::song::Song;
+use crate::awdio::stretch::{MAX_SPEED, MIN_SPEED, SpeedMode};
+use crate::awdio::waveform::Waveform;
+use crate::awdio::{AudioPlayer, current_timestamp};
 use crate::db;
+use crate::db::library::{
+    self, Library,
+    doctor::{self, Fix},
+    import::{self, ImportMode, ImportResult},
+    watch,
+};
 use crate::download;
 use crate::result::{EchoReport, EchoResult};
+use crate::timer::{FadeIn, SleepTimer};
 use crate::ui::EchoCanvas;
-use crate::{app::SelectedTab, awdio::AudioData, awdio::skip};
+use crate::{
+    app::SelectedTab,
+    awdio::{AudioData, SeekTarget, seek, skip},
+};
 
 mod echo;
 
+// enough to pick from, the list isn't paged
+const SEARCH_RESULTS: usize = 100;
+
 impl EchoCanvas {
     pub async fn handle_events(&mut self, evt: Event) -> EchoResult<()> {
         let exit = match evt {
             Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
                 self.handle_key_event(key_event).await
             }
+            Event::Mouse(mouse_event) => self.handle_mouse_event(mouse_event),
             _ => Ok(()),
         };
 
         exit
     }
 
+    /// A left click on the waveform overview seeks to that spot.
+    fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> EchoResult<()> {
+        if mouse_event.kind != MouseEventKind::Down(MouseButton::Left) {
+            return Ok(());
+        }
+
+        let area = self.state.waveform_area.get();
+        if area.width == 0 || !area.contains(Position::new(mouse_event.column, mouse_event.row)) {
+            return Ok(());
+        }
+
+        let fraction = ((mouse_event.column - area.x) as f64 + 0.5) / area.width as f64;
+        self.state.echo_tab_state.waveform_cursor = None;
+        self.seek_audio(SeekTarget::Percent(fraction * 100.0))
+    }
+
     async fn handle_key_event(&mut self, key_event: KeyEvent) -> EchoResult<()> {
+        match self.state.popup {
+            Some(Popup::Output) => {
+                self.handle_output_picker_key_event(key_event);
+                return Ok(());
+            }
+            Some(Popup::Bookmarks) => return self.handle_bookmarks_key_event(key_event).await,
+            Some(Popup::Doctor) => return self.handle_doctor_key_event(key_event).await,
+            None => {}
+        }
+
         match key_event.code {
             KeyCode::Esc => {
                 // If we're in an input mode, cancel it; otherwise exit
@@ -53,11 +99,9 @@ impl EchoCanvas {
                             return Ok(());
                         }
                     }
-                    SelectedTab::Echo => {
-                        if self.is_any_echo_buffer_active() {
-                            self.deavtivate_all_echo_buffer();
-                            return Ok(());
-                        }
+                    SelectedTab::Echo if self.is_any_echo_buffer_active() => {
+                        self.deavtivate_all_echo_buffer();
+                        return Ok(());
                     }
                     _ => {}
                 }
@@ -80,7 +124,7 @@ impl EchoCanvas {
             SelectedTab::Echo => echo::main_events::handle_echo_key_event(self, key_event).await?,
             SelectedTab::Download => self.handle_download_key_event(key_event).await?,
             SelectedTab::Playlist => self.handle_playlist_key_event(key_event).await?,
-            _ => {}
+            SelectedTab::Misc => self.handle_misc_key_event(key_event).await?,
         }
         Ok(())
     }
@@ -110,57 +154,37 @@ impl EchoCanvas {
                             level: LogLevel::INFO,
                         });
 
-                        match download::download_mp3(&url, &songs_dir).await {
+                        let staging = download::staging_dir(&songs_dir);
+                        match download::download_mp3(&url, &staging).await {
                             Ok(downloaded_path) => {
-                                let path_str = downloaded_path.to_str().unwrap_or("").to_string();
-
-                                // Read metadata from the downloaded file
-                                let metadata = Metadata::from_path(&path_str).unwrap_or_default();
-
-                                // Insert into DB
-                                match db::insert_song(&pool, &metadata, &path_str).await {
-                                    Ok(id) => {
-                                        // Rename to {id}.mp3
-                                        let new_name = format!("{}.mp3", id);
-                                        let new_path = songs_dir.join(&new_name);
-
-                                        if let Err(e) =
-                                            tokio::fs::rename(&downloaded_path, &new_path).await
-                                        {
-                                            let _ = reporter.send(Report {
-                                                log: Some(format!("Rename error: {}", e)),
-                                                report: None,
-                                                level: LogLevel::ERR,
-                                            });
-                                            return;
-                                        }
-
-                                        if let Some(new_path_str) = new_path.to_str() {
-                                            // Update file_path in DB
-                                            let _ =
-                                                db::update_song_path(&pool, id, new_path_str).await;
-
-                                            // Update tags in the renamed file
-                                            let _ = metadata.update_file(new_path_str);
-                                        }
-
-                                        let _ = reporter.send(Report {
-                                            log: Some(format!(
-                                                "Downloaded: {} (id={})",
-                                                metadata.title, id
-                                            )),
+                                let report = match import::import_file(
+                                    &pool,
+                                    &downloaded_path,
+                                    &songs_dir,
+                                    // the staged file is ours to move
+                                    ImportMode::Move,
+                                )
+                                .await
+                                {
+                                    ImportResult::Imported { id, title, path } => {
+                                        let _ = Library::scan_loudness(
+                                            &pool,
+                                            vec![(id, path.to_string_lossy().to_string())],
+                                        )
+                                        .await;
+                                        Report {
+                                            log: Some(format!("Downloaded: {} (id={})", title, id)),
                                             report: None,
                                             level: LogLevel::INFO,
-                                        });
-                                    }
-                                    Err(e) => {
-                                        let _ = reporter.send(Report {
-                                            log: Some(format!("DB insert error: {}", e)),
-                                            report: None,
-                                            level: LogLevel::ERR,
-                                        });
+                                        }
                                     }
-                                }
+                                    ImportResult::Skipped(e) | ImportResult::Failed(e) => Report {
+                                        log: Some(format!("Import failed: {}", e)),
+                                        report: Some(EchoReport::ImportError(e)),
+                                        level: LogLevel::ERR,
+                                    },
+                                };
+                                let _ = reporter.send(report);
                             }
                             Err(e) => {
                                 let _ = reporter.send(Report {
@@ -327,7 +351,7 @@ impl EchoCanvas {
                         let pid = playlist.id;
                         match db::get_playlist_song_paths(&pool, pid).await {
                             Ok(paths) => {
-                                let songs: Vec<Song> = paths
+                                let mut songs: Vec<Song> = paths
                                     .iter()
                                     .filter_map(|p| {
                                         if std::path::Path::new(p).exists() {
@@ -337,6 +361,17 @@ impl EchoCanvas {
                                         }
                                     })
                                     .collect();
+                                let _ = Library::fill_playback_info(&pool, &mut songs).await;
+                                if songs.len() < paths.len() {
+                                    let _ = self.state.report_tx.send(Report {
+                                        log: Some(format!(
+                                            "{} SONGS CAN'T BE FOUND · c ON ECHO: DOCTOR",
+                                            paths.len() - songs.len()
+                                        )),
+                                        report: None,
+                                        level: LogLevel::WARN,
+                                    });
+                                }
                                 self.state.playlist_songs = songs;
                                 self.state.selected_playlist_song_idx = 0;
                                 self.state.playlist_subtab = PlaylistSubTab::Songs;
@@ -402,6 +437,11 @@ impl EchoCanvas {
                                                 }
                                             })
                                             .collect();
+                                        let _ = Library::fill_playback_info(
+                                            &pool,
+                                            &mut self.state.playlist_songs,
+                                        )
+                                        .await;
                                     }
                                     if self.state.selected_playlist_song_idx > 0 {
                                         self.state.selected_playlist_song_idx -= 1;
@@ -423,35 +463,19 @@ impl EchoCanvas {
                         }
                     }
                 }
-                KeyCode::Enter => {
-                    // Play selected song
-                    if let Some(song) = self
+                KeyCode::Enter if !self.state.playlist_songs.is_empty() => {
+                    // Queue the playlist from the selected song
+                    let origin = self
                         .state
-                        .playlist_songs
-                        .get(self.state.selected_playlist_song_idx)
-                    {
-                        let reporter = self.state.report_tx.clone();
-                        let audio_player = match AudioPlayer::new(&song.path) {
-                            Ok(player) => player,
-                            Err(e) => {
-                                reporter
-                                    .send(Report {
-                                        log: Some(e.to_string()),
-                                        report: Some(EchoReport::LockPoisoned(e.to_string())),
-                                        level: LogLevel::ERR,
-                                    })
-                                    .ok();
-                                AudioPlayer::bad()
-                            }
-                        };
-                        self.state.active_track = song.clone();
-                        self.audio_player = audio_player;
-                        let mut audio_state = Some(self.audio_player.state.clone());
-                        if let Err(_) = self.audio_player.play() {
-                            audio_state = None;
-                        }
-                        self.audio_state = audio_state;
-                    }
+                        .playlists
+                        .get(self.state.selected_playlist_idx)
+                        .map(|pl| pl.name.clone())
+                        .unwrap_or_else(|| "PLAYLIST".into());
+                    self.play_queue(
+                        self.state.playlist_songs.clone(),
+                        self.state.selected_playlist_song_idx,
+                        origin,
+                    );
                 }
                 KeyCode::Backspace => {
                     self.state.playlist_subtab = PlaylistSubTab::List;
@@ -464,6 +488,975 @@ impl EchoCanvas {
         Ok(())
     }
 
+    // ── Playback ─────────────────────────────────────────────────
+
+    /// Build a fresh player for `song` and start it. Returns false if the
+    /// file could not be opened or the output stream failed.
+    pub fn play_song(&mut self, song: Song) -> bool {
+        let reporter = self.state.report_tx.clone();
+
+        // reuse the running output stream whenever the file fits it
+        let gain = self.song_gain(&song);
+        match self.audio_player.load(&song.path, gain) {
+            Ok(true) => {
+                self.state.active_track = song;
+                self.load_waveform();
+                self.apply_eq();
+                self.prime_next_track();
+                return true;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                let _ = reporter.send(Report {
+                    log: Some(format!("Can't open '{}': {}", song.metadata.title, e)),
+                    report: Some(EchoReport::Audio(e.to_string())),
+                    level: LogLevel::ERR,
+                });
+                return false;
+            }
+        }
+
+        let mut audio_player = match AudioPlayer::new(&song.path, gain) {
+            Ok(player) => player,
+            Err(e) => {
+                let _ = reporter.send(Report {
+                    log: Some(format!("Can't open '{}': {}", song.metadata.title, e)),
+                    report: Some(EchoReport::Audio(e.to_string())),
+                    level: LogLevel::ERR,
+                });
+                self.audio_player = AudioPlayer::bad();
+                self.audio_state = None;
+                return false;
+            }
+        };
+
+        audio_player.set_crossfade(self.ui_config.playback().crossfade());
+        audio_player.set_ramp(self.ui_config.playback().ramp());
+        audio_player.set_spectrum(self.ui_config.spectrum().settings());
+        audio_player.set_speed(self.state.speed, self.state.speed_mode);
+        // a new player starts at the default volume, not where a fade is
+        if let Some(fade_in) = &self.state.fade_in
+            && let Ok(state) = audio_player.state.lock()
+        {
+            state
+                .transport
+                .set_volume(fade_in.volume().unwrap_or(fade_in.target));
+        }
+        if let Err(e) = audio_player.play(&self.state.output_target) {
+            let _ = reporter.send(Report {
+                log: Some(format!("Playback error: {}", e)),
+                report: Some(EchoReport::Audio(e.to_string())),
+                level: LogLevel::ERR,
+            });
+            self.audio_player = AudioPlayer::bad();
+            self.audio_state = None;
+            return false;
+        }
+
+        self.state.active_track = song;
+        self.audio_state = Some(audio_player.state.clone());
+        self.audio_player = audio_player;
+        self.load_waveform();
+        self.apply_eq();
+        self.apply_visualizer();
+        self.prime_next_track();
+        true
+    }
+
+    /// Work out the waveform overview of the active track in the background,
+    /// the header shows a flat bar until it is there.
+    fn load_waveform(&mut self) {
+        let track = &self.state.active_track;
+        let (path, id) = (track.path.clone(), track.id);
+        self.state.echo_tab_state.waveform_cursor = None;
+
+        let Ok(mut current) = self.state.waveform.lock() else {
+            return;
+        };
+        if current.path == path {
+            return;
+        }
+        // no peaks yet marks the overview as pending
+        *current = Waveform::pending(&path);
+        drop(current);
+
+        let waveform = self.state.waveform.clone();
+        let cache_dir = self.all_paths.waveforms.clone();
+        tokio::task::spawn_blocking(move || {
+            let loaded = Waveform::load(&path, id, &cache_dir);
+            // the track may have changed while this one was decoded
+            if let Ok(mut current) = waveform.lock()
+                && current.path == path
+            {
+                *current = loaded.unwrap_or_default();
+            }
+        });
+    }
+
+    /// Hand the song after the current one to the decoder for gapless playback.
+    fn prime_next_track(&self) {
+        let next = self
+            .state
+            .queue
+            .peek_next()
+            .map(|song| (song.path.clone(), self.song_gain(song)));
+        self.audio_player.set_next(next);
+    }
+
+    /// The linear gain `song` plays with under the configured ReplayGain mode.
+    fn song_gain(&self, song: &Song) -> f32 {
+        let playback = self.ui_config.playback();
+        song.gain
+            .linear(playback.replaygain, playback.replaygain_preamp)
+    }
+
+    /// Replace the play queue and start playing from `start`.
+    pub fn play_queue(&mut self, songs: Vec<Song>, start: usize, origin: impl Into<String>) {
+        self.state.queue.load(songs, start, origin);
+        if let Some(song) = self.state.queue.current().cloned()
+            && !self.play_song(song)
+        {
+            self.next_track();
+        }
+    }
+
+    /// Play the next playable song of the queue. Returns false at the end of it.
+    pub fn next_track(&mut self) -> bool {
+        while let Some(song) = self.state.queue.advance().cloned() {
+            if self.play_song(song) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn previous_track(&mut self) {
+        if let Some(song) = self.state.queue.retreat().cloned() {
+            self.play_song(song);
+        }
+    }
+
+    /// Called on every ui tick, moves on to the next song once the current one ended.
+    pub fn poll_playback(&mut self) {
+        let skipped = self.audio_player.take_skipped_packets();
+        if skipped > 0 {
+            let _ = self.state.report_tx.send(Report {
+                log: Some(format!("SKIPPED {} BAD PACKETS", skipped)),
+                report: Some(EchoReport::Audio(format!(
+                    "Skipped {} undecodable packets in {}",
+                    skipped, self.state.active_track.path
+                ))),
+                level: LogLevel::WARN,
+            });
+        }
+
+        if self.audio_player.take_track_change() {
+            // the decoder already moved on to the primed song
+            if let Some(song) = self.state.queue.advance().cloned() {
+                self.state.active_track = song;
+            }
+            self.load_waveform();
+            self.apply_eq();
+            self.prime_next_track();
+            return;
+        }
+
+        if !self.audio_player.has_ended() {
+            return;
+        }
+
+        if !self.next_track() {
+            // keep `audio_state` around so the header still shows the last track
+            self.audio_player = AudioPlayer::bad();
+            let _ = self.state.report_tx.send(Report {
+                log: Some("QUEUE FINISHED".into()),
+                report: None,
+                level: LogLevel::INFO,
+            });
+        }
+    }
+
+    pub async fn queue_selected_album(&mut self) -> EchoResult<()> {
+        let Some(song) = self.state.local_songs.get(self.state.selected_song_pos) else {
+            return Ok(());
+        };
+        let selected_path = song.path.clone();
+        let album = song.metadata.album.clone();
+        if !library::is_album(&album) {
+            let _ = self.state.report_tx.send(Report {
+                log: Some("NO ALBUM TAG TO QUEUE".into()),
+                report: None,
+                level: LogLevel::WARN,
+            });
+            return Ok(());
+        }
+
+        let songs = Library::get_album_songs(
+            &self.db_connection_pool,
+            &album,
+            &song.metadata.album_artist,
+        )
+        .await?;
+        let start = songs
+            .iter()
+            .position(|s| s.path == selected_path)
+            .unwrap_or(0);
+        self.play_queue(songs, start, album);
+
+        Ok(())
+    }
+
+    /// Import everything under `sources` into the library in the background,
+    /// the way the `[library]` section says. The import tab shows how far it
+    /// got.
+    pub fn start_import(&self, sources: Vec<PathBuf>) {
+        let pool = self.db_connection_pool.clone();
+        let songs_dir = self.all_paths.songs.clone();
+        let mode = self.ui_config.library().mode;
+        let progress = self.state.echo_tab_state.import_progress.clone();
+        let reporter = self.state.report_tx.clone();
+
+        tokio::spawn(async move {
+            import::import(&pool, &sources, &songs_dir, mode, &progress, &reporter).await;
+        });
+    }
+
+    /// What `i` imports: the library roots when they are referenced in
+    /// place, files dropped into the songs folder otherwise.
+    pub fn import_library(&self) {
+        let library = self.ui_config.library();
+        if library.mode != ImportMode::Reference {
+            self.start_import(vec![self.all_paths.songs.clone()]);
+            return;
+        }
+
+        let roots = library.roots();
+        if roots.is_empty() {
+            let _ = self.state.report_tx.send(Report {
+                log: Some("NO LIBRARY ROOTS IN [library]".into()),
+                report: None,
+                level: LogLevel::WARN,
+            });
+            return;
+        }
+        self.start_import(roots);
+    }
+
+    /// Start following the songs folder, and the roots when they are
+    /// referenced in place, unless `watch` of the `[library]` section is off.
+    pub fn watch_library(&mut self) {
+        let library = self.ui_config.library();
+        if library.watch == Some(false) {
+            return;
+        }
+
+        let mut dirs = vec![self.all_paths.songs.clone()];
+        if library.mode == ImportMode::Reference {
+            dirs.extend(library.roots());
+        }
+        match watch::watch(
+            self.db_connection_pool.clone(),
+            &dirs,
+            self.all_paths.songs.clone(),
+            library.mode,
+            self.state.report_tx.clone(),
+        ) {
+            Ok(watcher) => self.state.library_watcher = Some(watcher),
+            Err(e) => {
+                let _ = self.state.report_tx.send(Report {
+                    log: Some(format!("Library watch error: {}", e)),
+                    report: Some(e),
+                    level: LogLevel::ERR,
+                });
+            }
+        }
+    }
+
+    /// Fill the song list with what matches the search buffer, or with the
+    /// first songs of the library while it is empty.
+    pub async fn search_library(&mut self) -> EchoResult<()> {
+        let query = self.state.echo_tab_state.search_buffer.trim();
+        self.state.local_songs = if query.is_empty() {
+            Library::get_songs_from_db(&self.db_connection_pool, 0, 10).await?
+        } else {
+            Library::search(&self.db_connection_pool, query, SEARCH_RESULTS).await?
+        };
+        self.state.selected_song_pos = 0;
+        Ok(())
+    }
+
+    /// Write the selected local song to the export folder in the background,
+    /// as the `[export]` section says.
+    pub fn export_selected_song(&self) {
+        let Some(song) = self.state.local_songs.get(self.state.selected_song_pos) else {
+            return;
+        };
+        let song = song.clone();
+
+        let config = self.ui_config.export();
+        let mut options = config.options();
+        if config.replaygain {
+            options.gain = Some(self.song_gain(&song));
+        }
+        if config.eq {
+            options.eq = self.state.eq.bands_for(&song);
+        }
+        let dest = config
+            .dir(&self.all_paths.exports)
+            .join(export::file_name(&song, options.format));
+
+        let reporter = self.state.report_tx.clone();
+        let _ = reporter.send(Report {
+            log: Some(format!("EXPORTING: {}", song.metadata.title)),
+            report: None,
+            level: LogLevel::INFO,
+        });
+        tokio::task::spawn_blocking(move || {
+            let report = match export::export(&song, &dest, &options) {
+                Ok(()) => Report {
+                    log: Some(format!("EXPORTED: {}", dest.display())),
+                    report: None,
+                    level: LogLevel::INFO,
+                },
+                Err(e) => Report {
+                    log: Some(format!("Export error: {}", e)),
+                    report: Some(EchoReport::Audio(e.to_string())),
+                    level: LogLevel::ERR,
+                },
+            };
+            let _ = reporter.send(report);
+        });
+    }
+
+    // ── Misc tab ─────────────────────────────────────────────────
+
+    async fn handle_misc_key_event(&mut self, key_event: KeyEvent) -> EchoResult<()> {
+        match key_event.code {
+            KeyCode::Char('t') => return self.cycle_sleep_timer(),
+            KeyCode::Char('A') => {
+                self.toggle_alarm();
+                return Ok(());
+            }
+            KeyCode::Char('h') => self.state.alarm.shift(-5),
+            KeyCode::Char('l') => self.state.alarm.shift(5),
+            KeyCode::Char('p') => self.cycle_alarm_playlist(),
+            _ => {}
+        }
+
+        let eq = &mut self.state.eq;
+        match key_event.code {
+            KeyCode::Char('a') => eq.previous_band(),
+            KeyCode::Char('d') => eq.next_band(),
+            KeyCode::Char('w') => eq.adjust_gain(Some(0.5)),
+            KeyCode::Char('s') => eq.adjust_gain(Some(-0.5)),
+            KeyCode::Char('0') => eq.adjust_gain(None),
+            KeyCode::Char('[') => eq.previous_preset(),
+            KeyCode::Char(']') => eq.next_preset(),
+            KeyCode::Char('e') => eq.is_enabled = !eq.is_enabled,
+            KeyCode::Char('m') => {
+                let preset = eq.preset_name().to_string();
+                self.pin_eq_preset(Some(preset)).await?;
+            }
+            KeyCode::Char('u') => self.pin_eq_preset(None).await?,
+            _ => return Ok(()),
+        }
+
+        self.apply_eq();
+        Ok(())
+    }
+
+    /// Pin `preset` to the playing song, or let it follow the selected
+    /// preset again with `None`.
+    async fn pin_eq_preset(&mut self, preset: Option<String>) -> EchoResult<()> {
+        let path = self.state.active_track.path.clone();
+        if path.is_empty() {
+            return Ok(());
+        }
+
+        Library::set_eq_preset(&self.db_connection_pool, &path, preset.as_deref()).await?;
+
+        let songs = std::iter::once(&mut self.state.active_track)
+            .chain(self.state.local_songs.iter_mut())
+            .filter(|song| song.path == path);
+        for song in songs {
+            song.eq_preset = preset.clone();
+        }
+
+        let _ = self.state.report_tx.send(Report {
+            log: Some(match &preset {
+                Some(name) => format!(
+                    "EQ: '{}' PINNED TO {}",
+                    name, self.state.active_track.metadata.title
+                ),
+                None => format!("EQ: UNPINNED {}", self.state.active_track.metadata.title),
+            }),
+            report: None,
+            level: LogLevel::INFO,
+        });
+
+        Ok(())
+    }
+
+    /// Hand the bands of the playing song to the decoder.
+    fn apply_eq(&self) {
+        let bands = self.state.eq.bands_for(&self.state.active_track);
+        self.audio_player.set_eq(bands);
+    }
+
+    // ── Sleep timer and alarm ────────────────────────────────────
+
+    fn cycle_sleep_timer(&mut self) -> EchoResult<()> {
+        let fade = self.ui_config.timer().sleep_fade();
+        let previous = self.state.sleep_timer.take();
+        // a timer that was fading leaves the volume where it started
+        if let Some(base) = previous.as_ref().and_then(|timer| timer.base_volume) {
+            self.with_audio_state(|state| state.transport.set_volume(base))?;
+        }
+
+        self.state.sleep_timer = SleepTimer::cycle(previous.as_ref(), fade);
+        self.state.sleep_remaining = None;
+
+        let _ = self.state.report_tx.send(Report {
+            log: Some(match &self.state.sleep_timer {
+                Some(timer) => format!("SLEEP: {}", timer.label()),
+                None => "SLEEP: OFF".into(),
+            }),
+            report: None,
+            level: LogLevel::INFO,
+        });
+        Ok(())
+    }
+
+    fn toggle_alarm(&mut self) {
+        let alarm = &mut self.state.alarm;
+        let log = if alarm.is_armed() {
+            alarm.disarm();
+            "ALARM: OFF".to_string()
+        } else if alarm.playlist_id.is_none() {
+            "ALARM: PICK A PLAYLIST WITH p".to_string()
+        } else {
+            alarm.arm();
+            format!("ALARM: {}", alarm.label())
+        };
+
+        let _ = self.state.report_tx.send(Report {
+            log: Some(log),
+            report: None,
+            level: LogLevel::INFO,
+        });
+    }
+
+    fn cycle_alarm_playlist(&mut self) {
+        let playlists = &self.state.playlists;
+        if playlists.is_empty() {
+            return;
+        }
+
+        let next = match self.state.alarm.playlist_id {
+            Some(id) => playlists
+                .iter()
+                .position(|playlist| playlist.id == id)
+                .map_or(0, |i| (i + 1) % playlists.len()),
+            None => 0,
+        };
+        self.state.alarm.playlist_id = Some(playlists[next].id);
+    }
+
+    /// Run the fades of the sleep timer and the alarm, and start the alarm
+    /// playlist once it is due.
+    pub async fn poll_timers(&mut self) -> EchoResult<()> {
+        if let Some(fade_in) = &self.state.fade_in {
+            let (volume, is_done) = match fade_in.volume() {
+                Some(volume) => (volume, false),
+                None => (fade_in.target, true),
+            };
+            self.with_audio_state(|state| state.transport.set_volume(volume))?;
+            if is_done {
+                self.state.fade_in = None;
+            }
+        }
+
+        if self.state.alarm.is_due() {
+            self.start_alarm().await?;
+        }
+
+        self.poll_sleep_timer()
+    }
+
+    fn poll_sleep_timer(&mut self) -> EchoResult<()> {
+        let Some(timer) = self.state.sleep_timer.as_mut() else {
+            return Ok(());
+        };
+
+        let mut playback = None;
+        if let Some(audio_state) = &self.audio_state {
+            let state = audio_state
+                .lock()
+                .map_err(|e| EchoReport::LockPoisoned(e.to_string()))?;
+            let duration = state.duration_frames();
+            // the track runs `speed` times faster than the wall clock
+            let track_left = (duration > 0 && !state.is_finished).then(|| {
+                let frames = duration.saturating_sub(state.position_frames());
+                let seconds = frames as f64 / state.sample_rate.max(1) as f64;
+                std::time::Duration::from_secs_f64(seconds / state.speed.max(0.1) as f64)
+            });
+            playback = Some((
+                track_left,
+                state.transport.volume(),
+                state.transport.is_paused(),
+            ));
+        }
+        let (track_left, volume, is_paused) = playback.unwrap_or((None, 0.0, true));
+
+        let is_album_end = match self.state.queue.peek_next() {
+            Some(next) => next.metadata.album != self.state.active_track.metadata.album,
+            None => true,
+        };
+        let remaining = timer.remaining(track_left, is_album_end);
+        self.state.sleep_remaining = remaining;
+
+        let Some(remaining) = remaining else {
+            return Ok(());
+        };
+        if is_paused && !remaining.is_zero() {
+            return Ok(());
+        }
+
+        // a tick or two early, so the end of a track is never missed
+        if remaining > std::time::Duration::from_millis(200) {
+            let factor = timer.fade_factor(remaining);
+            if factor < 1.0 {
+                let base = *timer.base_volume.get_or_insert(volume);
+                self.with_audio_state(|state| state.transport.set_volume(base * factor))?;
+            }
+            return Ok(());
+        }
+        let base = timer.base_volume.unwrap_or(volume);
+
+        // faded out, pause and leave the volume where it was for tomorrow
+        self.with_audio_state(|state| {
+            if !state.transport.is_paused() {
+                state.transport.toggle_pause();
+            }
+            state.transport.set_volume(base);
+        })?;
+        self.state.sleep_timer = None;
+        self.state.sleep_remaining = None;
+
+        let _ = self.state.report_tx.send(Report {
+            log: Some("SLEEP: PAUSED".into()),
+            report: None,
+            level: LogLevel::INFO,
+        });
+        Ok(())
+    }
+
+    async fn start_alarm(&mut self) -> EchoResult<()> {
+        let reporter = self.state.report_tx.clone();
+        let Some(playlist) = self
+            .state
+            .playlists
+            .iter()
+            .find(|playlist| Some(playlist.id) == self.state.alarm.playlist_id)
+            .cloned()
+        else {
+            let _ = reporter.send(Report {
+                log: Some("ALARM: PLAYLIST NOT FOUND".into()),
+                report: None,
+                level: LogLevel::WARN,
+            });
+            return Ok(());
+        };
+
+        let pool = self.db_connection_pool.clone();
+        let mut songs: Vec<Song> = db::get_playlist_song_paths(&pool, playlist.id)
+            .await?
+            .into_iter()
+            .filter(|p| Path::new(p).exists())
+            .map(Song::new)
+            .collect();
+        if songs.is_empty() {
+            let _ = reporter.send(Report {
+                log: Some(format!("ALARM: '{}' HAS NO SONGS", playlist.name)),
+                report: None,
+                level: LogLevel::WARN,
+            });
+            return Ok(());
+        }
+        let _ = Library::fill_playback_info(&pool, &mut songs).await;
+
+        self.state.sleep_timer = None;
+        self.state.sleep_remaining = None;
+        // silent before the first sample plays, whether the running output
+        // takes the playlist or a new one is opened for it
+        self.state.fade_in = Some(FadeIn {
+            started: std::time::Instant::now(),
+            duration: self.state.alarm.fade,
+            target: self.state.alarm.volume,
+        });
+        self.with_audio_state(|state| state.transport.set_volume(0.0))?;
+        self.play_queue(songs, 0, format!("Alarm · {}", playlist.name));
+        self.with_audio_state(|state| {
+            if state.transport.is_paused() {
+                state.transport.toggle_pause();
+            }
+        })?;
+
+        let _ = reporter.send(Report {
+            log: Some(format!("ALARM: {}", playlist.name)),
+            report: None,
+            level: LogLevel::INFO,
+        });
+        Ok(())
+    }
+
+    // ── Output device ────────────────────────────────────────────
+
+    pub fn open_output_picker(&mut self) {
+        self.state.output_devices = device::list_outputs();
+        self.state.selected_output_idx = self
+            .state
+            .output_devices
+            .iter()
+            .position(|target| *target == self.state.output_target)
+            .unwrap_or(0);
+        self.state.popup = Some(Popup::Output);
+    }
+
+    fn handle_output_picker_key_event(&mut self, key_event: KeyEvent) {
+        match key_event.code {
+            KeyCode::Esc => self.state.popup = None,
+            KeyCode::Char('w') => {
+                self.state.selected_output_idx = self.state.selected_output_idx.saturating_sub(1);
+            }
+            KeyCode::Char('s') if !self.state.output_devices.is_empty() => {
+                self.state.selected_output_idx =
+                    (self.state.selected_output_idx + 1).min(self.state.output_devices.len() - 1);
+            }
+            KeyCode::Enter => {
+                if let Some(target) = self
+                    .state
+                    .output_devices
+                    .get(self.state.selected_output_idx)
+                    .cloned()
+                {
+                    self.switch_output(target);
+                }
+                self.state.popup = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn switch_output(&mut self, target: OutputTarget) {
+        let reporter = self.state.report_tx.clone();
+        match self.audio_player.switch_output(&target) {
+            Ok(()) => {
+                let _ = reporter.send(Report {
+                    log: Some(format!("OUTPUT: {}", target.label())),
+                    report: None,
+                    level: LogLevel::INFO,
+                });
+                self.state.output_target = target;
+            }
+            Err(e) => {
+                let _ = reporter.send(Report {
+                    log: Some(format!("Output switch error: {}", e)),
+                    report: Some(EchoReport::Audio(e.to_string())),
+                    level: LogLevel::ERR,
+                });
+                // fall back to where it played before
+                let _ = self.audio_player.switch_output(&self.state.output_target);
+            }
+        }
+    }
+
+    // ── A–B loop and bookmarks ───────────────────────────────────
+
+    /// Put A, or B with `is_b`, at the current position. Setting A starts a
+    /// new loop, B closes it and the player repeats between the two.
+    pub fn set_loop_point(&mut self, is_b: bool) -> EchoResult<()> {
+        let mut log = None;
+        self.with_audio_state(|state| {
+            let position = state.position_frames();
+            let readable = |frames: u64| current_timestamp(frames, state.sample_rate).0;
+
+            if !is_b {
+                state.loop_a = Some(position);
+                state.loop_b = None;
+                log = Some(format!("LOOP A: {}", readable(position)));
+                return;
+            }
+
+            let Some(a) = state.loop_a else {
+                log = Some("SET A BEFORE B".into());
+                return;
+            };
+            // B before A just swaps the two
+            let (a, b) = (a.min(position), a.max(position));
+            if a == b {
+                return;
+            }
+            state.loop_a = Some(a);
+            state.loop_b = Some(b.min(state.duration_frames().saturating_sub(1)).max(a + 1));
+            log = Some(format!("LOOP: {} → {}", readable(a), readable(b)));
+        })?;
+
+        if let Some(log) = log {
+            let _ = self.state.report_tx.send(Report {
+                log: Some(log),
+                report: None,
+                level: LogLevel::INFO,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn clear_loop(&mut self) -> EchoResult<()> {
+        self.with_audio_state(|state| {
+            state.loop_a = None;
+            state.loop_b = None;
+        })
+    }
+
+    /// Ask for a name for a bookmark at the current position.
+    pub fn open_bookmark_prompt(&mut self) -> EchoResult<()> {
+        if self.state.active_track.path.is_empty() {
+            return Ok(());
+        }
+
+        let mut position = 0.0;
+        self.with_audio_state(|state| {
+            position = state.position_frames() as f64 / state.sample_rate.max(1) as f64;
+        })?;
+
+        let tab_state = &mut self.state.echo_tab_state;
+        tab_state.bookmark_position = position;
+        tab_state.bookmark_buffer.clear();
+        tab_state.is_echo_bookmark_buffer_being_filled = true;
+        Ok(())
+    }
+
+    /// Put the waveform cursor on the playhead, or take it away again.
+    pub fn toggle_waveform_cursor(&mut self) -> EchoResult<()> {
+        if self.state.echo_tab_state.waveform_cursor.take().is_some()
+            || self.state.active_track.path.is_empty()
+        {
+            return Ok(());
+        }
+
+        let mut percent = 0.0;
+        self.with_audio_state(|state| {
+            let duration = state.duration_frames();
+            if duration > 0 {
+                percent = state.position_frames() as f64 / duration as f64 * 100.0;
+            }
+        })?;
+
+        self.state.echo_tab_state.waveform_cursor = Some(percent.clamp(0.0, 100.0));
+        Ok(())
+    }
+
+    pub async fn save_bookmark(&mut self, name: String) -> EchoResult<()> {
+        let position = self.state.echo_tab_state.bookmark_position;
+        let name = if name.trim().is_empty() {
+            current_timestamp(position as u64, 1).0
+        } else {
+            name.trim().to_string()
+        };
+
+        db::add_bookmark(
+            &self.db_connection_pool,
+            &self.state.active_track.path,
+            &name,
+            position,
+        )
+        .await?;
+
+        let _ = self.state.report_tx.send(Report {
+            log: Some(format!("BOOKMARK: {}", name)),
+            report: None,
+            level: LogLevel::INFO,
+        });
+        Ok(())
+    }
+
+    pub async fn open_bookmarks(&mut self) -> EchoResult<()> {
+        if self.state.active_track.path.is_empty() {
+            return Ok(());
+        }
+
+        self.state.bookmarks =
+            db::get_bookmarks(&self.db_connection_pool, &self.state.active_track.path).await?;
+        self.state.selected_bookmark_idx = 0;
+        self.state.popup = Some(Popup::Bookmarks);
+        Ok(())
+    }
+
+    async fn handle_bookmarks_key_event(&mut self, key_event: KeyEvent) -> EchoResult<()> {
+        match key_event.code {
+            KeyCode::Esc => self.state.popup = None,
+            KeyCode::Char('w') => {
+                self.state.selected_bookmark_idx =
+                    self.state.selected_bookmark_idx.saturating_sub(1);
+            }
+            KeyCode::Char('s') if !self.state.bookmarks.is_empty() => {
+                self.state.selected_bookmark_idx =
+                    (self.state.selected_bookmark_idx + 1).min(self.state.bookmarks.len() - 1);
+            }
+            KeyCode::Enter => {
+                if let Some(bookmark) = self.state.bookmarks.get(self.state.selected_bookmark_idx) {
+                    self.seek_audio(SeekTarget::Seconds(bookmark.position))?;
+                }
+                self.state.popup = None;
+            }
+            KeyCode::Char('x') => {
+                if self.state.selected_bookmark_idx < self.state.bookmarks.len() {
+                    let bookmark = self
+                        .state
+                        .bookmarks
+                        .remove(self.state.selected_bookmark_idx);
+                    db::delete_bookmark(&self.db_connection_pool, bookmark.id).await?;
+                    self.state.selected_bookmark_idx = self
+                        .state
+                        .selected_bookmark_idx
+                        .min(self.state.bookmarks.len().saturating_sub(1));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Check the library in the background and open the doctor, which lists
+    /// what is wrong with it once the check is done.
+    pub fn open_doctor(&mut self) {
+        self.state.popup = Some(Popup::Doctor);
+        if self.state.doctor_check.is_some() {
+            return;
+        }
+
+        let pool = self.db_connection_pool.clone();
+        let songs_dir = self.all_paths.songs.clone();
+        let roots = self.ui_config.library().roots();
+        self.state.doctor_issues.clear();
+        self.state.selected_issue_idx = 0;
+        self.state.is_doctor_delete_armed = false;
+        self.state.doctor_check = Some(tokio::spawn(async move {
+            doctor::diagnose(&pool, &songs_dir, &roots).await
+        }));
+    }
+
+    /// Called on every ui tick, fills the doctor once its check is done.
+    pub async fn poll_doctor(&mut self) {
+        if !self
+            .state
+            .doctor_check
+            .as_ref()
+            .is_some_and(|check| check.is_finished())
+        {
+            return;
+        }
+        let Some(check) = self.state.doctor_check.take() else {
+            return;
+        };
+
+        let report = match check
+            .await
+            .map_err(|e| EchoReport::ImportError(e.to_string()))
+            .and_then(|issues| issues)
+        {
+            Ok(issues) => {
+                let report = Report {
+                    log: Some(format!("DOCTOR: {} ISSUES", issues.len())),
+                    report: None,
+                    level: if issues.is_empty() {
+                        LogLevel::INFO
+                    } else {
+                        LogLevel::WARN
+                    },
+                };
+                self.state.doctor_issues = issues;
+                self.state.selected_issue_idx = 0;
+                report
+            }
+            Err(e) => Report {
+                log: Some(format!("Doctor error: {}", e)),
+                report: Some(e),
+                level: LogLevel::ERR,
+            },
+        };
+        let _ = self.state.report_tx.send(report);
+    }
+
+    async fn handle_doctor_key_event(&mut self, key_event: KeyEvent) -> EchoResult<()> {
+        // deleting takes a second `x`, anything else calls it off
+        let is_delete_confirmed = std::mem::take(&mut self.state.is_doctor_delete_armed);
+        let fix = match key_event.code {
+            KeyCode::Esc => {
+                self.state.popup = None;
+                return Ok(());
+            }
+            KeyCode::Char('w') => {
+                self.state.selected_issue_idx = self.state.selected_issue_idx.saturating_sub(1);
+                return Ok(());
+            }
+            KeyCode::Char('s') => {
+                self.state.selected_issue_idx = (self.state.selected_issue_idx + 1)
+                    .min(self.state.doctor_issues.len().saturating_sub(1));
+                return Ok(());
+            }
+            KeyCode::Char('r') => Fix::Relocate,
+            KeyCode::Char('x') if !is_delete_confirmed => {
+                self.state.is_doctor_delete_armed = self
+                    .state
+                    .doctor_issues
+                    .get(self.state.selected_issue_idx)
+                    .is_some();
+                return Ok(());
+            }
+            KeyCode::Char('x') => Fix::Delete,
+            KeyCode::Char('i') => Fix::Import,
+            _ => return Ok(()),
+        };
+        let Some(issue) = self.state.doctor_issues.get(self.state.selected_issue_idx) else {
+            return Ok(());
+        };
+
+        let report = match doctor::fix(
+            &self.db_connection_pool,
+            issue,
+            fix,
+            &self.all_paths.songs,
+            self.ui_config.library().mode,
+        )
+        .await
+        {
+            Ok(Some(done)) => {
+                self.state
+                    .doctor_issues
+                    .remove(self.state.selected_issue_idx);
+                self.state.selected_issue_idx = self
+                    .state
+                    .selected_issue_idx
+                    .min(self.state.doctor_issues.len().saturating_sub(1));
+                Report {
+                    log: Some(done),
+                    report: None,
+                    level: LogLevel::INFO,
+                }
+            }
+            Ok(None) => return Ok(()),
+            Err(e) => Report {
+                log: Some(format!("Doctor error: {}", e)),
+                report: Some(e),
+                level: LogLevel::ERR,
+            },
+        };
+        let _ = self.state.report_tx.send(report);
+        Ok(())
+    }
+
     fn skip_audio(&mut self, amount: f64) -> EchoResult<()> {
         let _ = self.with_audio_state(|state| {
             let _ = skip(state, amount);
@@ -472,12 +1465,69 @@ impl EchoCanvas {
         Ok(())
     }
 
+    pub fn seek_audio(&mut self, target: SeekTarget) -> EchoResult<()> {
+        self.with_audio_state(|state| {
+            let _ = seek(state, target);
+        })
+    }
+
     fn adjust_volume(&mut self, amount: f32) -> EchoResult<()> {
-        self.with_audio_state(|state| state.volume = (state.volume + amount).clamp(0.0, 1.0))
+        self.with_audio_state(|state| {
+            let volume = state.transport.volume();
+            state
+                .transport
+                .set_volume((volume + amount).clamp(0.0, 1.0));
+        })
+    }
+
+    fn adjust_speed(&mut self, amount: f32) {
+        // whole tenths, so repeated steps don't drift
+        let speed = ((self.state.speed + amount) * 10.0).round() / 10.0;
+        self.state.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.apply_speed();
+    }
+
+    fn toggle_speed_mode(&mut self) {
+        self.state.speed_mode = match self.state.speed_mode {
+            SpeedMode::Stretch => SpeedMode::Varispeed,
+            SpeedMode::Varispeed => SpeedMode::Stretch,
+        };
+        self.apply_speed();
+    }
+
+    fn apply_speed(&mut self) {
+        self.audio_player
+            .set_speed(self.state.speed, self.state.speed_mode);
+
+        let _ = self.state.report_tx.send(Report {
+            log: Some(format!(
+                "SPEED: {:.1}x ({})",
+                self.state.speed,
+                match self.state.speed_mode {
+                    SpeedMode::Stretch => "PITCH KEPT",
+                    SpeedMode::Varispeed => "VARISPEED",
+                }
+            )),
+            report: None,
+            level: LogLevel::INFO,
+        });
     }
 
     fn toggle_pause(&mut self) -> EchoResult<()> {
-        self.with_audio_state(|state| state.is_pause = !state.is_pause)
+        self.with_audio_state(|state| state.transport.toggle_pause())
+    }
+
+    fn cycle_visualizer(&mut self) {
+        let tab = &mut self.state.echo_tab_state;
+        tab.visualizer = tab.visualizer.next();
+        self.apply_visualizer();
+    }
+
+    /// Hide the panel and stop the analysis while the visualizer is off.
+    fn apply_visualizer(&mut self) {
+        let is_enabled = self.state.echo_tab_state.visualizer != Visualizer::Off;
+        self.state.echo_tab_state.is_fft_enable = is_enabled;
+        let _ = self.with_audio_state(|state| state.enable_fft_compute = is_enabled);
     }
 
     fn with_audio_state<F>(&self, f: F) -> EchoResult<()>
@@ -509,6 +1559,12 @@ impl EchoCanvas {
                 .state
                 .echo_tab_state
                 .is_echo_metadata_buffer_being_filled
+            || self.state.echo_tab_state.is_echo_seek_buffer_being_filled
+            || self
+                .state
+                .echo_tab_state
+                .is_echo_bookmark_buffer_being_filled
+            || self.state.echo_tab_state.waveform_cursor.is_some()
     }
 
     fn deavtivate_all_echo_buffer(&mut self) {
@@ -516,5 +1572,8 @@ impl EchoCanvas {
         state.is_echo_search_buffer_being_filled = false;
         state.is_echo_import_buffer_being_filled = false;
         state.is_echo_metadata_buffer_being_filled = false;
+        state.is_echo_seek_buffer_being_filled = false;
+        state.is_echo_bookmark_buffer_being_filled = false;
+        state.waveform_cursor = None;
     }
 }
@@ -1,33 +1,39 @@
-use tokio::fs;
-
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
 use crate::{
-    app::EchoSubTab, awdio::metadata::Metadata, event::echo::sub_events, result::EchoResult,
-    ui::EchoCanvas,
+    app::EchoSubTab, awdio::SeekTarget, event::echo::sub_events, result::EchoResult, ui::EchoCanvas,
 };
 
 pub async fn handle_echo_key_event(canvas: &mut EchoCanvas, key_event: KeyEvent) -> EchoResult<()> {
-    // later
-    // if canvas
-    //     .state
-    //     .echo_tab_state
-    //     .is_echo_metadata_buffer_being_filled
-    // {
-    //     return canvas.handle_echo_metadata_key_event(key_event).await;
-    // }
+    // a buffer being typed into takes every key, before any binding below
     if canvas
+        .state
+        .echo_tab_state
+        .is_echo_metadata_buffer_being_filled
+    {
+        return sub_events::handle_echo_metadata_key_event(canvas, key_event).await;
+    } else if canvas
         .state
         .echo_tab_state
         .is_echo_search_buffer_being_filled
     {
-        return sub_events::handle_echo_search_key_event(canvas, key_event);
+        return sub_events::handle_echo_search_key_event(canvas, key_event).await;
     } else if canvas
         .state
         .echo_tab_state
         .is_echo_import_buffer_being_filled
     {
         return sub_events::handle_echo_import_key_enent(canvas, key_event).await;
+    } else if canvas.state.echo_tab_state.is_echo_seek_buffer_being_filled {
+        return sub_events::handle_echo_seek_key_event(canvas, key_event);
+    } else if canvas
+        .state
+        .echo_tab_state
+        .is_echo_bookmark_buffer_being_filled
+    {
+        return sub_events::handle_echo_bookmark_key_event(canvas, key_event).await;
+    } else if canvas.state.echo_tab_state.waveform_cursor.is_some() {
+        return sub_events::handle_echo_waveform_key_event(canvas, key_event);
     }
 
     match (key_event.code, key_event.modifiers) {
@@ -47,104 +53,39 @@ pub async fn handle_echo_key_event(canvas: &mut EchoCanvas, key_event: KeyEvent)
             canvas.state.switch_echo_subtab('M');
         }
 
-        (KeyCode::Char('i'), KeyModifiers::NONE) => {
-            let pool = canvas.db_connection_pool.clone();
-            let song_path = canvas.all_paths.songs.clone();
-
-            tokio::spawn(async move {
-                let mut entries = match fs::read_dir(&song_path).await {
-                    Ok(e) => e,
-                    Err(e) => {
-                        eprintln!("read_dir error: {:?}", e);
-                        return;
-                    }
-                };
-
-                while let Ok(Some(entry)) = entries.next_entry().await {
-                    let old_path = entry.path();
-                    if old_path.is_dir() {
-                        continue;
-                    }
-                    if old_path.extension().and_then(|s| s.to_str()) != Some("mp3") {
-                        continue;
-                    }
-
-                    let stem = old_path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-                    if stem.parse::<i64>().is_ok() {
-                        continue;
-                    }
+        (KeyCode::Char('i'), KeyModifiers::NONE) => canvas.import_library(),
 
-                    let path_str = match old_path.to_str() {
-                        Some(p) => p,
-                        None => {
-                            eprintln!("invalid path encoding");
-                            continue;
-                        }
-                    };
-
-                    let mut tag = match Metadata::from_path(path_str) {
-                        Ok(t) => t,
-                        Err(e) => {
-                            eprintln!("metadata error: {:?}", e);
-                            continue;
-                        }
-                    };
-
-                    let db_title = if tag.title.is_empty() {
-                        stem
-                    } else {
-                        &tag.title
-                    };
-
-                    let id = match sqlx::query!(
-                        "INSERT INTO songs (title, artist, album, file_path) VALUES (?, ?, ?, ?)",
-                        db_title,
-                        tag.artist,
-                        tag.album,
-                        "PENDING"
-                    )
-                    .execute(&pool)
-                    .await
-                    {
-                        Ok(res) => res.last_insert_rowid(),
-                        Err(e) => {
-                            eprintln!("db insert error: {:?}", e);
-                            continue;
-                        }
-                    };
-
-                    let new_file_name = format!("{}.mp3", id);
-                    let new_path = song_path.join(&new_file_name);
-
-                    if let Err(e) = fs::rename(&old_path, &new_path).await {
-                        eprintln!("rename error: {:?}", e);
-                        continue;
-                    }
-
-                    tag.title = db_title.to_string();
-                    if let Some(new_path_str) = new_path.to_str() {
-                        let _ = tag.update_file(new_path_str);
-                        let _ = sqlx::query!(
-                            "UPDATE songs SET file_path = ? WHERE id = ?",
-                            new_path_str,
-                            id
-                        )
-                        .execute(&pool)
-                        .await;
-                    }
-                }
-            });
-        }
-
-        (KeyCode::Char('f'), _) => {
-            let mut ok = canvas.audio_player.state.lock().unwrap();
-            ok.enable_fft_compute = !ok.enable_fft_compute;
-        }
+        (KeyCode::Char('f'), _) => canvas.cycle_visualizer(),
         (KeyCode::Char('P') | KeyCode::Char('p'), _) => canvas.toggle_pause()?,
         (KeyCode::Char('K') | KeyCode::Char('k'), _) => canvas.adjust_volume(0.1)?,
         (KeyCode::Char('J') | KeyCode::Char('j'), _) => canvas.adjust_volume(-0.1)?,
+        (KeyCode::Char('+') | KeyCode::Char('='), _) => canvas.adjust_speed(0.1),
+        (KeyCode::Char('-'), _) => canvas.adjust_speed(-0.1),
+        (KeyCode::Char('v'), _) => canvas.toggle_speed_mode(),
         (KeyCode::Char('h'), _) => canvas.skip_audio(-1.0)?,
         (KeyCode::Char('l'), _) => canvas.skip_audio(1.0)?,
+        (KeyCode::Char('g'), _) => {
+            canvas.state.echo_tab_state.seek_buffer.clear();
+            canvas.state.echo_tab_state.is_echo_seek_buffer_being_filled = true;
+        }
+        (KeyCode::Char(c @ '0'..='9'), _) => {
+            let percent = c.to_digit(10).unwrap_or(0) as f64 * 10.0;
+            canvas.seek_audio(SeekTarget::Percent(percent))?
+        }
+        (KeyCode::Char('n'), _) => {
+            canvas.next_track();
+        }
+        (KeyCode::Char('b'), _) => canvas.previous_track(),
+        (KeyCode::Char('a'), _) => canvas.queue_selected_album().await?,
+        (KeyCode::Char('o'), _) => canvas.open_output_picker(),
+        (KeyCode::Char('['), _) => canvas.set_loop_point(false)?,
+        (KeyCode::Char(']'), _) => canvas.set_loop_point(true)?,
+        (KeyCode::Char('\\'), _) => canvas.clear_loop()?,
+        (KeyCode::Char('m'), KeyModifiers::NONE) => canvas.open_bookmark_prompt()?,
+        (KeyCode::Char('\''), _) => canvas.open_bookmarks().await?,
+        (KeyCode::Char('W'), _) => canvas.toggle_waveform_cursor()?,
+        (KeyCode::Char('x'), _) => canvas.export_selected_song(),
+        (KeyCode::Char('c'), _) => canvas.open_doctor(),
 
         (KeyCode::Char('|'), _) => match canvas.state.echo_tab_state.echo_subtab {
             EchoSubTab::SEARCH => {
@@ -152,7 +93,6 @@ pub async fn handle_echo_key_event(canvas: &mut EchoCanvas, key_event: KeyEvent)
                     .state
                     .echo_tab_state
                     .is_echo_search_buffer_being_filled = true;
-                return sub_events::handle_echo_search_key_event(canvas, key_event);
             }
             EchoSubTab::IMPORT => {
                 canvas
@@ -173,7 +113,7 @@ pub async fn handle_echo_key_event(canvas: &mut EchoCanvas, key_event: KeyEvent)
 
         _ => match canvas.state.echo_tab_state.echo_subtab {
             EchoSubTab::SEARCH => {
-                return sub_events::handle_echo_search_key_event(canvas, key_event);
+                return sub_events::handle_echo_search_key_event(canvas, key_event).await;
             }
             EchoSubTab::IMPORT => {
                 return sub_events::handle_echo_import_key_enent(canvas, key_event).await;
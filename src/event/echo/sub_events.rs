@@ -1,12 +1,10 @@
-use std::path::Path;
-
-use tokio::fs;
+use std::path::PathBuf;
 
 use crossterm::event::{KeyCode, KeyEvent};
 
 use crate::{
     app::{LogLevel, Report},
-    awdio::{AudioPlayer, metadata::Metadata},
+    awdio::SeekTarget,
     db,
     result::{EchoReport, EchoResult},
     ui::EchoCanvas,
@@ -30,84 +28,8 @@ pub async fn handle_echo_import_key_enent(
                     .state
                     .echo_tab_state
                     .is_echo_import_buffer_being_filled = false;
-                let pool = canvas.db_connection_pool.clone();
-                let song_path = canvas.state.echo_tab_state.import_buffer.clone();
-
-                tokio::spawn(async move {
-                    let mut entries = match fs::read_dir(&song_path).await {
-                        Ok(e) => e,
-                        Err(e) => {
-                            eprintln!("read_dir error: {:?}", e);
-                            return;
-                        }
-                    };
-
-                    while let Ok(Some(entry)) = entries.next_entry().await {
-                        let old_path = entry.path();
-                        if old_path.is_dir() {
-                            continue;
-                        }
-                        if old_path.extension().and_then(|s| s.to_str()) != Some("mp3") {
-                            continue;
-                        }
-
-                        let stem = old_path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
-
-                        let path_str = match old_path.to_str() {
-                            Some(p) => p,
-                            None => {
-                                eprintln!("invalid path encoding");
-                                continue;
-                            }
-                        };
-
-                        let mut tag = match Metadata::from_path(path_str) {
-                            Ok(t) => t,
-                            Err(e) => {
-                                eprintln!("metadata error: {:?}", e);
-                                continue;
-                            }
-                        };
-
-                        let db_title = if tag.title.is_empty() {
-                            stem
-                        } else {
-                            &tag.title
-                        };
-
-                        let id = match sqlx::query!(
-                            "INSERT INTO songs (title, artist, album, file_path) VALUES (?, ?, ?, ?)",
-                            db_title, tag.artist, tag.album, "PENDING"
-                        )
-                        .execute(&pool).await {
-                            Ok(res) => res.last_insert_rowid(),
-                            Err(e) => {
-                                eprintln!("db insert error: {:?}", e);
-                                continue;
-                            }
-                        };
-
-                        let new_file_name = format!("{}{}.mp3", song_path, id);
-                        let new_path = Path::new(&new_file_name);
-
-                        if let Err(e) = fs::rename(&old_path, &new_path).await {
-                            eprintln!("rename error: {:?}", e);
-                            continue;
-                        }
-
-                        tag.title = db_title.to_string();
-                        if let Some(new_path_str) = new_path.to_str() {
-                            let _ = tag.update_file(new_path_str);
-                            let _ = sqlx::query!(
-                                "UPDATE songs SET file_path = ? WHERE id = ?",
-                                new_path_str,
-                                id
-                            )
-                            .execute(&pool)
-                            .await;
-                        }
-                    }
-                });
+                let source = PathBuf::from(canvas.state.echo_tab_state.import_buffer.trim());
+                canvas.start_import(vec![source]);
 
                 return Ok(());
             }
@@ -129,7 +51,91 @@ pub async fn handle_echo_import_key_enent(
     Ok(())
 }
 
-pub fn handle_echo_search_key_event(
+/// The `g` prompt: type `mm:ss`, `h:mm:ss`, seconds or `NN%` and jump there.
+pub fn handle_echo_seek_key_event(canvas: &mut EchoCanvas, key_event: KeyEvent) -> EchoResult<()> {
+    let tab_state = &mut canvas.state.echo_tab_state;
+
+    match key_event.code {
+        KeyCode::Char(c) => tab_state.seek_buffer.push(c),
+        KeyCode::Backspace => {
+            tab_state.seek_buffer.pop();
+        }
+        KeyCode::Esc => tab_state.is_echo_seek_buffer_being_filled = false,
+        KeyCode::Enter => {
+            tab_state.is_echo_seek_buffer_being_filled = false;
+            let input = std::mem::take(&mut tab_state.seek_buffer);
+
+            match SeekTarget::parse(&input) {
+                Some(target) => canvas.seek_audio(target)?,
+                None => {
+                    let _ = canvas.state.report_tx.send(Report {
+                        log: Some(format!("INVALID SEEK: {}", input)),
+                        report: None,
+                        level: LogLevel::WARN,
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Move the cursor over the waveform overview, Enter seeks to it.
+pub fn handle_echo_waveform_key_event(
+    canvas: &mut EchoCanvas,
+    key_event: KeyEvent,
+) -> EchoResult<()> {
+    let tab_state = &mut canvas.state.echo_tab_state;
+    let Some(cursor) = tab_state.waveform_cursor else {
+        return Ok(());
+    };
+
+    let step = match key_event.code {
+        KeyCode::Char('h') => -1.0,
+        KeyCode::Char('l') => 1.0,
+        KeyCode::Char('H') => -10.0,
+        KeyCode::Char('L') => 10.0,
+        KeyCode::Char('W') | KeyCode::Esc => {
+            tab_state.waveform_cursor = None;
+            return Ok(());
+        }
+        KeyCode::Enter => {
+            tab_state.waveform_cursor = None;
+            return canvas.seek_audio(SeekTarget::Percent(cursor));
+        }
+        _ => return Ok(()),
+    };
+
+    tab_state.waveform_cursor = Some((cursor + step).clamp(0.0, 100.0));
+    Ok(())
+}
+
+pub async fn handle_echo_bookmark_key_event(
+    canvas: &mut EchoCanvas,
+    key_event: KeyEvent,
+) -> EchoResult<()> {
+    let tab_state = &mut canvas.state.echo_tab_state;
+
+    match key_event.code {
+        KeyCode::Char(c) => tab_state.bookmark_buffer.push(c),
+        KeyCode::Backspace => {
+            tab_state.bookmark_buffer.pop();
+        }
+        KeyCode::Esc => tab_state.is_echo_bookmark_buffer_being_filled = false,
+        KeyCode::Enter => {
+            tab_state.is_echo_bookmark_buffer_being_filled = false;
+            let name = std::mem::take(&mut tab_state.bookmark_buffer);
+            canvas.save_bookmark(name).await?;
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub async fn handle_echo_search_key_event(
     canvas: &mut EchoCanvas,
     key_event: KeyEvent,
 ) -> EchoResult<()> {
@@ -141,7 +147,11 @@ pub fn handle_echo_search_key_event(
         match key_event.code {
             KeyCode::Char(c) => {
                 canvas.state.echo_tab_state.search_buffer.push(c);
-                return Ok(());
+                return canvas.search_library().await;
+            }
+            KeyCode::Backspace => {
+                canvas.state.echo_tab_state.search_buffer.pop();
+                return canvas.search_library().await;
             }
             KeyCode::Enter => {
                 canvas
@@ -173,36 +183,16 @@ pub fn handle_echo_search_key_event(
             }
             canvas.state.next_local_song()
         }
-        KeyCode::Enter => match canvas.state.local_songs.get(canvas.state.selected_song_pos) {
-            Some(v) => {
-                let reporter = canvas.state.report_tx.clone();
-                let audio_player = match AudioPlayer::new(&v.path) {
-                    Ok(player) => player,
-                    Err(e) => {
-                        reporter
-                            .send(Report {
-                                log: Some(e.to_string()),
-                                report: Some(crate::result::EchoReport::LockPoisoned(
-                                    e.to_string(),
-                                )),
-                                level: LogLevel::ERR,
-                            })
-                            .ok();
-                        AudioPlayer::bad()
-                    }
-                };
-                canvas.state.active_track =
-                    canvas.state.local_songs[canvas.state.selected_song_pos].to_owned();
-                canvas.audio_player = audio_player;
-
-                let mut audio_state = Some(canvas.audio_player.state.clone());
-                if let Err(_) = canvas.audio_player.play() {
-                    audio_state = None
-                }
-                canvas.audio_state = audio_state
+        KeyCode::Enter => {
+            if canvas.state.local_songs.is_empty() {
+                return Ok(());
             }
-            None => {}
-        },
+            canvas.play_queue(
+                canvas.state.local_songs.clone(),
+                canvas.state.selected_song_pos,
+                "LIBRARY",
+            );
+        }
         _ => {}
     }
     Ok(())
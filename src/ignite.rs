@@ -15,6 +15,8 @@ pub struct Paths {
     pub config: PathBuf,
     pub data: PathBuf,
     pub songs: PathBuf,
+    pub waveforms: PathBuf,
+    pub exports: PathBuf,
 }
 
 impl Paths {
@@ -41,12 +43,18 @@ impl Paths {
         // data
         fs::create_dir_all(data.join("songs"))?;
         fs::create_dir_all(data.join("playlists"))?;
+        fs::create_dir_all(data.join("waveforms"))?;
+        fs::create_dir_all(data.join("exports"))?;
         let songs = data.join("songs");
+        let waveforms = data.join("waveforms");
+        let exports = data.join("exports");
 
         Ok(Self {
             config: config.to_path_buf(),
             data: data.to_path_buf(),
             songs,
+            waveforms,
+            exports,
         })
     }
 }
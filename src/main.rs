@@ -1,5 +1,8 @@
+use clap::Parser;
+
 mod app;
 mod awdio;
+mod cli;
 mod config;
 mod db;
 mod download;
@@ -7,19 +10,38 @@ mod event;
 mod ignite;
 mod logger;
 mod result;
+#[cfg(test)]
+mod testing;
+mod timer;
 mod ui;
 
 #[tokio::main]
 async fn main() -> result::EchoResult<()> {
+    let cli = cli::Cli::parse();
     logger::init_logger();
 
     match ignite::engine().await {
-        Ok(val) => {
-            if let Err(e) = app::start(val).await {
+        Ok(mut val) => {
+            cli.apply(&mut val.0);
+            if let Some(command) = &cli.command {
+                match command.run(&val.0, &val.1, &val.2).await {
+                    Ok(path) => println!("{}", path.display()),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else if let Err(e) = app::start(val).await {
                 eprintln!("{}", e);
             }
         }
-        Err(e) => eprintln!("{}", e),
+        Err(e) => {
+            eprintln!("{}", e);
+            // scripts running a command need to tell it didn't
+            if cli.command.is_some() {
+                std::process::exit(1);
+            }
+        }
     }
 
     Ok(())
@@ -40,6 +40,9 @@ pub enum EchoReport {
 
     #[error("Download error: {0}")]
     DownloadError(String),
+
+    #[error("Import error: {0}")]
+    ImportError(String),
 }
 
 pub type EchoResult<T> = Result<T, EchoReport>;
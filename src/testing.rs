@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use sqlx::SqlitePool;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A folder of its own under the system temp dir, so tests running at the
+/// same time never share one. Removed again once dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A library in a temp dir: its songs folder and a migrated database.
+pub struct TestLibrary {
+    pub dir: TempDir,
+    pub songs: PathBuf,
+    pub pool: SqlitePool,
+}
+
+impl TestLibrary {
+    pub async fn new(name: &str) -> Self {
+        let dir = TempDir::new(name);
+        let songs = dir.join("songs");
+        std::fs::create_dir_all(&songs).unwrap();
+        let pool = crate::db::init_db(dir.join("music.db").to_str().unwrap())
+            .await
+            .unwrap();
+        Self { dir, songs, pool }
+    }
+}
+
+/// A mono 997 Hz sine, `level` of full scale.
+pub fn sine_wav(path: &Path, sample_rate: u32, seconds: f32, level: f32) {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for i in 0..(sample_rate as f32 * seconds) as u32 {
+        let phase = i as f32 * 997.0 * std::f32::consts::TAU / sample_rate as f32;
+        writer
+            .write_sample((phase.sin() * level * i16::MAX as f32) as i16)
+            .unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+/// A second of sine, all a song needs to be imported.
+pub fn song_wav(path: &Path) {
+    sine_wav(path, 8_000, 1.0, 0.5);
+}
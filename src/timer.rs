@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local, NaiveTime};
+
+/// Sleep timer choices `t` steps through, in minutes.
+const SLEEP_MINUTES: [u64; 5] = [15, 30, 45, 60, 90];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepUntil {
+    Deadline(Instant),
+    EndOfTrack,
+    EndOfAlbum,
+}
+
+#[derive(Debug)]
+pub struct SleepTimer {
+    pub until: SleepUntil,
+    pub fade: Duration,
+    // the volume to go back to once playback is paused
+    pub base_volume: Option<f32>,
+}
+
+impl SleepTimer {
+    /// The next choice after `current`, off after the last one.
+    pub fn cycle(current: Option<&SleepTimer>, fade: Duration) -> Option<SleepTimer> {
+        let until = match current.map(|timer| timer.until) {
+            None => SleepUntil::Deadline(Instant::now() + minutes(SLEEP_MINUTES[0])),
+            Some(SleepUntil::Deadline(deadline)) => {
+                // step up from the choice the deadline was set with
+                let left = deadline.saturating_duration_since(Instant::now());
+                match SLEEP_MINUTES
+                    .iter()
+                    .find(|&&m| minutes(m) > left + minutes(1))
+                {
+                    Some(&m) => SleepUntil::Deadline(Instant::now() + minutes(m)),
+                    None => SleepUntil::EndOfTrack,
+                }
+            }
+            Some(SleepUntil::EndOfTrack) => SleepUntil::EndOfAlbum,
+            Some(SleepUntil::EndOfAlbum) => return None,
+        };
+
+        Some(SleepTimer {
+            until,
+            fade,
+            base_volume: None,
+        })
+    }
+
+    /// Time until playback pauses. `track_left` is the wall-clock time the
+    /// playing track still needs, `is_album_end` whether it is the last one
+    /// of its album in the queue.
+    pub fn remaining(&self, track_left: Option<Duration>, is_album_end: bool) -> Option<Duration> {
+        match self.until {
+            SleepUntil::Deadline(deadline) => {
+                Some(deadline.saturating_duration_since(Instant::now()))
+            }
+            SleepUntil::EndOfTrack => track_left,
+            SleepUntil::EndOfAlbum if is_album_end => track_left,
+            SleepUntil::EndOfAlbum => None,
+        }
+    }
+
+    /// How loud to play relative to the base volume with `remaining` left.
+    pub fn fade_factor(&self, remaining: Duration) -> f32 {
+        if self.fade.is_zero() || remaining >= self.fade {
+            return 1.0;
+        }
+        remaining.as_secs_f32() / self.fade.as_secs_f32()
+    }
+
+    pub fn label(&self) -> String {
+        match self.until {
+            SleepUntil::Deadline(deadline) => {
+                readable(deadline.saturating_duration_since(Instant::now()))
+            }
+            SleepUntil::EndOfTrack => "END OF TRACK".into(),
+            SleepUntil::EndOfAlbum => "END OF ALBUM".into(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Alarm {
+    pub time: NaiveTime,
+    pub playlist_id: Option<i64>,
+    pub fade: Duration,
+    // volume the fade-in ends at
+    pub volume: f32,
+    // next time it goes off, `None` while disarmed
+    pub next: Option<DateTime<Local>>,
+}
+
+impl Alarm {
+    pub fn new(time: NaiveTime, fade: Duration, volume: f32) -> Self {
+        Self {
+            time,
+            playlist_id: None,
+            fade,
+            volume,
+            next: None,
+        }
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.next.is_some()
+    }
+
+    /// Arm for the next time the clock shows `time`, today or tomorrow.
+    pub fn arm(&mut self) {
+        let now = Local::now();
+        let today = now
+            .date_naive()
+            .and_time(self.time)
+            .and_local_timezone(Local)
+            .earliest();
+
+        self.next = match today {
+            Some(at) if at > now => Some(at),
+            _ => (now.date_naive() + chrono::Days::new(1))
+                .and_time(self.time)
+                .and_local_timezone(Local)
+                .earliest(),
+        };
+    }
+
+    pub fn disarm(&mut self) {
+        self.next = None;
+    }
+
+    /// Move the alarm by `minutes`, it stays armed if it was.
+    pub fn shift(&mut self, minutes: i64) {
+        self.time += chrono::Duration::minutes(minutes);
+        if self.is_armed() {
+            self.arm();
+        }
+    }
+
+    /// True once when the alarm time passed, it arms itself for the next day.
+    pub fn is_due(&mut self) -> bool {
+        match self.next {
+            Some(at) if Local::now() >= at => {
+                self.arm();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        self.time.format("%H:%M").to_string()
+    }
+}
+
+/// Raises the volume from silence after the alarm went off.
+#[derive(Debug)]
+pub struct FadeIn {
+    pub started: Instant,
+    pub duration: Duration,
+    pub target: f32,
+}
+
+impl FadeIn {
+    /// The volume right now, `None` once the fade is done.
+    pub fn volume(&self) -> Option<f32> {
+        let elapsed = self.started.elapsed();
+        if elapsed >= self.duration {
+            return None;
+        }
+        Some(self.target * elapsed.as_secs_f32() / self.duration.as_secs_f32())
+    }
+}
+
+pub fn readable(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs / 3600 {
+        0 => format!("{:02}:{:02}", secs / 60, secs % 60),
+        hours => format!("{}:{:02}:{:02}", hours, (secs % 3600) / 60, secs % 60),
+    }
+}
+
+fn minutes(minutes: u64) -> Duration {
+    Duration::from_secs(minutes * 60)
+}
@@ -8,6 +8,7 @@ use std::{
 use ratatui::{
     Frame,
     crossterm::{
+        event::{DisableMouseCapture, EnableMouseCapture},
         execute,
         terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
     },
@@ -63,7 +64,7 @@ impl EchoCanvas {
 
     pub async fn paint(&mut self) -> EchoResult<()> {
         enable_raw_mode()?;
-        execute!(stdout(), EnterAlternateScreen)?;
+        execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
 
         let mut terminal = ratatui::init();
 
@@ -93,7 +94,15 @@ impl EchoCanvas {
 
             tokio::select! {
                 _ = ticker.tick() => {
-                    // refresh ui
+                    self.poll_playback();
+                    self.poll_doctor().await;
+                    if let Err(e) = self.poll_timers().await {
+                        self.state.report_tx.send(Report {
+                            log: Some(e.to_string()),
+                            report: Some(e),
+                            level: LogLevel::ERR
+                        }).ok();
+                    }
                 }
 
                 _ = timestamp_ticker.tick() => {
@@ -125,7 +134,11 @@ impl EchoCanvas {
         }
 
         disable_raw_mode()?;
-        execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        execute!(
+            terminal.backend_mut(),
+            LeaveAlternateScreen,
+            DisableMouseCapture
+        )?;
 
         ratatui::restore();
         Ok(())
@@ -3,24 +3,26 @@ use std::{
     sync::{Arc, Mutex},
 };
 
-use ratatui::widgets::Widget;
+use ratatui::widgets::{Clear, Widget};
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
+    layout::{Constraint, Flex, Layout, Rect},
     style::Style,
     text::{Line, Span},
     widgets::Padding,
 };
 
 use crate::{
-    app::{LogLevel, SelectedTab, State},
-    awdio::{AudioData, DurationInfo, current_timestamp},
+    app::{LogLevel, Popup, SelectedTab, State},
+    awdio::{AudioData, DurationInfo, current_timestamp, scope::Scope, stretch::SpeedMode},
     config::UiConfig,
     ignite::Paths,
+    timer,
 };
 
 mod shared;
 mod tabs;
+mod waveform;
 
 pub fn main_header(
     song_name_area: Rect,
@@ -46,23 +48,25 @@ pub fn main_header(
         min_buffer_threshold,
         fft,
         enable_fft_compute,
+        loop_points,
     ) = {
         match audio_state {
             Some(v) => {
                 let audio = v.lock().unwrap();
                 (
-                    audio.samples.len(),
+                    audio.buffered_samples(),
                     audio.sample_rate,
                     audio.channels,
                     audio.file_size.clone(),
                     audio.duration.clone(),
                     audio.host.clone(),
-                    audio.is_pause,
-                    audio.volume,
-                    audio.total_samples_played,
+                    audio.transport.is_paused(),
+                    audio.transport.volume(),
+                    audio.position_frames(),
                     audio.min_buffer_threshold,
-                    audio.fft_state.clone(),
+                    audio.scope.clone(),
                     audio.enable_fft_compute.clone(),
+                    (audio.loop_a, audio.loop_b),
                 )
             }
             None => (
@@ -76,17 +80,21 @@ pub fn main_header(
                 0.0,
                 0,
                 0,
-                Vec::new(),
+                Scope::default(),
                 true,
+                (None, None),
             ),
         }
     };
-    let max_samples = (duration.seconds * sample_rate as u64) as i64;
+    let max_samples = duration.frames_at(sample_rate);
     let timestamp = current_timestamp(total_samples_played, sample_rate);
-    let timestamp_percent = ((timestamp.1 / duration.seconds as f64) * 100.0)
-        .ceil()
-        .min(100.0);
-    let position = (((timestamp_percent / 100.0) * 50.0) as usize).min(49);
+    let timestamp_percent = if max_samples > 0 {
+        (total_samples_played as f64 / max_samples as f64 * 100.0)
+            .floor()
+            .min(100.0)
+    } else {
+        0.0
+    };
 
     let text = vec![
         state.active_track.metadata.title.clone().into(),
@@ -118,41 +126,78 @@ pub fn main_header(
     let timestamp_block =
         shared::block::bordered_block(Line::default(), ui_config.colors["colors"].border)
             .title_style(Style::new().fg(ui_config.colors["colors"].title))
+            .title(Line::from(timers_title(state)).right_aligned())
             .title(Line::from(format!(" UPTIME: {} ", state.uptime_readable)).right_aligned())
             .title(Line::from(" ⟐  ").left_aligned())
             .title(Line::from(state.current_clock.clone()).centered())
-            .title_bottom(Line::from(format!(" VOL: {:.1} ", volume)))
+            .title_bottom(Line::from(format!(
+                " VOL: {:.1} · SPD: {:.1}x{} ",
+                volume,
+                state.speed,
+                match state.speed_mode {
+                    SpeedMode::Stretch => "",
+                    SpeedMode::Varispeed => " VARI",
+                }
+            )))
             .title_bottom(Line::from(format!(" HOST: {} ", host)).centered())
-            .title_bottom(Line::from(" TICK: 100ms ").right_aligned());
+            .title_bottom(
+                Line::from(if state.echo_tab_state.is_echo_seek_buffer_being_filled {
+                    format!(" SEEK: {}_ ", state.echo_tab_state.seek_buffer)
+                } else if state.echo_tab_state.is_echo_bookmark_buffer_being_filled {
+                    format!(" BOOKMARK: {}_ ", state.echo_tab_state.bookmark_buffer)
+                } else if let Some(cursor) = state.echo_tab_state.waveform_cursor {
+                    format!(" SEEK TO: {:.0}% · h/l H/L · ⏎ ", cursor)
+                } else {
+                    " TICK: 100ms ".to_string()
+                })
+                .right_aligned(),
+            );
 
-    let mut anim = state.animations.animation_timestamp.borrow_mut();
-    for i in 0..=anim.vals.len() - 1 {
-        if i == position {
-            anim.vals[position] = ui_config.animations["animations"].timestamp.clone();
-            continue;
+    // the A–B loop and the playhead as fractions of the song
+    let fraction = |frames: u64| {
+        if max_samples == 0 {
+            return 0.0;
         }
-        anim.vals[i] = ui_config.animations["animations"].timestamp_bar.clone();
-    }
-    drop(anim);
-
-    let timestamp_bar: String = state
-        .animations
-        .animation_timestamp
-        .borrow_mut()
-        .vals
-        .join("");
+        (frames as f64 / max_samples as f64).min(1.0)
+    };
+    let loop_region = match loop_points {
+        (Some(a), Some(b)) => Some(fraction(a)..=fraction(b)),
+        (Some(a), None) => Some(fraction(a)..=fraction(a)),
+        _ => None,
+    };
     let timestamp = format!(
         "{} ■ {} :|{:02}%|: {} ■ {}",
         timestamp.1 as u64, timestamp.0, timestamp_percent, duration.readable, duration.seconds
     );
 
-    shared::paragraph::paragraph(
-        vec![Line::from(timestamp_bar), Line::from(timestamp)],
-        timestamp_block,
-    )
-    .style(Style::default().fg(ui_config.colors["colors"].fg))
-    .centered()
-    .render(header_area[1], buf);
+    // the overview takes every row but the last, which holds the time
+    let timestamp_inner = timestamp_block.inner(header_area[1]);
+    let [waveform_area, timestamp_area] = Layout::vertical([
+        Constraint::Fill(1),
+        Constraint::Length(timestamp_inner.height.saturating_sub(1).min(1)),
+    ])
+    .areas(timestamp_inner);
+    state.waveform_area.set(waveform_area);
+
+    timestamp_block.render(header_area[1], buf);
+    waveform::render_waveform(
+        waveform_area,
+        buf,
+        waveform::Overview {
+            waveform: &state.waveform.lock().unwrap(),
+            progress: fraction(total_samples_played),
+            loop_region,
+            cursor: state
+                .echo_tab_state
+                .waveform_cursor
+                .map(|cursor| cursor / 100.0),
+        },
+        ui_config,
+    );
+    Line::from(timestamp)
+        .style(Style::default().fg(ui_config.colors["colors"].fg))
+        .centered()
+        .render(timestamp_area, buf);
 
     let tab_block =
         shared::block::bordered_block(Line::default(), ui_config.colors["colors"].border)
@@ -181,6 +226,11 @@ pub fn main_header(
                         .title_style(Style::default().fg(ui_config.colors["colors"].success))
                         .render(tab_area[1], buf)
                 }
+                LogLevel::WARN => {
+                    shared::block::unbordered_block(Line::from(format!(" ⚠ {}", val)))
+                        .title_style(Style::default().fg(ui_config.colors["colors"].warning))
+                        .render(tab_area[1], buf)
+                }
                 _ => {}
             }
         }
@@ -192,23 +242,156 @@ pub fn main_header(
         SelectedTab::Echo => tabs::echo::render_echo(
             body_area,
             buf,
-            samples,
-            sample_rate,
-            channels,
-            total_samples_played,
-            max_samples as u64,
-            min_buffer_threshold,
-            fft,
-            ui_config.colors["colors"].fg,
-            ui_config.colors["colors"].title,
-            ui_config.colors["colors"].border,
+            tabs::echo::EchoView {
+                sample_buffer_size: samples,
+                sample_rate,
+                channels,
+                total_samples_played,
+                max_samples,
+                min_buffer_threshold,
+                scope: fft,
+                low_color: ui_config.colors["colors"].fg,
+                mid_color: ui_config.colors["colors"].title,
+                high_color: ui_config.colors["colors"].border,
+                songs: &state.local_songs,
+                selected_song_pos: &state.selected_song_pos,
+                queue: &state.queue,
+                echo_tab_state: &state.echo_tab_state,
+                songs_path: &all_paths.songs,
+            },
             config,
-            &state.local_songs,
-            &state.selected_song_pos,
-            &state.active_track,
-            &state.echo_tab_state,
-            &all_paths.songs,
         ),
+        SelectedTab::Misc => tabs::misc::render_misc(body_area, buf, state, ui_config),
         _ => {}
     }
+
+    match state.popup {
+        Some(Popup::Output) => output_picker(body_area, buf, state, ui_config),
+        Some(Popup::Bookmarks) => bookmarks_picker(body_area, buf, state, ui_config),
+        Some(Popup::Doctor) => doctor_popup(body_area, buf, state, ui_config),
+        None => {}
+    }
+}
+
+/// Time left on the sleep timer and when the alarm goes off, empty when
+/// neither is set.
+fn timers_title(state: &State) -> String {
+    let mut title = String::new();
+    if let Some(timer) = &state.sleep_timer {
+        let left = match state.sleep_remaining {
+            Some(remaining) => timer::readable(remaining),
+            None => timer.label(),
+        };
+        title.push_str(&format!(" SLEEP: {} ", left));
+    }
+    if state.alarm.is_armed() {
+        title.push_str(&format!(" ALARM: {} ", state.alarm.label()));
+    }
+    title
+}
+
+fn bookmarks_picker(area: Rect, buf: &mut Buffer, state: &State, ui_config: &UiConfig) {
+    let height = (state.bookmarks.len().max(1) as u16 + 2).min(area.height);
+    let [popup_area] = Layout::horizontal([Constraint::Percentage(60)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup_area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup_area);
+
+    let block =
+        shared::block::bordered_block(Line::from(" BOOKMARKS "), ui_config.colors["colors"].border)
+            .title_bottom(
+                Line::from(" w/s:move · enter:jump · x:delete · esc:close ").right_aligned(),
+            )
+            .title_style(Style::new().fg(ui_config.colors["colors"].title));
+
+    Clear.render(popup_area, buf);
+    ratatui::widgets::Widget::render(
+        shared::table::bookmarks_table(
+            &state.bookmarks,
+            state.selected_bookmark_idx,
+            ui_config.colors["colors"].fg,
+            ui_config.colors["colors"].title,
+        )
+        .block(block),
+        popup_area,
+        buf,
+    );
+}
+
+fn doctor_popup(area: Rect, buf: &mut Buffer, state: &State, ui_config: &UiConfig) {
+    let height = (state.doctor_issues.len().max(1) as u16 + 2).min(area.height);
+    let [popup_area] = Layout::horizontal([Constraint::Percentage(80)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup_area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup_area);
+
+    let is_checking = state.doctor_check.is_some();
+    let title = if is_checking {
+        let spinner = &ui_config.animations["animations"].spinner;
+        let frame = spinner
+            .get(state.animations.animation_spinner.0)
+            .copied()
+            .unwrap_or(' ');
+        format!(" LIBRARY DOCTOR · CHECKING {} ", frame)
+    } else {
+        format!(" LIBRARY DOCTOR · {} ", state.doctor_issues.len())
+    };
+    let hint = if state.is_doctor_delete_armed {
+        " x:delete for good · any other key:keep it "
+    } else {
+        " w/s:move · r:relocate · x:delete · i:import · esc:close "
+    };
+
+    let block = shared::block::bordered_block(Line::from(title), ui_config.colors["colors"].border)
+        .title_bottom(Line::from(hint).right_aligned())
+        .title_style(Style::new().fg(ui_config.colors["colors"].title));
+
+    Clear.render(popup_area, buf);
+    ratatui::widgets::Widget::render(
+        shared::table::doctor_table(
+            &state.doctor_issues,
+            state.selected_issue_idx,
+            is_checking,
+            ui_config.colors["colors"].fg,
+            ui_config.colors["colors"].title,
+        )
+        .block(block),
+        popup_area,
+        buf,
+    );
+}
+
+fn output_picker(area: Rect, buf: &mut Buffer, state: &State, ui_config: &UiConfig) {
+    let height = (state.output_devices.len() as u16 + 2).min(area.height);
+    let [popup_area] = Layout::horizontal([Constraint::Percentage(60)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [popup_area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(popup_area);
+
+    let block = shared::block::bordered_block(
+        Line::from(" OUTPUT DEVICE "),
+        ui_config.colors["colors"].border,
+    )
+    .title_bottom(Line::from(" w/s:move · enter:select · esc:close ").right_aligned())
+    .title_style(Style::new().fg(ui_config.colors["colors"].title));
+
+    Clear.render(popup_area, buf);
+    ratatui::widgets::Widget::render(
+        shared::table::output_devices_table(
+            &state.output_devices,
+            state.selected_output_idx,
+            &state.output_target,
+            ui_config.colors["colors"].fg,
+            ui_config.colors["colors"].title,
+        )
+        .block(block),
+        popup_area,
+        buf,
+    );
 }
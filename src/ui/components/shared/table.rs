@@ -1,11 +1,18 @@
 use ratatui::{
     layout::Constraint,
     style::{Color, Modifier, Style},
-    text::Text,
+    text::{Line, Span, Text},
     widgets::{Cell, Row, Table},
 };
 
-use crate::{app::EchoSubTab, awdio::song::Song, db::Playlist};
+use crate::{
+    app::EchoSubTab,
+    awdio::{current_timestamp, device::OutputTarget, queue::PlayQueue, song::Song},
+    db::{
+        Bookmark, Playlist,
+        library::{self, doctor::Issue},
+    },
+};
 
 pub fn echo_metadata_table<'a>(
     metadata: Vec<(&'a str, &'a String)>,
@@ -49,9 +56,9 @@ pub fn echo_metadata_table<'a>(
 
 pub fn local_songs_table(
     songs: &Vec<Song>,
+    query: &str,
     fg: Color,
-    _bg: Color,
-    _accent: Color,
+    accent: Color,
     title: Color,
     selected_song_pos: &usize,
     echo_subtab: &EchoSubTab,
@@ -64,6 +71,8 @@ pub fn local_songs_table(
         _ => selected_row_style = Style::default(),
     }
 
+    let terms = library::search_terms(query);
+
     let rows = songs.iter().enumerate().map(|(i, data)| {
         let is_selected = i == *selected_song_pos;
         let row_style = if is_selected {
@@ -76,8 +85,8 @@ pub fn local_songs_table(
         let (name, artist) = (item[0], item[1]);
 
         Row::new(vec![
-            Cell::from(Text::from(name.clone())),
-            Cell::from(Text::from(artist.clone())),
+            Cell::from(highlighted(name, &terms, accent)),
+            Cell::from(highlighted(artist, &terms, accent)),
         ])
         .height(1)
         .style(row_style)
@@ -90,6 +99,52 @@ pub fn local_songs_table(
     .row_highlight_style(selected_row_style)
 }
 
+/// `text` with the word starts that match one of `terms` in `accent`, the
+/// way the library search matches them.
+fn highlighted(text: &str, terms: &[String], accent: Color) -> Line<'static> {
+    let mut spans = Vec::new();
+    // where the text that isn't highlighted starts
+    let mut plain = 0;
+    for (start, word) in library::words(text) {
+        let Some(len) = terms.iter().filter_map(|term| prefix_len(word, term)).max() else {
+            continue;
+        };
+
+        let end = start + len;
+        if plain < start {
+            spans.push(Span::raw(text[plain..start].to_string()));
+        }
+        spans.push(Span::styled(
+            text[start..end].to_string(),
+            Style::default().fg(accent).add_modifier(Modifier::BOLD),
+        ));
+        plain = end;
+    }
+    if plain < text.len() {
+        spans.push(Span::raw(text[plain..].to_string()));
+    }
+    Line::from(spans)
+}
+
+// how many bytes of `word` spell out the folded `term`, with the marks that
+// sit on the last letter
+fn prefix_len(word: &str, term: &str) -> Option<usize> {
+    let mut expected = term.chars().peekable();
+    let mut chars = word.char_indices();
+    while expected.peek().is_some() {
+        let (_, c) = chars.next()?;
+        for folded in library::fold(c) {
+            if expected.next_if_eq(&folded).is_none() && expected.peek().is_some() {
+                return None;
+            }
+        }
+    }
+    let rest = chars
+        .find(|(_, c)| library::fold(*c).next().is_some())
+        .map_or(word.len(), |(i, _)| i);
+    Some(rest)
+}
+
 pub fn playlist_list_table(
     playlists: &[Playlist],
     selected_idx: usize,
@@ -152,3 +207,168 @@ pub fn playlist_songs_table(
     )
     .row_highlight_style(selected_style)
 }
+
+pub fn queue_table(queue: &PlayQueue, fg: Color, title: Color) -> Table<'static> {
+    let current_style = Style::default().add_modifier(Modifier::BOLD).fg(title);
+
+    let rows = queue.songs.iter().enumerate().map(|(i, song)| {
+        let is_current = queue.position == Some(i);
+        let (marker, row_style) = if is_current {
+            (" ▶", current_style)
+        } else {
+            ("  ", Style::default().fg(fg))
+        };
+
+        Row::new(vec![
+            Cell::from(Text::from(format!("{} {:>2}", marker, i + 1))),
+            Cell::from(Text::from(song.metadata.title.clone())),
+            Cell::from(Text::from(song.metadata.artist.clone())),
+        ])
+        .height(1)
+        .style(row_style)
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Percentage(55),
+            Constraint::Percentage(45),
+        ],
+    )
+}
+
+pub fn output_devices_table(
+    outputs: &[OutputTarget],
+    selected_idx: usize,
+    current: &OutputTarget,
+    fg: Color,
+    title: Color,
+) -> Table<'static> {
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED).fg(title);
+
+    let rows = outputs.iter().enumerate().map(|(i, target)| {
+        let marker = if target == current { " ▶" } else { "  " };
+        let row_style = if i == selected_idx {
+            selected_style
+        } else {
+            Style::default().fg(fg)
+        };
+
+        Row::new(vec![Cell::from(Text::from(format!(
+            "{} {}",
+            marker,
+            target.label()
+        )))])
+        .height(1)
+        .style(row_style)
+    });
+
+    Table::new(rows, [Constraint::Percentage(100)]).row_highlight_style(selected_style)
+}
+
+pub fn bookmarks_table(
+    bookmarks: &[Bookmark],
+    selected_idx: usize,
+    fg: Color,
+    title: Color,
+) -> Table<'static> {
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED).fg(title);
+
+    if bookmarks.is_empty() {
+        let row = Row::new(vec![Cell::from("  No bookmarks yet, press m to add one")])
+            .style(Style::default().fg(fg));
+        return Table::new(vec![row], [Constraint::Percentage(100)]);
+    }
+
+    let rows = bookmarks.iter().enumerate().map(|(i, bookmark)| {
+        let row_style = if i == selected_idx {
+            selected_style
+        } else {
+            Style::default().fg(fg)
+        };
+
+        Row::new(vec![
+            Cell::from(format!(
+                "  {}",
+                current_timestamp(bookmark.position as u64, 1).0
+            )),
+            Cell::from(bookmark.name.clone()),
+        ])
+        .height(1)
+        .style(row_style)
+    });
+
+    Table::new(rows, [Constraint::Length(10), Constraint::Fill(1)])
+        .row_highlight_style(selected_style)
+}
+
+pub fn doctor_table(
+    issues: &[Issue],
+    selected_idx: usize,
+    is_checking: bool,
+    fg: Color,
+    title: Color,
+) -> Table<'static> {
+    let selected_style = Style::default().add_modifier(Modifier::REVERSED).fg(title);
+
+    if issues.is_empty() {
+        let message = if is_checking {
+            "  Checking the library..."
+        } else {
+            "  Nothing wrong with the library"
+        };
+        let row = Row::new(vec![Cell::from(message)]).style(Style::default().fg(fg));
+        return Table::new(vec![row], [Constraint::Percentage(100)]);
+    }
+
+    let rows = issues.iter().enumerate().map(|(i, issue)| {
+        let row_style = if i == selected_idx {
+            selected_style
+        } else {
+            Style::default().fg(fg)
+        };
+
+        Row::new(vec![
+            Cell::from(format!("  {}", issue.kind())),
+            Cell::from(issue.subject()),
+            Cell::from(issue.detail()),
+        ])
+        .height(1)
+        .style(row_style)
+    });
+
+    Table::new(
+        rows,
+        [
+            Constraint::Length(11),
+            Constraint::Percentage(35),
+            Constraint::Fill(1),
+        ],
+    )
+    .row_highlight_style(selected_style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the highlighted parts of `text` when searching for `query`
+    fn marked(text: &str, query: &str) -> Vec<String> {
+        highlighted(text, &library::search_terms(query), Color::Red)
+            .spans
+            .into_iter()
+            .filter(|span| span.style.fg == Some(Color::Red))
+            .map(|span| span.content.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn highlight_folds_like_the_search() {
+        assert_eq!(marked("Beyoncé", "beyonce"), ["Beyoncé"]);
+        assert_eq!(marked("Beyonce\u{301}", "BEYONCÉ"), ["Beyonce\u{301}"]);
+        assert_eq!(marked("Beyoncé", "bey"), ["Bey"]);
+        assert_eq!(marked("AC/DC", "ac/dc"), ["AC", "DC"]);
+        assert_eq!(marked("Back in Black", "ack"), Vec::<String>::new());
+    }
+}
@@ -1,43 +1,64 @@
 use std::path::{Path, PathBuf};
 
 use ratatui::text::Span;
-use ratatui::widgets::{Paragraph, Widget};
+use ratatui::widgets::{LineGauge, Paragraph, Widget};
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style},
     text::Line,
-    widgets::{
-        block::Title,
-        canvas::{Canvas, Points},
-    },
+    widgets::block::Title,
 };
 use toml::to_string;
 
 use crate::app::EchoSubTab;
+use crate::db::library::import::ImportProgress;
 use crate::ui::components::shared;
-use crate::{app::EchoTabState, awdio::song::Song, config::UiConfig};
+use crate::ui::components::tabs::visualizer;
+use crate::{
+    app::EchoTabState,
+    awdio::{queue::PlayQueue, scope::Scope, song::Song},
+    config::UiConfig,
+};
 
-pub fn render_echo(
-    area: Rect,
-    buf: &mut Buffer,
-    sample_buffer_size: usize,
-    sample_rate: u32,
-    channels: u16,
-    total_samples_played: u64,
-    max_samples: u64,
-    min_buffer_threshold: usize,
-    fft_state: Vec<f32>,
-    low_color: Color,
-    mid_color: Color,
-    high_color: Color,
-    config: &UiConfig,
-    songs: &Vec<Song>,
-    selected_song_pos: &usize,
-    current_song: &Song,
-    echo_tab_state: &EchoTabState,
-    songs_path: &PathBuf,
-) {
+/// What the echo tab shows: the output stream, the visualizer and the
+/// library, queue and tab state it is drawn from.
+pub struct EchoView<'a> {
+    pub sample_buffer_size: usize,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub total_samples_played: u64,
+    pub max_samples: u64,
+    pub min_buffer_threshold: usize,
+    pub scope: Scope,
+    pub low_color: Color,
+    pub mid_color: Color,
+    pub high_color: Color,
+    pub songs: &'a Vec<Song>,
+    pub selected_song_pos: &'a usize,
+    pub queue: &'a PlayQueue,
+    pub echo_tab_state: &'a EchoTabState,
+    pub songs_path: &'a PathBuf,
+}
+
+pub fn render_echo(area: Rect, buf: &mut Buffer, view: EchoView, config: &UiConfig) {
+    let EchoView {
+        sample_buffer_size,
+        sample_rate,
+        channels,
+        total_samples_played,
+        max_samples,
+        min_buffer_threshold,
+        scope,
+        low_color,
+        mid_color,
+        high_color,
+        songs,
+        selected_song_pos,
+        queue,
+        echo_tab_state,
+        songs_path,
+    } = view;
     let info = config.colors["colors"].info;
     let title = config.colors["colors"].title;
     let bg = config.colors["colors"].bg;
@@ -59,86 +80,28 @@ pub fn render_echo(
     let body_area = chunks[1];
 
     if echo_tab_state.is_fft_enable {
-        let title_ttf = Line::from(" ▪︎ ");
+        let mode = echo_tab_state.visualizer;
+        let title_ttf = Line::from(visualizer::title(mode, &scope, config));
         let ttf_block = shared::block::bordered_block(title_ttf, low_color)
             .border_style(Style::new().fg(high_color))
-            .title_style(Style::new().fg(mid_color));
-        let fft_data: Vec<f64> = fft_state.iter().map(|value| *value as f64).collect();
-
-        let inner_area = ttf_block.inner(ttf_area);
-        let width = inner_area.width as f64;
-        let height = (inner_area.height as f64) + 50.0;
-
-        let middle = height / 2.0;
-
-        let gradient_start =
-            hex_to_rgb(&config.colors["colors"].fg.to_string()).unwrap_or((0, 0, 0));
-        let gradient_mid =
-            hex_to_rgb(&config.colors["colors"].title.to_string()).unwrap_or((0, 0, 0));
-        let gradient_stop =
-            hex_to_rgb(&config.colors["colors"].border.to_string()).unwrap_or((255, 255, 255));
-
-        let gradient = gradient_steps(gradient_start, gradient_mid, gradient_stop, 32);
-
-        let mut all_points = vec![];
-
-        for (i_idx, i) in fft_data.iter().enumerate() {
-            for j in 0..(*i as usize) {
-                let upper = j as f64 + middle;
-                let height_percent = upper / height;
-
-                let level_idx =
-                    (height_percent.clamp(0.0, 1.0) * (gradient.len() - 1) as f64) as usize;
-
-                all_points.push(((i_idx as f64), upper, gradient[level_idx]));
-                all_points.push((
-                    (i_idx as f64),
-                    height - middle - j as f64,
-                    gradient[level_idx],
-                ));
-            }
-        }
-
-        Canvas::default()
-            .block(
-                ttf_block
-                    .title_bottom(Line::from(format!(
-                        " ○ ○ SAMPLE_POS: {} / {} • ",
-                        total_samples_played, max_samples
-                    )))
-                    .title_bottom(
-                        Line::from(format!(
-                            " • • MIN_BUF_THRESHOLD: {} ⋯ ",
-                            min_buffer_threshold
-                        ))
-                        .right_aligned(),
-                    )
-                    .title(Title::from(format!(
-                        " ■ SAMPLE_BUF: {} // SAMPLE_RATE: {} // BUS: {}X ",
-                        sample_buffer_size, sample_rate, channels
-                    ))),
+            .title_style(Style::new().fg(mid_color))
+            .title_bottom(Line::from(format!(
+                " ○ ○ SAMPLE_POS: {} / {} • ",
+                total_samples_played, max_samples
+            )))
+            .title_bottom(
+                Line::from(format!(
+                    " • • MIN_BUF_THRESHOLD: {} ⋯ ",
+                    min_buffer_threshold
+                ))
+                .right_aligned(),
             )
-            .x_bounds([0.0, width])
-            .y_bounds([-50.0, height + 50.0])
-            .paint(|ctx| {
-                ctx.layer();
-
-                for (x, y, color) in all_points.iter() {
-                    let (r, g, b) = color.clone();
-                    let main_color = Color::Rgb(r, g, b);
-                    let fade_color = Color::Rgb(r / 2, g / 2, b / 2);
-
-                    ctx.draw(&Points {
-                        coords: &[(*x, *y)],
-                        color: main_color,
-                    });
-                    ctx.draw(&Points {
-                        coords: &[(x + 0.5, y + 0.5)],
-                        color: fade_color,
-                    });
-                }
-            })
-            .render(ttf_area, buf);
+            .title(Title::from(format!(
+                " ■ SAMPLE_BUF: {} // SAMPLE_RATE: {} // BUS: {}X ",
+                sample_buffer_size, sample_rate, channels
+            )));
+
+        visualizer::render_visualizer(ttf_area, buf, ttf_block, &scope, mode, config);
     }
 
     let body = Layout::default()
@@ -223,13 +186,31 @@ pub fn render_echo(
     let upper_area = info_layout[0];
     let lower_area = info_layout[1];
 
-    let app_info =
-        Line::from(" PLAYLISTS ").style(Style::default().fg(config.colors["colors"].title));
-    shared::block::bordered_block(
-        app_info,
+    let queue_title = match queue.position {
+        Some(pos) => format!(" QUEUE ·· {} ·· {}/{} ", queue.origin, pos + 1, queue.len()),
+        None => " QUEUE ".to_string(),
+    };
+    let queue_block = shared::block::bordered_block(
+        Line::from(queue_title).style(Style::default().fg(config.colors["colors"].title)),
         ratatui::style::Color::from(config.colors["colors"].border),
     )
-    .render(upper_area, buf);
+    .title_bottom(Line::from(" n:next · b:prev · a:album "));
+
+    if queue.is_empty() {
+        Paragraph::new("QUEUE IS EMPTY.")
+            .style(Style::default().fg(config.colors["colors"].fg))
+            .centered()
+            .block(queue_block)
+            .render(upper_area, buf);
+    } else {
+        shared::table::queue_table(
+            queue,
+            config.colors["colors"].fg,
+            config.colors["colors"].title,
+        )
+        .block(queue_block)
+        .render(upper_area, buf);
+    }
 
     let metadata_title = match echo_tab_state.echo_subtab {
         EchoSubTab::METADATA => Line::from(vec![
@@ -299,50 +280,6 @@ pub fn render_echo(
     table.block(metadata_block).render(lower_area, buf);
 }
 
-fn hex_to_rgb(hex: &str) -> Option<(usize, usize, usize)> {
-    let hex = hex.strip_prefix('#').unwrap_or(hex);
-
-    if hex.len() != 6 {
-        return None;
-    }
-
-    let r = usize::from_str_radix(&hex[0..2], 16).ok()?;
-    let g = usize::from_str_radix(&hex[2..4], 16).ok()?;
-    let b = usize::from_str_radix(&hex[4..6], 16).ok()?;
-
-    Some((r, g, b))
-}
-
-fn gradient_steps(
-    start: (usize, usize, usize),
-    mid: (usize, usize, usize),
-    end: (usize, usize, usize),
-    steps: usize,
-) -> Vec<(u8, u8, u8)> {
-    let mut result = Vec::new();
-    let half = steps / 2;
-
-    // first half: start → mid
-    for i in 0..half {
-        let t = i as f64 / (half - 1) as f64;
-        let r = start.0 as f64 + (mid.0 as f64 - start.0 as f64) * t / 2.0;
-        let g = start.1 as f64 + (mid.1 as f64 - start.1 as f64) * t / 2.0;
-        let b = start.2 as f64 + (mid.2 as f64 - start.2 as f64) * t / 2.0;
-        result.push((r as u8, g as u8, b as u8));
-    }
-
-    // second half: mid → end
-    for i in 0..(steps - half) {
-        let t = i as f64 / (steps - half - 1) as f64;
-        let r = mid.0 as f64 + (end.0 as f64 - mid.0 as f64) * t / 2.0;
-        let g = mid.1 as f64 + (end.1 as f64 - mid.1 as f64) * t / 2.0;
-        let b = mid.2 as f64 + (end.2 as f64 - mid.2 as f64) * t / 2.0;
-        result.push((r as u8, g as u8, b as u8));
-    }
-
-    result
-}
-
 fn echo_main_title_search<'a>(info: Color, title: Color, bg: Color) -> Line<'a> {
     Line::from(vec![
         Span::styled(" S", Style::default().bg(info).fg(title)),
@@ -405,11 +342,17 @@ fn render_import_subtab(
     title: ratatui::style::Color,
     echo_tab_state: &EchoTabState,
 ) {
+    let progress = echo_tab_state
+        .import_progress
+        .lock()
+        .map(|progress| progress.clone())
+        .unwrap_or_default();
+
     let outer_block = shared::block::bordered_block(
         echo_main_title,
         ratatui::style::Color::from(config.colors["colors"].border),
     )
-    .title_bottom(" ⎔  ⎔  FOUND:")
+    .title_bottom(format!(" ⎔  ⎔  FOUND: {} ", progress.total))
     .title_style(Style::default().fg(config.colors["colors"].title));
 
     let inner_area = outer_block.inner(left_area);
@@ -428,6 +371,73 @@ fn render_import_subtab(
         .style(Style::default().fg(info));
 
     input_widget.render(import_layout[0], buf);
+
+    render_import_progress(import_layout[1], buf, &progress, config);
+}
+
+/// A gauge of the running or last import and every file that failed.
+fn render_import_progress(
+    area: Rect,
+    buf: &mut Buffer,
+    progress: &ImportProgress,
+    config: &UiConfig,
+) {
+    let colors = &config.colors["colors"];
+    if progress.total == 0 && !progress.is_running {
+        Paragraph::new("⏎ IMPORTS THE FOLDER AND ITS SUBFOLDERS · i IMPORTS THE SONGS FOLDER")
+            .style(Style::default().fg(colors.fg))
+            .centered()
+            .render(area, buf);
+        return;
+    }
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .split(area);
+
+    let label = if progress.is_running {
+        format!(
+            "{}/{} · {}",
+            progress.done, progress.total, progress.current
+        )
+    } else {
+        format!(
+            "{}/{} · {} IMPORTED · {} FAILED",
+            progress.done,
+            progress.total,
+            progress.imported,
+            progress.failures.len()
+        )
+    };
+    LineGauge::default()
+        .ratio(progress.ratio())
+        .label(label)
+        .style(Style::default().fg(colors.fg))
+        .filled_style(Style::default().fg(colors.primary))
+        .unfilled_style(Style::default().fg(colors.border))
+        .render(layout[0], buf);
+
+    let failures: Vec<Line> = progress
+        .failures
+        .iter()
+        .rev()
+        .map(|(path, reason)| {
+            Line::from(vec![
+                Span::styled("✗ ", Style::default().fg(colors.error)),
+                Span::styled(
+                    path.to_string_lossy().to_string(),
+                    Style::default().fg(colors.fg),
+                ),
+                Span::styled(format!(" · {}", reason), Style::default().fg(colors.error)),
+            ])
+        })
+        .collect();
+    Paragraph::new(failures).render(layout[2], buf);
 }
 
 fn render_search_subtab<'a>(
@@ -474,8 +484,8 @@ fn render_search_subtab<'a>(
 
     let table = shared::table::local_songs_table(
         songs,
+        &echo_tab_state.search_buffer,
         config.colors["colors"].fg,
-        config.colors["colors"].bg,
         config.colors["colors"].accent,
         config.colors["colors"].title,
         selected_song_pos,
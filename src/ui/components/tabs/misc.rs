@@ -0,0 +1,148 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Paragraph, Widget},
+};
+
+use crate::{
+    app::{EqState, State},
+    awdio::{eq::MAX_GAIN_DB, song::Song},
+    config::UiConfig,
+    timer,
+    ui::components::shared,
+};
+
+pub fn render_misc(area: Rect, buf: &mut Buffer, state: &State, config: &UiConfig) {
+    let [eq_area, timers_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(3)]).areas(area);
+
+    render_eq(eq_area, buf, &state.eq, &state.active_track, config);
+    render_timers(timers_area, buf, state, config);
+}
+
+fn render_timers(area: Rect, buf: &mut Buffer, state: &State, config: &UiConfig) {
+    let colors = &config.colors["colors"];
+
+    let sleep = match (&state.sleep_timer, state.sleep_remaining) {
+        (Some(_), Some(remaining)) => timer::readable(remaining),
+        (Some(timer), None) => timer.label(),
+        (None, _) => "OFF".into(),
+    };
+    let playlist = state
+        .playlists
+        .iter()
+        .find(|playlist| Some(playlist.id) == state.alarm.playlist_id)
+        .map_or("none", |playlist| playlist.name.as_str());
+    let alarm = format!(
+        "{} → {} · {} · fade {}s",
+        state.alarm.label(),
+        playlist,
+        if state.alarm.is_armed() {
+            "ARMED"
+        } else {
+            "OFF"
+        },
+        state.alarm.fade.as_secs()
+    );
+
+    let block = shared::block::bordered_block(Line::from(" TIMERS "), colors.border)
+        .title_bottom(
+            Line::from(" t:sleep · A:alarm on/off · h/l:alarm time · p:alarm playlist ")
+                .right_aligned(),
+        )
+        .title_style(Style::new().fg(colors.title));
+
+    Paragraph::new(Line::from(vec![
+        Span::styled(" SLEEP: ", Style::new().fg(colors.title)),
+        Span::styled(sleep, Style::new().fg(colors.fg)),
+        Span::styled("   ALARM: ", Style::new().fg(colors.title)),
+        Span::styled(alarm, Style::new().fg(colors.fg)),
+    ]))
+    .block(block)
+    .render(area, buf);
+}
+
+fn render_eq(
+    area: Rect,
+    buf: &mut Buffer,
+    eq_state: &EqState,
+    current_song: &Song,
+    config: &UiConfig,
+) {
+    let colors = &config.colors["colors"];
+
+    let mut title = format!(
+        " EQ ·· {} ·· {} ",
+        eq_state.preset_name(),
+        if eq_state.is_enabled { "ON" } else { "OFF" }
+    );
+    if let Some(pinned) = &current_song.eq_preset {
+        title.push_str(&format!("·· PINNED: {} ", pinned));
+    }
+
+    let block = shared::block::bordered_block(Line::from(title), colors.border)
+        .title_bottom(
+            Line::from(" a/d:band · w/s:gain · 0:reset · [/]:preset · e:on/off · m/u:pin/unpin ")
+                .right_aligned(),
+        )
+        .title_style(Style::new().fg(colors.title));
+    let inner = block.inner(area);
+    block.render(area, buf);
+
+    let bands = eq_state.bands();
+    // a gain label above and a frequency label below every slider
+    if bands.is_empty() || inner.height < 5 || inner.width == 0 {
+        return;
+    }
+
+    let column_width = (inner.width / bands.len() as u16).max(1);
+    let slider_top = inner.y + 1;
+    let slider_height = inner.height - 2;
+    let zero_row = slider_top + slider_height / 2;
+    let half = (slider_height / 2).max(1) as f32;
+
+    for (i, band) in bands.iter().enumerate() {
+        let x = inner.x + i as u16 * column_width;
+        if x >= inner.right() {
+            break;
+        }
+        let center = x + column_width / 2;
+
+        let style = if i == eq_state.selected_band {
+            Style::new().fg(colors.title).add_modifier(Modifier::BOLD)
+        } else if eq_state.is_enabled {
+            Style::new().fg(colors.fg)
+        } else {
+            Style::new().fg(colors.border)
+        };
+
+        let rows = ((band.gain / MAX_GAIN_DB) * half).round() as i32;
+        for row in slider_top..slider_top + slider_height {
+            let offset = zero_row as i32 - row as i32;
+            let symbol = if offset == 0 {
+                "─"
+            } else if (rows > 0 && offset > 0 && offset <= rows)
+                || (rows < 0 && offset < 0 && offset >= rows)
+            {
+                "█"
+            } else {
+                "·"
+            };
+            buf.set_string(center, row, symbol, style);
+        }
+
+        let gain = format!("{:+.1}", band.gain);
+        let freq = if band.freq >= 1000.0 {
+            format!("{}k", band.freq / 1000.0)
+        } else {
+            format!("{}", band.freq)
+        };
+        for (row, label) in [(inner.y, gain), (inner.bottom() - 1, freq)] {
+            let label_x = center.saturating_sub(label.len() as u16 / 2).max(x);
+            let width = (inner.right() - label_x) as usize;
+            buf.set_stringn(label_x, row, label, width.min(column_width as usize), style);
+        }
+    }
+}
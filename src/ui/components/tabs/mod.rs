@@ -1 +1,3 @@
 pub mod echo;
+pub mod misc;
+pub mod visualizer;
@@ -0,0 +1,463 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    symbols::Marker,
+    text::Span,
+    widgets::{
+        Block, Widget,
+        canvas::{Canvas, Context, Line as CanvasLine, Points},
+    },
+};
+
+use crate::{app::Visualizer, awdio::scope::Scope, config::UiConfig};
+
+// the quietest level the meters show
+const METER_FLOOR_DB: f32 = -60.0;
+// waterfall cells quieter than this stay empty
+const SPECTROGRAM_FLOOR: f32 = 0.05;
+
+/// The title of the panel in `mode`, with what that view measures.
+pub fn title(mode: Visualizer, scope: &Scope, config: &UiConfig) -> String {
+    match mode {
+        Visualizer::Spectrum => {
+            let settings = config.spectrum().settings();
+            format!(
+                " ▪︎ {} · {} BARS · {} – {} ",
+                mode,
+                settings.bars,
+                readable_freq(settings.min_freq),
+                readable_freq(settings.max_freq)
+            )
+        }
+        Visualizer::Meters => {
+            let levels: Vec<String> = scope
+                .meters
+                .iter()
+                .enumerate()
+                .map(|(channel, meter)| {
+                    format!("{} {:.1}", channel_name(channel), to_db(meter.peak))
+                })
+                .collect();
+            format!(" ▪︎ {} · {} dBFS ", mode, levels.join(" · "))
+        }
+        Visualizer::Stereo => format!(
+            " ▪︎ {} · CORRELATION {:+.2} ",
+            mode,
+            correlation(&scope.stereo())
+        ),
+        _ => format!(" ▪︎ {} ", mode),
+    }
+}
+
+pub fn render_visualizer(
+    area: Rect,
+    buf: &mut Buffer,
+    block: Block<'static>,
+    scope: &Scope,
+    mode: Visualizer,
+    config: &UiConfig,
+) {
+    let inner = block.inner(area);
+    let colors = &config.colors["colors"];
+    let layers = Layers::new(theme_gradient(config));
+
+    match mode {
+        Visualizer::Spectrum => spectrum(inner, scope, layers, colors.info)
+            .block(block)
+            .render(area, buf),
+        Visualizer::Oscilloscope => oscilloscope(inner, scope, layers, colors.border)
+            .block(block)
+            .render(area, buf),
+        Visualizer::Spectrogram => spectrogram(inner, scope, layers)
+            .block(block)
+            .render(area, buf),
+        Visualizer::Meters => meters(inner, scope, layers, colors.info, colors.border)
+            .block(block)
+            .render(area, buf),
+        Visualizer::Stereo => goniometer(scope, layers, colors.border)
+            .block(block)
+            .render(area, buf),
+        Visualizer::Off => {}
+    }
+}
+
+/// Log-spaced bars growing up and down from the middle, with peak-hold dots.
+fn spectrum<'a>(
+    inner: Rect,
+    scope: &'a Scope,
+    mut layers: Layers,
+    peak_color: Color,
+) -> Canvas<'a, impl Fn(&mut Context) + 'a> {
+    let spectrum = &scope.spectrum;
+    let bars = spectrum.bars.len().max(1) as f64;
+    // braille dots, each bar grows up and down from the middle
+    let half_dots = (inner.height as usize * 2).max(1);
+    let dot = 1.0 / half_dots as f64;
+    let dots_per_bar = (inner.width as f64 * 2.0 / bars).max(1.0) as usize;
+
+    let mut peaks = vec![];
+    for (i, (level, peak)) in spectrum.bars.iter().zip(spectrum.peaks.iter()).enumerate() {
+        // one dot column of gap between neighbouring bars
+        let columns = dots_per_bar.saturating_sub(1).max(1);
+        let dots = (*level as f64 * half_dots as f64).round() as usize;
+
+        for k in 0..columns {
+            let x = i as f64 + k as f64 / dots_per_bar as f64;
+            for j in 0..dots {
+                let y = j as f64 * dot;
+                layers.push(x, y, y);
+                layers.push(x, -y, y);
+            }
+            if *peak > 0.0 {
+                peaks.push((x, *peak as f64));
+                peaks.push((x, -*peak as f64));
+            }
+        }
+    }
+
+    Canvas::default()
+        .x_bounds([0.0, bars])
+        .y_bounds([-1.0, 1.0])
+        .paint(move |ctx| {
+            layers.draw(ctx);
+
+            // peak-hold markers on top of the bars
+            ctx.layer();
+            ctx.draw(&Points {
+                coords: &peaks,
+                color: peak_color,
+            });
+        })
+}
+
+/// The mono waveform, started on a rising zero crossing so a steady tone
+/// stands still.
+fn oscilloscope<'a>(
+    inner: Rect,
+    scope: &'a Scope,
+    layers: Layers,
+    axis_color: Color,
+) -> Canvas<'a, impl Fn(&mut Context) + 'a> {
+    let mono = scope.mono();
+    let shown = mono.len() / 2;
+    let start = (1..mono.len() - shown)
+        .find(|&i| mono[i - 1] < 0.0 && mono[i] >= 0.0)
+        .unwrap_or(0);
+    let trace = &mono[start..start + shown];
+
+    // about one point per braille dot column
+    let step = (trace.len() / (inner.width as usize * 2).max(1)).max(1);
+    let points: Vec<(f64, f64)> = trace
+        .iter()
+        .enumerate()
+        .step_by(step)
+        .map(|(x, y)| (x as f64, y.clamp(-1.0, 1.0) as f64))
+        .collect();
+    let width = shown.max(1) as f64;
+
+    Canvas::default()
+        .x_bounds([0.0, width])
+        .y_bounds([-1.0, 1.0])
+        .paint(move |ctx| {
+            ctx.draw(&CanvasLine {
+                x1: 0.0,
+                y1: 0.0,
+                x2: width,
+                y2: 0.0,
+                color: axis_color,
+            });
+            ctx.layer();
+
+            for pair in points.windows(2) {
+                let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+                ctx.draw(&CanvasLine {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    color: layers.color(y1.abs().max(y2.abs())),
+                });
+            }
+        })
+}
+
+/// Past spectra scrolling down from the top, one half cell per update.
+fn spectrogram<'a>(
+    inner: Rect,
+    scope: &'a Scope,
+    mut layers: Layers,
+) -> Canvas<'a, impl Fn(&mut Context) + 'a> {
+    let width = inner.width.max(1) as usize;
+    let rows = inner.height as usize * 2;
+
+    for (row, bars) in scope.history.iter().take(rows).enumerate() {
+        if bars.is_empty() {
+            continue;
+        }
+        let y = (rows - row) as f64 - 0.5;
+        for column in 0..width {
+            let level = bars[column * bars.len() / width];
+            if level > SPECTROGRAM_FLOOR {
+                layers.push(column as f64 + 0.5, y, level as f64);
+            }
+        }
+    }
+
+    Canvas::default()
+        .marker(Marker::HalfBlock)
+        .x_bounds([0.0, width as f64])
+        .y_bounds([0.0, rows.max(1) as f64])
+        .paint(move |ctx| layers.draw(ctx))
+}
+
+/// A VU bar per channel with its PPM peak as a line and a clip light at the
+/// end, on a dBFS scale.
+fn meters<'a>(
+    inner: Rect,
+    scope: &'a Scope,
+    mut layers: Layers,
+    peak_color: Color,
+    idle_color: Color,
+) -> Canvas<'a, impl Fn(&mut Context) + 'a> {
+    let width = inner.width.max(1) as f64;
+    let rows = inner.height as usize * 2;
+    let channels = scope.meters.len().max(1);
+    let band = (rows / channels).max(1);
+
+    // a label on the left, the clip light on the right
+    let (bar_start, bar_end) = (4.0, (width - 7.0).max(5.0));
+    let bar_x = |linear: f32| bar_start + (bar_end - bar_start) * meter_position(linear);
+
+    let mut peaks = vec![];
+    let mut labels = vec![];
+    for (channel, meter) in scope.meters.iter().enumerate() {
+        let top = rows.saturating_sub(channel * band);
+        // a half cell of room between channels
+        let thickness = band.saturating_sub(1).max(1);
+        let ys: Vec<f64> = (0..thickness)
+            .map(|k| top.saturating_sub(k) as f64 - 0.5)
+            .collect();
+
+        let rms_end = bar_x(meter.rms);
+        let mut x = bar_start + 0.5;
+        while x < rms_end {
+            for &y in ys.iter() {
+                layers.push(x, y, (x - bar_start) / (bar_end - bar_start));
+            }
+            x += 1.0;
+        }
+
+        let peak_x = bar_x(meter.peak);
+        peaks.push((peak_x, ys[0], ys[ys.len() - 1]));
+        labels.push((ys[ys.len() / 2], channel_name(channel), meter.is_clipping()));
+    }
+
+    Canvas::default()
+        .marker(Marker::HalfBlock)
+        .x_bounds([0.0, width])
+        .y_bounds([0.0, rows.max(1) as f64])
+        .paint(move |ctx| {
+            layers.draw(ctx);
+
+            ctx.layer();
+            for &(x, y1, y2) in peaks.iter() {
+                ctx.draw(&CanvasLine {
+                    x1: x,
+                    y1,
+                    x2: x,
+                    y2,
+                    color: peak_color,
+                });
+            }
+
+            for (y, name, is_clipping) in labels.iter() {
+                ctx.print(
+                    0.0,
+                    *y,
+                    Span::styled(name.clone(), Style::new().fg(idle_color)),
+                );
+                let clip = if *is_clipping {
+                    Style::new().fg(Color::Black).bg(Color::Red)
+                } else {
+                    Style::new().fg(idle_color)
+                };
+                ctx.print(width - 6.0, *y, Span::styled(" CLIP ", clip));
+            }
+        })
+}
+
+/// Mid up, side across: a mono track is a vertical line, out of phase
+/// channels lie flat.
+fn goniometer<'a>(
+    scope: &'a Scope,
+    mut layers: Layers,
+    axis_color: Color,
+) -> Canvas<'a, impl Fn(&mut Context) + 'a> {
+    for (left, right) in scope.stereo() {
+        let mid = (left + right) as f64 * std::f64::consts::FRAC_1_SQRT_2;
+        let side = (right - left) as f64 * std::f64::consts::FRAC_1_SQRT_2;
+        let radius = (mid * mid + side * side).sqrt();
+        layers.push(side.clamp(-1.0, 1.0), mid.clamp(-1.0, 1.0), radius);
+    }
+
+    Canvas::default()
+        .x_bounds([-1.0, 1.0])
+        .y_bounds([-1.0, 1.0])
+        .paint(move |ctx| {
+            // the L and R axes and the mono line
+            for (x1, y1, x2, y2) in [
+                (-0.7, 0.7, 0.7, -0.7),
+                (0.7, 0.7, -0.7, -0.7),
+                (0.0, -1.0, 0.0, 1.0),
+            ] {
+                ctx.draw(&CanvasLine {
+                    x1,
+                    y1,
+                    x2,
+                    y2,
+                    color: axis_color,
+                });
+            }
+            ctx.print(-0.75, 0.75, Span::styled("L", Style::new().fg(axis_color)));
+            ctx.print(0.72, 0.75, Span::styled("R", Style::new().fg(axis_color)));
+
+            ctx.layer();
+            layers.draw(ctx);
+        })
+}
+
+/// Points sorted by the gradient color they are drawn in, so every color
+/// is one draw call.
+struct Layers {
+    gradient: Vec<(u8, u8, u8)>,
+    points: Vec<Vec<(f64, f64)>>,
+}
+
+impl Layers {
+    fn new(gradient: Vec<(u8, u8, u8)>) -> Self {
+        let points = vec![Vec::new(); gradient.len()];
+        Self { gradient, points }
+    }
+
+    fn index(&self, level: f64) -> usize {
+        (level.clamp(0.0, 1.0) * (self.gradient.len() - 1) as f64) as usize
+    }
+
+    fn color(&self, level: f64) -> Color {
+        let (r, g, b) = self.gradient[self.index(level)];
+        Color::Rgb(r, g, b)
+    }
+
+    /// Add a point colored by `level` in `0.0..=1.0` of the gradient.
+    fn push(&mut self, x: f64, y: f64, level: f64) {
+        let index = self.index(level);
+        self.points[index].push((x, y));
+    }
+
+    fn draw(&self, ctx: &mut Context) {
+        for (&(r, g, b), coords) in self.gradient.iter().zip(self.points.iter()) {
+            if !coords.is_empty() {
+                ctx.draw(&Points {
+                    coords,
+                    color: Color::Rgb(r, g, b),
+                });
+            }
+        }
+    }
+}
+
+/// How alike left and right are, 1 for mono, 0 for unrelated, -1 for out
+/// of phase.
+fn correlation(frames: &[(f32, f32)]) -> f32 {
+    let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+    for (left, right) in frames {
+        lr += left * right;
+        ll += left * left;
+        rr += right * right;
+    }
+    let energy = (ll * rr).sqrt();
+    if energy > f32::EPSILON {
+        lr / energy
+    } else {
+        0.0
+    }
+}
+
+fn to_db(linear: f32) -> f32 {
+    (20.0 * linear.max(1e-6).log10()).max(METER_FLOOR_DB)
+}
+
+fn meter_position(linear: f32) -> f64 {
+    ((to_db(linear) - METER_FLOOR_DB) / -METER_FLOOR_DB).clamp(0.0, 1.0) as f64
+}
+
+fn channel_name(channel: usize) -> String {
+    match channel {
+        0 => "L".into(),
+        1 => "R".into(),
+        n => (n + 1).to_string(),
+    }
+}
+
+fn readable_freq(freq: f32) -> String {
+    if freq >= 1000.0 {
+        format!("{:.1}kHz", freq / 1000.0)
+    } else {
+        format!("{:.0}Hz", freq)
+    }
+}
+
+/// The theme's fg → title → border gradient every view is colored with.
+fn theme_gradient(config: &UiConfig) -> Vec<(u8, u8, u8)> {
+    let colors = &config.colors["colors"];
+    let gradient_start = hex_to_rgb(&colors.fg.to_string()).unwrap_or((0, 0, 0));
+    let gradient_mid = hex_to_rgb(&colors.title.to_string()).unwrap_or((0, 0, 0));
+    let gradient_stop = hex_to_rgb(&colors.border.to_string()).unwrap_or((255, 255, 255));
+
+    gradient_steps(gradient_start, gradient_mid, gradient_stop, 32)
+}
+
+fn hex_to_rgb(hex: &str) -> Option<(usize, usize, usize)> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let r = usize::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = usize::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = usize::from_str_radix(&hex[4..6], 16).ok()?;
+
+    Some((r, g, b))
+}
+
+fn gradient_steps(
+    start: (usize, usize, usize),
+    mid: (usize, usize, usize),
+    end: (usize, usize, usize),
+    steps: usize,
+) -> Vec<(u8, u8, u8)> {
+    let mut result = Vec::new();
+    let half = steps / 2;
+
+    // first half: start → mid
+    for i in 0..half {
+        let t = i as f64 / (half - 1) as f64;
+        let r = start.0 as f64 + (mid.0 as f64 - start.0 as f64) * t / 2.0;
+        let g = start.1 as f64 + (mid.1 as f64 - start.1 as f64) * t / 2.0;
+        let b = start.2 as f64 + (mid.2 as f64 - start.2 as f64) * t / 2.0;
+        result.push((r as u8, g as u8, b as u8));
+    }
+
+    // second half: mid → end
+    for i in 0..(steps - half) {
+        let t = i as f64 / (steps - half - 1) as f64;
+        let r = mid.0 as f64 + (end.0 as f64 - mid.0 as f64) * t / 2.0;
+        let g = mid.1 as f64 + (end.1 as f64 - mid.1 as f64) * t / 2.0;
+        let b = mid.2 as f64 + (end.2 as f64 - mid.2 as f64) * t / 2.0;
+        result.push((r as u8, g as u8, b as u8));
+    }
+
+    result
+}
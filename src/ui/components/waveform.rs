@@ -0,0 +1,82 @@
+use std::ops::RangeInclusive;
+
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::Color,
+    symbols::Marker,
+    widgets::{
+        Widget,
+        canvas::{Canvas, Line as CanvasLine},
+    },
+};
+
+use crate::{awdio::waveform::Waveform, config::UiConfig};
+
+/// Where the overview is drawn and what it marks, every position is a
+/// fraction of the song.
+pub struct Overview<'a> {
+    pub waveform: &'a Waveform,
+    pub progress: f64,
+    pub loop_region: Option<RangeInclusive<f64>>,
+    pub cursor: Option<f64>,
+}
+
+/// Draw the min/max peaks of every column, the played part in the primary
+/// color and the A–B loop in the accent color. Without peaks the song is a
+/// flat line.
+pub fn render_waveform(area: Rect, buf: &mut Buffer, overview: Overview, config: &UiConfig) {
+    if area.width == 0 || area.height == 0 {
+        return;
+    }
+
+    let colors = &config.colors["colors"];
+    let animations = &config.animations["animations"];
+    let width = area.width as f64;
+    // a braille cell is two dots wide
+    let dots = area.width as usize * 2;
+    let peaks = overview.waveform.columns(dots);
+
+    let color_at = |position: f64| {
+        if overview
+            .loop_region
+            .as_ref()
+            .is_some_and(|region| region.contains(&position))
+        {
+            colors.accent
+        } else if position <= overview.progress {
+            colors.primary
+        } else {
+            colors.border
+        }
+    };
+
+    Canvas::default()
+        .marker(Marker::Braille)
+        .x_bounds([0.0, width])
+        .y_bounds([-1.0, 1.0])
+        .paint(|ctx| {
+            for dot in 0..dots {
+                let position = (dot as f64 + 0.5) / dots as f64;
+                let (min, max) = peaks.get(dot).copied().unwrap_or_default();
+                ctx.draw(&CanvasLine {
+                    x1: position * width,
+                    y1: min as f64,
+                    x2: position * width,
+                    y2: max as f64,
+                    color: color_at(position),
+                });
+            }
+        })
+        .render(area, buf);
+
+    let mut mark = |position: f64, symbol: &str, color: Color| {
+        let x = area.x + ((position * width) as u16).min(area.width - 1);
+        let y = area.y + area.height / 2;
+        buf[(x, y)].set_symbol(symbol).set_fg(color);
+    };
+    mark(overview.progress, &animations.timestamp, colors.title);
+    if let Some(cursor) = overview.cursor {
+        mark(cursor, &animations.timestamp_bar, colors.warning);
+    }
+}